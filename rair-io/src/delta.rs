@@ -0,0 +1,92 @@
+/*
+ * delta.rs: byte level changes done on top of a file.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Bytes changed on top of an opened file, stored as non overlapping and non adjacent runs
+/// keyed by their offset from the begining of the file.
+#[derive(Default, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ByteDelta {
+    runs: BTreeMap<u64, Vec<u8>>,
+}
+
+impl ByteDelta {
+    pub(crate) fn new() -> ByteDelta {
+        Default::default()
+    }
+    /// Records that *data* was written at *offset*, overriding any previous change in the same range.
+    pub(crate) fn record(&mut self, offset: u64, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let end = offset + data.len() as u64;
+        // runs that overlap or touch [offset, end) are merged with the new data.
+        let merged: Vec<u64> = self
+            .runs
+            .range(..=end)
+            .rev()
+            .take_while(|(start, run)| *start + run.len() as u64 >= offset)
+            .map(|(start, _)| *start)
+            .collect();
+        let mut start = offset;
+        let mut new_end = end;
+        for key in &merged {
+            start = start.min(*key);
+            new_end = new_end.max(*key + self.runs[key].len() as u64);
+        }
+        let mut run = vec![0; (new_end - start) as usize];
+        for key in merged {
+            let old = self.runs.remove(&key).unwrap();
+            let off = (key - start) as usize;
+            run[off..off + old.len()].copy_from_slice(&old);
+        }
+        let off = (offset - start) as usize;
+        run[off..off + data.len()].copy_from_slice(data);
+        self.runs.insert(start, run);
+    }
+    /// Returns *true* if no bytes were changed.
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+    /// Iterate over changed runs as (offset, data) sorted by offset.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.runs.iter().map(|(offset, run)| (*offset, &run[..]))
+    }
+}
+
+#[cfg(test)]
+mod test_delta {
+    use super::*;
+    #[test]
+    fn test_record() {
+        let mut delta = ByteDelta::new();
+        assert!(delta.is_empty());
+        delta.record(0x10, &[1, 2, 3]);
+        delta.record(0x20, &[4]);
+        delta.record(0x5, &[]);
+        assert_eq!(delta.iter().collect::<Vec<_>>(), vec![(0x10, &[1, 2, 3][..]), (0x20, &[4][..])]);
+        // adjacent to the first run
+        delta.record(0x13, &[5]);
+        // overlaps the first run from the left
+        delta.record(0xf, &[6, 7]);
+        assert_eq!(delta.iter().collect::<Vec<_>>(), vec![(0xf, &[6, 7, 2, 3, 5][..]), (0x20, &[4][..])]);
+        // spans both runs
+        delta.record(0x12, &[8; 0x10]);
+        assert_eq!(delta.iter().collect::<Vec<_>>(), vec![(0xf, &[6, 7, 2, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8][..])]);
+        assert!(!delta.is_empty());
+    }
+}
@@ -14,9 +14,11 @@
  * You should have received a copy of the GNU Lesser General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+use crate::delta::ByteDelta;
+use crate::dirty::DirtyRanges;
 use crate::plugin::*;
 use crate::utils::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[derive(Serialize, Deserialize)]
 pub struct RIODesc {
     pub(crate) name: String,
@@ -25,14 +27,32 @@ pub struct RIODesc {
     pub(crate) paddr: u64, //padd is simulated physical address
     pub(crate) size: u64,
     raddr: u64, // raddr is the IO descriptor address, general rule of interaction paddr is high level lie, while raddr is the real thing.
+    // Copy-On-Write changes live only in the plugin private memory, so they are recorded
+    // here as well in order to be replayed after the file is reopened.
+    #[serde(default)]
+    cow_delta: ByteDelta,
+    // Physical ranges written to since the file was opened.
+    #[serde(default)]
+    dirty: DirtyRanges,
     // Since we are skiping files operation structures .. after deserializing RIO .. we must
-    // reopen the files again and make sure that they are in the right place
-    // for sake of serde skip Box<dyn RIOPluginOperations + Sync + Send> must implement Default and
-    // the implementation is found in plugins.rs
-    #[serde(skip)]
+    // reopen the files again and make sure that they are in the right place. Only the state
+    // returned by save_state is serialized, it is restored by reopen. For sake of serde default
+    // Box<dyn RIOPluginOperations + Sync + Send> must implement Default and the implementation
+    // is found in plugins.rs
+    #[serde(default, serialize_with = "serialize_state", deserialize_with = "deserialize_state")]
     plugin_operations: Box<dyn RIOPluginOperations + Sync + Send>,
 }
 
+#[allow(clippy::borrowed_box)]
+fn serialize_state<S: Serializer>(ops: &Box<dyn RIOPluginOperations + Sync + Send>, serializer: S) -> Result<S::Ok, S::Error> {
+    ops.save_state().serialize(serializer)
+}
+
+fn deserialize_state<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Box<dyn RIOPluginOperations + Sync + Send>, D::Error> {
+    let state = Option::<Vec<u8>>::deserialize(deserializer)?;
+    Ok(Box::new(DefPluginOperations { state }))
+}
+
 impl RIODesc {
     pub(crate) fn raddr(&self) -> u64 {
         self.raddr
@@ -47,20 +67,52 @@ impl RIODesc {
             size: plugin_desc.size,
             plugin_operations: plugin_desc.plugin_operations,
             raddr: plugin_desc.raddr,
+            cow_delta: ByteDelta::new(),
+            dirty: DirtyRanges::new(),
         };
         Ok(desc)
     }
     pub(crate) fn reopen(&mut self, plugin: &mut dyn RIOPlugin) -> Result<(), IoError> {
         let plugin_desc = plugin.open(&self.name, self.perm)?;
+        let state = self.plugin_operations.save_state();
         self.plugin_operations = plugin_desc.plugin_operations;
         self.raddr = plugin_desc.raddr;
+        if let Some(state) = state {
+            self.plugin_operations.restore_state(&state)?;
+        }
+        for (offset, data) in self.cow_delta.iter() {
+            self.plugin_operations.write((offset + self.raddr) as usize, data)?;
+        }
+        Ok(())
+    }
+    // Writes changes done to a Copy-On-Write file into the file it was opened from.
+    pub(crate) fn commit(&mut self, plugin: &mut dyn RIOPlugin) -> Result<(), IoError> {
+        let mut file = plugin.open(&self.name, IoMode::READ | IoMode::WRITE)?;
+        for (offset, data) in self.cow_delta.iter() {
+            file.plugin_operations.write((offset + file.raddr) as usize, data)?;
+        }
+        self.cow_delta = ByteDelta::new();
+        Ok(())
+    }
+    // Drops changes done to a Copy-On-Write file by opening it again.
+    pub(crate) fn discard(&mut self, plugin: &mut dyn RIOPlugin) -> Result<(), IoError> {
+        let plugin_desc = plugin.open(&self.name, self.perm)?;
+        self.plugin_operations = plugin_desc.plugin_operations;
+        self.raddr = plugin_desc.raddr;
+        self.cow_delta = ByteDelta::new();
+        self.dirty = DirtyRanges::new();
         Ok(())
     }
     pub(crate) fn read(&mut self, paddr: usize, buffer: &mut [u8]) -> Result<(), IoError> {
         self.plugin_operations.read(paddr - self.paddr as usize + self.raddr as usize as usize, buffer)
     }
     pub(crate) fn write(&mut self, paddr: usize, buffer: &[u8]) -> Result<(), IoError> {
-        self.plugin_operations.write(paddr - self.paddr as usize + self.raddr as usize, buffer)
+        self.plugin_operations.write(paddr - self.paddr as usize + self.raddr as usize, buffer)?;
+        if self.perm.contains(IoMode::COW) {
+            self.cow_delta.record(paddr as u64 - self.paddr, buffer);
+        }
+        self.dirty.record(paddr as u64, buffer.len() as u64);
+        Ok(())
     }
     /// Returns URI of current file descriptor.
     pub fn name(&self) -> &str {
@@ -82,6 +134,19 @@ impl RIODesc {
     pub fn perm(&self) -> IoMode {
         self.perm
     }
+    /// Returns changes done to a file opened with [IoMode::COW] that are not stored on disk.
+    pub fn cow_delta(&self) -> &ByteDelta {
+        &self.cow_delta
+    }
+    /// Returns physical ranges of this file that were modified since it was opened.
+    pub fn dirty(&self) -> &DirtyRanges {
+        &self.dirty
+    }
+    /// Returns problems the plugin tolerated while loading the file (for example records with
+    /// invalid checksum in files opened with `?lenient`).
+    pub fn warnings(&self) -> &[String] {
+        self.plugin_operations.warnings()
+    }
     /// Returns the Handle of given file descriptor.
     pub fn hndl(&self) -> u64 {
         self.hndl
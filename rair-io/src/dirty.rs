@@ -0,0 +1,94 @@
+/*
+ * dirty.rs: Ranges modified during the session.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use rtrees::ist::IST;
+use serde::{Deserialize, Serialize};
+
+/// Physical ranges that were written to, stored as non overlapping and non adjacent closed
+/// intervals. Each interval is stored along with its own bounds.
+#[derive(Default, Serialize, Deserialize)]
+pub struct DirtyRanges {
+    ranges: IST<u64, (u64, u64)>,
+}
+
+impl DirtyRanges {
+    pub(crate) fn new() -> DirtyRanges {
+        Default::default()
+    }
+    /// Marks *size* bytes starting at *paddr* as modified.
+    pub(crate) fn record(&mut self, paddr: u64, size: u64) {
+        if size == 0 {
+            return;
+        }
+        let mut lo = paddr;
+        let mut hi = paddr + (size - 1);
+        // ranges that overlap or touch [lo, hi] are merged into one range.
+        for (start, end) in self.ranges.delete_overlap(lo.saturating_sub(1), hi.saturating_add(1)) {
+            lo = lo.min(start);
+            hi = hi.max(end);
+        }
+        self.ranges.insert(lo, hi, (lo, hi));
+    }
+    /// Returns *true* if no bytes were modified.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.size() == 0
+    }
+    /// Returns *true* if byte at *paddr* was modified.
+    pub fn is_dirty(&self, paddr: u64) -> bool {
+        !self.ranges.at(paddr).is_empty()
+    }
+    /// Iterate over modified ranges as closed intervals (lo, hi) sorted by address.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        (&self.ranges).into_iter().map(|(_, _, range)| *range)
+    }
+}
+
+#[cfg(test)]
+mod test_dirty {
+    use super::*;
+    #[test]
+    fn test_record() {
+        let mut dirty = DirtyRanges::new();
+        assert!(dirty.is_empty());
+        dirty.record(0x10, 3);
+        dirty.record(0x20, 1);
+        dirty.record(0x5, 0);
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), vec![(0x10, 0x12), (0x20, 0x20)]);
+        // adjacent to the first range
+        dirty.record(0x13, 1);
+        // overlaps the first range from the left
+        dirty.record(0xf, 2);
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), vec![(0xf, 0x13), (0x20, 0x20)]);
+        assert!(dirty.is_dirty(0xf));
+        assert!(!dirty.is_dirty(0x14));
+        // spans both ranges
+        dirty.record(0x12, 0x10);
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), vec![(0xf, 0x21)]);
+        dirty.record(u64::MAX, 1);
+        assert!(dirty.is_dirty(u64::MAX));
+        assert!(!dirty.is_empty());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut dirty = DirtyRanges::new();
+        dirty.record(0x10, 3);
+        dirty.record(0x20, 1);
+        let data = serde_json::to_string(&dirty).unwrap();
+        let dirty: DirtyRanges = serde_json::from_str(&data).unwrap();
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), vec![(0x10, 0x12), (0x20, 0x20)]);
+    }
+}
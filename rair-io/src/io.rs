@@ -16,12 +16,15 @@
  */
 use crate::desc::RIODesc;
 use crate::descquery::RIODescQuery;
+use crate::journal::{Journal, JournalChunk, JournalEntry};
 use crate::mapsquery::{RIOMap, RIOMapQuery};
 use crate::plugin::*;
 use crate::plugins;
 use crate::utils::*;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap;
+use std::fs;
+use std::io;
 use std::sync::Arc;
 
 // Credits goes to @Talchas#7429 for the idea of using remote
@@ -40,6 +43,8 @@ use std::sync::Arc;
 pub struct RIO {
     descs: RIODescQuery,
     maps: RIOMapQuery,
+    #[serde(default)]
+    journal: Journal,
     #[serde(skip)]
     plugins: Vec<Box<dyn RIOPlugin + Sync + Send>>,
 }
@@ -154,7 +159,8 @@ impl RIO {
     }
 
     /// Close an opened file, delete its physical and virtual address space.
-    /// In case of Error, an [IoError] is returned explaining why *close* failed.
+    /// All memory maps backed by the closed file are removed and returned sorted by their
+    /// virtual address. In case of Error, an [IoError] is returned explaining why *close* failed.
     ///
     /// # Example
     ///
@@ -170,10 +176,22 @@ impl RIO {
     /// }
     /// ```
 
-    pub fn close(&mut self, hndl: u64) -> Result<(), IoError> {
+    pub fn close(&mut self, hndl: u64) -> Result<Vec<RIOMap>, IoError> {
+        let desc = self.descs.close(hndl)?;
+        // writes to the closed file must not be replayed into files opened later at the same address.
+        self.journal.forget(desc.paddr, desc.size);
         // delete all memory mappings related to the closed handle
-        self.descs.close(hndl)?;
-        Ok(())
+        Ok(self.maps.unmap_paddr_range(desc.paddr, desc.size))
+    }
+
+    /// Close an opened file just like [RIO::close], except that memory maps backed by the
+    /// closed file are kept as dangling maps. Dangling maps are not used for address
+    /// translation, they can be listed using [RIO::dangling_iter] and removed using
+    /// [RIO::remove_dangling].
+    pub fn close_keep_maps(&mut self, hndl: u64) -> Result<Vec<RIOMap>, IoError> {
+        let maps = self.close(hndl)?;
+        self.maps.add_dangling(&maps);
+        Ok(maps)
     }
 
     /// Close all open files, and reset all virtual and physical address spaces.
@@ -196,6 +214,7 @@ impl RIO {
     pub fn close_all(&mut self) {
         self.maps = RIOMapQuery::new();
         self.descs = RIODescQuery::new();
+        self.journal = Journal::new();
     }
 
     /// Read from the physical address space of current [RIO] object. If there is no enough
@@ -251,7 +270,8 @@ impl RIO {
         Ok(result)
     }
     /// Write into the physical address space of current [RIO] object. If there is no enough
-    /// space to accomodate *buf* an error is returned.
+    /// space to accomodate *buf* an error is returned. The write is recorded in the [RIO::journal]
+    /// so that it can be undone, unless the old data cannot be read.
     ///
     /// # Example
     ///
@@ -264,6 +284,21 @@ impl RIO {
     /// io.pwrite(0x20, &fillme);
     /// ```
     pub fn pwrite(&mut self, paddr: u64, buf: &[u8]) -> Result<(), IoError> {
+        let chunk = self.pwrite_chunk(paddr, buf)?;
+        self.journal.record(JournalEntry { chunks: chunk.into_iter().collect() });
+        Ok(())
+    }
+    // Writes *buf* at *paddr* and returns what was overwritten if it could be read.
+    fn pwrite_chunk(&mut self, paddr: u64, buf: &[u8]) -> Result<Option<JournalChunk>, IoError> {
+        let mut old = vec![0; buf.len()];
+        let readable = self.pread(paddr, &mut old).is_ok();
+        self.pwrite_unjournaled(paddr, buf)?;
+        if !readable {
+            return Ok(None);
+        }
+        Ok(Some(JournalChunk { paddr, old, new: buf.to_vec() }))
+    }
+    fn pwrite_unjournaled(&mut self, paddr: u64, buf: &[u8]) -> Result<(), IoError> {
         let result = self.descs.paddr_range_to_hndl(paddr, buf.len() as u64);
         if let Some(operations) = result {
             let mut start = 0;
@@ -285,6 +320,16 @@ impl RIO {
         self.maps.map(paddr, vaddr, size)
     }
 
+    /// Map memory region described by *map* (including its permissions, name and tags)
+    /// from physical address space to virtual address space. The region can overlap maps
+    /// of different priority in the active bank, the map with highest priority is used.
+    pub fn map_region(&mut self, map: RIOMap) -> Result<(), IoError> {
+        if self.descs.paddr_range_to_hndl(map.paddr, map.size).is_none() {
+            return Err(IoError::AddressNotFound);
+        }
+        self.maps.map_region(map)
+    }
+
     /// unmap already mapped regions
     pub fn unmap(&mut self, vaddr: u64, size: u64) -> Result<(), IoError> {
         self.maps.unmap(vaddr, size)
@@ -295,6 +340,9 @@ impl RIO {
     pub fn vread(&mut self, vaddr: u64, buf: &mut [u8]) -> Result<(), IoError> {
         let result = self.maps.split_vaddr_range(vaddr, buf.len() as u64);
         if let Some(maps) = result {
+            if maps.iter().any(|map| !map.perm.contains(MapPerm::READ)) {
+                return Err(IoError::Parse(io::Error::new(io::ErrorKind::PermissionDenied, "Map Not Readable")));
+            }
             let mut start = 0;
             for map in maps {
                 self.pread(map.paddr, &mut buf[start as usize..(start + map.size) as usize])?;
@@ -306,12 +354,12 @@ impl RIO {
         }
     }
     /// read memory from virtual address space. Data is stored in a sparce
-    /// vector represented by [BTreeMap]. Error is returned only in case of
-    /// internal IO errors.
+    /// vector represented by [BTreeMap]. Maps that are not readable are skipped.
+    /// Error is returned only in case of internal IO errors.
     pub fn vread_sparce(&mut self, vaddr: u64, size: u64) -> Result<BTreeMap<u64, u8>, IoError> {
         let mut result = BTreeMap::new();
         let maps = self.maps.split_vaddr_sparce_range(vaddr, size);
-        for map in maps {
+        for map in maps.iter().filter(|map| map.perm.contains(MapPerm::READ)) {
             let mut buf = vec![0; map.size as usize];
             self.pread(map.paddr, &mut buf)?;
             for (i, v) in buf.iter().enumerate() {
@@ -320,20 +368,132 @@ impl RIO {
         }
         Ok(result)
     }
-    /// write memory into virtual address space
+    /// write memory into virtual address space, the write is recorded in the [RIO::journal]
+    /// as single entry.
     pub fn vwrite(&mut self, vaddr: u64, buf: &[u8]) -> Result<(), IoError> {
         let result = self.maps.split_vaddr_range(vaddr, buf.len() as u64);
         if let Some(maps) = result {
+            if maps.iter().any(|map| !map.perm.contains(MapPerm::WRITE)) {
+                return Err(IoError::Parse(io::Error::new(io::ErrorKind::PermissionDenied, "Map Not Writable")));
+            }
             let mut start = 0;
+            let mut entry = JournalEntry::default();
+            let mut result = Ok(());
             for map in maps {
-                self.pwrite(map.paddr, &buf[start as usize..(start + map.size) as usize])?;
+                match self.pwrite_chunk(map.paddr, &buf[start as usize..(start + map.size) as usize]) {
+                    Ok(chunk) => entry.chunks.extend(chunk),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
                 start += map.size;
             }
-            Ok(())
+            // whatever got written before failing can still be undone.
+            self.journal.record(entry);
+            result
         } else {
             Err(IoError::AddressNotFound)
         }
     }
+    /// Journal of writes done using [RIO::pwrite] and [RIO::vwrite].
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+    /// Restore the data overwritten by the last write in the [RIO::journal] that is not undone yet.
+    /// Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<bool, IoError> {
+        let entry = match self.journal.pop_done() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        for chunk in entry.chunks.iter().rev() {
+            if let Err(e) = self.pwrite_unjournaled(chunk.paddr, &chunk.old) {
+                self.journal.push_done(entry);
+                return Err(e);
+            }
+        }
+        self.journal.push_undone(entry);
+        Ok(true)
+    }
+    /// Write again the data of the last undone write. Returns false if there is nothing to redo.
+    pub fn redo(&mut self) -> Result<bool, IoError> {
+        let entry = match self.journal.pop_undone() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        for chunk in &entry.chunks {
+            if let Err(e) = self.pwrite_unjournaled(chunk.paddr, &chunk.new) {
+                self.journal.push_undone(entry);
+                return Err(e);
+            }
+        }
+        self.journal.push_done(entry);
+        Ok(true)
+    }
+    // Returns file with given handle and the plugin that opened it, the file must be opened as Copy-On-Write.
+    fn cow_desc(&mut self, hndl: u64) -> Result<(&mut RIODesc, &mut dyn RIOPlugin), IoError> {
+        let desc = self.descs.hndl_to_mut_desc(hndl).ok_or(IoError::HndlNotFoundError)?;
+        if !desc.perm.contains(IoMode::COW) {
+            return Err(IoError::Custom("File is not opened as Copy-On-Write".to_string()));
+        }
+        match self.plugins.iter_mut().find(|plugin| plugin.accept_uri(&desc.name)) {
+            Some(plugin) => Ok((desc, &mut **plugin)),
+            None => Err(IoError::IoPluginNotFoundError),
+        }
+    }
+    /// Write changes done to file opened with [IoMode::COW] back to the file it was opened from.
+    /// The file stays opened with [IoMode::COW] and the changes are no longer listed in its
+    /// [RIODesc::cow_delta].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rair_io::{RIO, IoMode, IoError};
+    /// fn main() -> Result<(), IoError> {
+    ///     let mut io = RIO::new();
+    ///     let hndl = io.open("hello.txt", IoMode::COW)?;
+    ///     io.pwrite(0, &[0x90; 4])?;
+    ///     io.commit(hndl)?;
+    ///     return Ok(());
+    /// }
+    /// ```
+    pub fn commit(&mut self, hndl: u64) -> Result<(), IoError> {
+        let (desc, plugin) = self.cow_desc(hndl)?;
+        desc.commit(plugin)
+    }
+    /// Write content of file opened with [IoMode::COW] including changes done to it into a new file
+    /// at *path*. The opened file and its changes are left intact.
+    pub fn commit_to(&mut self, hndl: u64, path: &str) -> Result<(), IoError> {
+        let (desc, _) = self.cow_desc(hndl)?;
+        let mut data = vec![0; desc.size as usize];
+        desc.read(desc.paddr as usize, &mut data)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+    /// Revert file opened with [IoMode::COW] to the content of the file it was opened from, dropping
+    /// all changes done to it.
+    pub fn discard(&mut self, hndl: u64) -> Result<(), IoError> {
+        let (desc, plugin) = self.cow_desc(hndl)?;
+        let (paddr, size) = (desc.paddr, desc.size);
+        desc.discard(plugin)?;
+        self.journal.forget(paddr, size);
+        Ok(())
+    }
+    /// Returns *true* if byte at physical address *paddr* was modified since its file was opened.
+    pub fn pdirty(&self, paddr: u64) -> bool {
+        match self.descs.paddr_range_to_hndl(paddr, 1) {
+            Some(operations) => operations.iter().any(|(hndl, _, _)| self.descs.hndl_to_desc(*hndl).unwrap().dirty().is_dirty(paddr)),
+            None => false,
+        }
+    }
+    /// Returns *true* if byte at virtual address *vaddr* was modified since its file was opened.
+    pub fn vdirty(&self, vaddr: u64) -> bool {
+        match self.vir_to_phy(vaddr, 1) {
+            Some(maps) => maps.iter().any(|map| self.pdirty(map.paddr)),
+            None => false,
+        }
+    }
 
     /// convert virtual address to physical address
     pub fn vir_to_phy(&self, vaddr: u64, size: u64) -> Option<Vec<RIOMap>> {
@@ -351,10 +511,40 @@ impl RIO {
         self.descs.into_iter()
     }
 
-    /// Iterate over memory maps
+    /// Iterate over memory maps of the active bank, including maps shadowed by maps of higher priority.
     pub fn map_iter<'a>(&'a self) -> Box<dyn Iterator<Item = Arc<RIOMap>> + 'a> {
         self.maps.into_iter()
     }
+    /// Iterate over dangling memory maps left behind by [RIO::close_keep_maps] sorted by virtual address.
+    pub fn dangling_iter(&self) -> impl Iterator<Item = &RIOMap> {
+        self.maps.dangling_iter()
+    }
+    /// Remove dangling memory maps that overlap virtual range [*vaddr*, *vaddr* + *size*) and return them.
+    pub fn remove_dangling(&mut self, vaddr: u64, size: u64) -> Vec<RIOMap> {
+        self.maps.remove_dangling(vaddr, size)
+    }
+    /// Fragments of the memory maps of the active bank that are used for address translation,
+    /// sorted by virtual address. Parts of maps shadowed by maps of higher priority are omitted.
+    pub fn effective_maps(&self) -> Vec<RIOMap> {
+        self.maps.effective_maps()
+    }
+    /// Name of the active map bank.
+    pub fn bank(&self) -> &str {
+        self.maps.bank()
+    }
+    /// Sorted names of all map banks.
+    pub fn banks(&self) -> Vec<&str> {
+        self.maps.banks()
+    }
+    /// Switch virtual address space to map bank *name*, empty bank is created if it doesn't exist.
+    /// Maps of inactive banks are kept but they are not used for address translation.
+    pub fn switch_bank(&mut self, name: &str) {
+        self.maps.switch_bank(name)
+    }
+    /// Remove inactive map bank *name* with all of its maps.
+    pub fn remove_bank(&mut self, name: &str) -> Result<(), IoError> {
+        self.maps.remove_bank(name)
+    }
     // Return equivalent [RIODesc] structure for the given *hndl*
     pub fn hndl_to_desc(&self, hndl: u64) -> Option<&RIODesc> {
         self.descs.hndl_to_desc(hndl)
@@ -504,16 +694,19 @@ mod rio_tests {
                 paddr: 0x1000,
                 vaddr: 0x400,
                 size: DATA.len() as u64,
+                ..Default::default()
             },
             RIOMap {
                 paddr: 0x2000,
                 vaddr: 0x400 + DATA.len() as u64,
                 size: DATA.len() as u64,
+                ..Default::default()
             },
             RIOMap {
                 paddr: 0x3000,
                 vaddr: 0x400 + DATA.len() as u64 * 2,
                 size: DATA.len() as u64,
+                ..Default::default()
             },
         ];
         assert_eq!(io.vir_to_phy(0x400, DATA.len() as u64 * 3).unwrap(), maps);
@@ -523,6 +716,7 @@ mod rio_tests {
             paddr: 0x1000,
             vaddr: 0x400,
             size: DATA.len() as u64,
+            ..Default::default()
         }];
         assert_eq!(io.vir_to_phy(0x400, DATA.len() as u64).unwrap(), maps);
         maps = vec![
@@ -530,11 +724,13 @@ mod rio_tests {
                 paddr: 0x2000 + DATA.len() as u64 / 2,
                 vaddr: 0x400 + DATA.len() as u64 * 3 / 2,
                 size: DATA.len() as u64 - DATA.len() as u64 / 2,
+                ..Default::default()
             },
             RIOMap {
                 paddr: 0x3000,
                 vaddr: 0x400 + DATA.len() as u64 * 2,
                 size: DATA.len() as u64,
+                ..Default::default()
             },
         ];
         assert_eq!(io.vir_to_phy(0x400 + DATA.len() as u64 * 3 / 2, DATA.len() as u64 * 2 - DATA.len() as u64 / 2).unwrap(), maps);
@@ -640,10 +836,42 @@ mod rio_tests {
         io.map(0x200, 0x2000, size).unwrap();
         io.map(0x300, 0x3000, size).unwrap();
         let mut iter = io.map_iter();
-        assert_eq!(iter.next().unwrap(), RIOMap { paddr: 0x200, vaddr: 0x2000, size });
-        assert_eq!(iter.next().unwrap(), RIOMap { paddr: 0x300, vaddr: 0x3000, size });
-        assert_eq!(iter.next().unwrap(), RIOMap { paddr: 0, vaddr: 0x4000, size });
-        assert_eq!(iter.next().unwrap(), RIOMap { paddr: 0x100, vaddr: 0x5000, size });
+        assert_eq!(
+            iter.next().unwrap(),
+            RIOMap {
+                paddr: 0x200,
+                vaddr: 0x2000,
+                size,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            iter.next().unwrap(),
+            RIOMap {
+                paddr: 0x300,
+                vaddr: 0x3000,
+                size,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            iter.next().unwrap(),
+            RIOMap {
+                paddr: 0,
+                vaddr: 0x4000,
+                size,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            iter.next().unwrap(),
+            RIOMap {
+                paddr: 0x100,
+                vaddr: 0x5000,
+                size,
+                ..Default::default()
+            }
+        );
         assert_eq!(iter.next(), None);
     }
     #[test]
@@ -761,4 +989,246 @@ mod rio_tests {
     fn test_serde() {
         operate_on_files(&serde_cb, &[DATA, DATA, DATA]);
     }
+    fn serde_cow_cb(path: &Path) {
+        let mut io = RIO::new();
+        let hndl = io.open(&path.to_string_lossy(), IoMode::COW).unwrap();
+        io.pwrite(0x10, &[0xaa; 4]).unwrap();
+        io.pwrite(0x12, &[0xbb; 4]).unwrap();
+        let serialized = serde_json::to_string(&io).unwrap();
+        drop(io);
+        io = serde_json::from_str(&serialized).unwrap();
+        let mut fillme: Vec<u8> = vec![0; 8];
+        io.pread(0x10, &mut fillme).unwrap();
+        assert_eq!(fillme, [0xaa, 0xaa, 0xbb, 0xbb, 0xbb, 0xbb, 0x2f, 0xf1]);
+        assert_eq!(
+            io.hndl_to_desc(hndl).unwrap().cow_delta().iter().collect::<Vec<_>>(),
+            vec![(0x10, &[0xaa, 0xaa, 0xbb, 0xbb, 0xbb, 0xbb][..])]
+        );
+        // file on disk is never touched
+        let mut io = RIO::new();
+        io.open(&path.to_string_lossy(), IoMode::READ).unwrap();
+        io.pread(0x10, &mut fillme).unwrap();
+        assert_eq!(fillme, &DATA[0x10..0x18]);
+    }
+    #[test]
+    fn test_serde_cow() {
+        operate_on_file(&serde_cow_cb, DATA);
+    }
+    fn commit_cb(paths: &[&Path]) {
+        let mut io = RIO::new();
+        let hndl = io.open(&paths[0].to_string_lossy(), IoMode::COW).unwrap();
+        let malloc = io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        io.pwrite(0x10, &[0xaa; 4]).unwrap();
+        io.commit_to(hndl, &paths[1].to_string_lossy()).unwrap();
+        io.commit(hndl).unwrap();
+        assert!(io.hndl_to_desc(hndl).unwrap().cow_delta().is_empty());
+        io.pwrite(0x0, &[0xbb; 2]).unwrap();
+        io.discard(hndl).unwrap();
+        let mut fillme = vec![0; 4];
+        io.pread(0x0, &mut fillme).unwrap();
+        assert_eq!(fillme, &DATA[0..4]);
+        assert!(io.hndl_to_desc(hndl).unwrap().dirty().is_empty());
+        assert!(io.journal().done().is_empty());
+        assert!(!io.undo().unwrap());
+        assert_eq!(io.commit(malloc).err().unwrap(), IoError::Custom("File is not opened as Copy-On-Write".to_string()));
+        assert_eq!(io.discard(malloc + 1).err().unwrap(), IoError::HndlNotFoundError);
+        drop(io);
+        for path in paths {
+            let mut io = RIO::new();
+            io.open(&path.to_string_lossy(), IoMode::READ).unwrap();
+            let mut data = vec![0; DATA.len()];
+            io.pread(0x0, &mut data).unwrap();
+            assert_eq!(data[0x10..0x14], [0xaa; 4]);
+            assert_eq!(data[..0x10], DATA[..0x10]);
+            assert_eq!(data[0x14..], DATA[0x14..]);
+        }
+    }
+    #[test]
+    fn test_commit() {
+        operate_on_files(&commit_cb, &[DATA, &[]]);
+    }
+    #[test]
+    fn test_close_maps() {
+        let mut io = RIO::new();
+        let hndl = io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        // second map spans both files.
+        io.map(0x10, 0x1000, 0x10).unwrap();
+        io.map(0xf0, 0x2000, 0x20).unwrap();
+        io.map(0x180, 0x3000, 0x10).unwrap();
+        let removed = io.close(hndl).unwrap();
+        assert_eq!(
+            removed,
+            vec![
+                RIOMap {
+                    paddr: 0x10,
+                    vaddr: 0x1000,
+                    size: 0x10,
+                    ..Default::default()
+                },
+                RIOMap {
+                    paddr: 0xf0,
+                    vaddr: 0x2000,
+                    size: 0x10,
+                    ..Default::default()
+                }
+            ]
+        );
+        let maps: Vec<RIOMap> = io.map_iter().map(|m| (*m).clone()).collect();
+        assert_eq!(
+            maps,
+            vec![
+                RIOMap {
+                    paddr: 0x100,
+                    vaddr: 0x2010,
+                    size: 0x10,
+                    ..Default::default()
+                },
+                RIOMap {
+                    paddr: 0x180,
+                    vaddr: 0x3000,
+                    size: 0x10,
+                    ..Default::default()
+                }
+            ]
+        );
+        assert_eq!(io.dangling_iter().count(), 0);
+        // reopening at the same physical address doesn't revive old maps.
+        io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        assert_eq!(io.vread(0x1000, &mut [0; 1]).err().unwrap(), IoError::AddressNotFound);
+        let removed = io.close_keep_maps(1).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_eq!(io.map_iter().count(), 0);
+        assert_eq!(io.dangling_iter().cloned().collect::<Vec<_>>(), removed);
+        assert_eq!(io.vread(0x3000, &mut [0; 1]).err().unwrap(), IoError::AddressNotFound);
+        assert_eq!(io.remove_dangling(0x2f00, 0x101), vec![removed[1].clone()]);
+        assert_eq!(io.dangling_iter().cloned().collect::<Vec<_>>(), vec![removed[0].clone()]);
+        let serialized = serde_json::to_string(&io).unwrap();
+        io = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(io.dangling_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_map_perm() {
+        let mut io = RIO::new();
+        io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        io.pwrite(0, &[1, 2, 3, 4]).unwrap();
+        let map = RIOMap {
+            paddr: 0,
+            vaddr: 0x1000,
+            size: 0x10,
+            perm: MapPerm::READ,
+            name: "text".to_string(),
+            tags: vec!["code".to_string()],
+            priority: 0,
+        };
+        io.map_region(map.clone()).unwrap();
+        io.map_region(RIOMap {
+            paddr: 0x10,
+            vaddr: 0x1010,
+            size: 0x10,
+            perm: MapPerm::WRITE,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            io.map_region(RIOMap {
+                paddr: 0x100,
+                vaddr: 0x5000,
+                size: 0x10,
+                ..Default::default()
+            })
+            .err()
+            .unwrap(),
+            IoError::AddressNotFound
+        );
+        let mut data = [0; 4];
+        io.vread(0x1000, &mut data).unwrap();
+        assert_eq!(data, [1, 2, 3, 4]);
+        let e = io.vwrite(0x1000, &data).err().unwrap();
+        assert_eq!(e, IoError::Parse(io::Error::new(io::ErrorKind::PermissionDenied, "Map Not Writable")));
+        let e = io.vread(0x100e, &mut data).err().unwrap();
+        assert_eq!(e, IoError::Parse(io::Error::new(io::ErrorKind::PermissionDenied, "Map Not Readable")));
+        io.vwrite(0x1010, &data).unwrap();
+        assert_eq!(io.vread_sparce(0x100e, 4).unwrap().len(), 2);
+        assert_eq!(*io.map_iter().next().unwrap(), map);
+    }
+
+    #[test]
+    fn test_journal() {
+        let mut io = RIO::new();
+        io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        io.map(0x1c, 0x1000, 4).unwrap();
+        io.map(0x20, 0x1004, 4).unwrap();
+        assert!(!io.undo().unwrap());
+        io.pwrite(0x1e, &[1, 2, 3, 4]).unwrap();
+        io.vwrite(0x1003, &[5, 6, 7, 8]).unwrap();
+        assert_eq!(io.journal().done().len(), 2);
+        assert_eq!(io.journal().done()[1].chunks.len(), 2);
+        assert_eq!(io.journal().done()[1].size(), 4);
+        let mut data = [0; 6];
+        io.pread(0x1d, &mut data).unwrap();
+        assert_eq!(data, [0, 1, 5, 6, 7, 8]);
+        assert!(io.undo().unwrap());
+        io.pread(0x1d, &mut data).unwrap();
+        assert_eq!(data, [0, 1, 2, 3, 4, 0]);
+        assert!(io.undo().unwrap());
+        assert!(!io.undo().unwrap());
+        io.pread(0x1d, &mut data).unwrap();
+        assert_eq!(data, [0; 6]);
+        assert!(io.redo().unwrap());
+        io.pread(0x1d, &mut data).unwrap();
+        assert_eq!(data, [0, 1, 2, 3, 4, 0]);
+        // new writes drop undone writes.
+        io.pwrite(0x0, &[9]).unwrap();
+        assert!(!io.redo().unwrap());
+        assert_eq!(io.journal().undone().len(), 0);
+        assert_eq!(io.journal().done().len(), 2);
+        // failed writes are not recorded.
+        assert!(io.pwrite(0x3f, &[1, 2]).is_err());
+        assert_eq!(io.journal().done().len(), 2);
+        io.close_all();
+        assert!(io.journal().done().is_empty());
+    }
+    #[test]
+    fn test_journal_close() {
+        let mut io = RIO::new();
+        let hndl = io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        io.pwrite(0xe, &[1, 2, 3, 4]).unwrap();
+        io.pwrite(0x0, &[0xaa]).unwrap();
+        io.close(hndl).unwrap();
+        io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        assert_eq!(io.journal().done().len(), 1);
+        assert!(io.undo().unwrap());
+        assert!(!io.undo().unwrap());
+        assert!(io.redo().unwrap());
+        let mut data = [0; 4];
+        io.pread(0x0, &mut data).unwrap();
+        assert_eq!(data, [0; 4]);
+        io.pread(0xe, &mut data).unwrap();
+        assert_eq!(data, [0, 0, 3, 4]);
+    }
+    #[test]
+    fn test_dirty() {
+        let mut io = RIO::new();
+        io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        io.map(0x1c, 0x1000, 8).unwrap();
+        io.pwrite(0x2, &[1, 2]).unwrap();
+        io.vwrite(0x1002, &[3, 4, 5, 6]).unwrap();
+        assert!(io.pdirty(0x3));
+        assert!(!io.pdirty(0x4));
+        assert!(!io.pdirty(0x100));
+        assert!(io.vdirty(0x1005));
+        assert!(!io.vdirty(0x1006));
+        assert!(!io.vdirty(0x2000));
+        let dirty = |io: &RIO, hndl| io.hndl_to_desc(hndl).unwrap().dirty().iter().collect::<Vec<_>>();
+        assert_eq!(dirty(&io, 0), vec![(0x2, 0x3), (0x1e, 0x1f)]);
+        assert_eq!(dirty(&io, 1), vec![(0x20, 0x21)]);
+        // undone bytes are still reported as modified.
+        io.undo().unwrap();
+        assert!(io.pdirty(0x20));
+    }
 }
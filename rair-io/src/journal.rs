@@ -0,0 +1,189 @@
+/*
+ * journal.rs: Record of writes done through RIO.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use serde::{Deserialize, Serialize};
+
+/// Contiguous physical range overwritten by a write.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct JournalChunk {
+    pub paddr: u64,
+    /// Data found at *paddr* before the write.
+    pub old: Vec<u8>,
+    /// Data written at *paddr*.
+    pub new: Vec<u8>,
+}
+
+impl JournalChunk {
+    // Parts of this chunk that lie outside [lo, hi).
+    fn clip(self, lo: u64, hi: u64) -> Vec<JournalChunk> {
+        let end = self.paddr + self.new.len() as u64;
+        if end <= lo || self.paddr >= hi {
+            return vec![self];
+        }
+        let mut parts = Vec::new();
+        if self.paddr < lo {
+            let n = (lo - self.paddr) as usize;
+            parts.push(JournalChunk {
+                paddr: self.paddr,
+                old: self.old[..n].to_vec(),
+                new: self.new[..n].to_vec(),
+            });
+        }
+        if end > hi {
+            let n = (hi - self.paddr) as usize;
+            parts.push(JournalChunk {
+                paddr: hi,
+                old: self.old[n..].to_vec(),
+                new: self.new[n..].to_vec(),
+            });
+        }
+        parts
+    }
+}
+
+/// Single call to [RIO::pwrite] or [RIO::vwrite], a virtual write spanning many maps
+/// is made of one chunk per map.
+///
+/// [RIO::pwrite]: crate::RIO::pwrite
+/// [RIO::vwrite]: crate::RIO::vwrite
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub chunks: Vec<JournalChunk>,
+}
+
+impl JournalEntry {
+    /// Total number of bytes written.
+    pub fn size(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.new.len() as u64).sum()
+    }
+}
+
+/// Writes that can be undone, followed by undone writes that can be redone.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Journal {
+    done: Vec<JournalEntry>,
+    undone: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub(crate) fn new() -> Journal {
+        Default::default()
+    }
+    /// Records new write, writes that were undone can no longer be redone.
+    pub(crate) fn record(&mut self, entry: JournalEntry) {
+        if entry.chunks.is_empty() {
+            return;
+        }
+        self.undone.clear();
+        self.done.push(entry);
+    }
+    pub(crate) fn pop_done(&mut self) -> Option<JournalEntry> {
+        self.done.pop()
+    }
+    pub(crate) fn pop_undone(&mut self) -> Option<JournalEntry> {
+        self.undone.pop()
+    }
+    pub(crate) fn push_done(&mut self, entry: JournalEntry) {
+        self.done.push(entry);
+    }
+    pub(crate) fn push_undone(&mut self, entry: JournalEntry) {
+        self.undone.push(entry);
+    }
+    /// Drops the parts of recorded writes that fall within *size* bytes starting at *paddr*, writes
+    /// left with nothing to restore are dropped altogether.
+    pub(crate) fn forget(&mut self, paddr: u64, size: u64) {
+        let end = paddr.saturating_add(size);
+        for entries in [&mut self.done, &mut self.undone].iter_mut() {
+            for entry in entries.iter_mut() {
+                entry.chunks = entry.chunks.drain(..).flat_map(|chunk| chunk.clip(paddr, end)).collect();
+            }
+            entries.retain(|entry| !entry.chunks.is_empty());
+        }
+    }
+    /// Writes that can be undone, oldest first.
+    pub fn done(&self) -> &[JournalEntry] {
+        &self.done
+    }
+    /// Writes that can be redone, the last one is redone first.
+    pub fn undone(&self) -> &[JournalEntry] {
+        &self.undone
+    }
+}
+
+#[cfg(test)]
+mod test_journal {
+    use super::*;
+
+    fn entry(paddr: u64, old: &[u8], new: &[u8]) -> JournalEntry {
+        JournalEntry {
+            chunks: vec![JournalChunk {
+                paddr,
+                old: old.to_vec(),
+                new: new.to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_journal() {
+        let mut journal = Journal::new();
+        journal.record(JournalEntry::default());
+        assert!(journal.done().is_empty());
+        journal.record(entry(0x10, &[0, 0], &[1, 2]));
+        journal.record(entry(0x20, &[0], &[3]));
+        assert_eq!(journal.done()[0].size(), 2);
+        let undone = journal.pop_done().unwrap();
+        journal.push_undone(undone);
+        assert_eq!(journal.done(), &[entry(0x10, &[0, 0], &[1, 2])]);
+        assert_eq!(journal.undone(), &[entry(0x20, &[0], &[3])]);
+        journal.record(entry(0x30, &[0], &[4]));
+        assert!(journal.undone().is_empty());
+        assert_eq!(journal.done().len(), 2);
+        assert_eq!(journal.pop_undone(), None);
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut journal = Journal::new();
+        journal.record(entry(0x10, &[0, 0, 0, 0], &[1, 2, 3, 4]));
+        journal.record(entry(0x20, &[0], &[5]));
+        journal.record(entry(0x12, &[0], &[6]));
+        let undone = journal.pop_done().unwrap();
+        journal.push_undone(undone);
+        journal.forget(0x11, 2);
+        assert_eq!(
+            journal.done(),
+            &[
+                JournalEntry {
+                    chunks: vec![
+                        JournalChunk {
+                            paddr: 0x10,
+                            old: vec![0],
+                            new: vec![1]
+                        },
+                        JournalChunk {
+                            paddr: 0x13,
+                            old: vec![0],
+                            new: vec![4]
+                        },
+                    ],
+                },
+                entry(0x20, &[0], &[5]),
+            ]
+        );
+        assert!(journal.undone().is_empty());
+    }
+}
@@ -37,15 +37,23 @@ extern crate rtrees;
 extern crate serde;
 #[cfg(test)]
 extern crate test_file;
+mod delta;
 mod desc;
 mod descquery;
+mod dirty;
 mod io;
+mod journal;
 mod mapsquery;
 mod plugin;
 mod plugins;
 mod utils;
+pub use crate::delta::ByteDelta;
 pub use crate::desc::*;
+pub use crate::dirty::DirtyRanges;
 pub use crate::io::*;
+pub use crate::journal::*;
 pub use crate::mapsquery::*;
 pub use crate::plugin::*;
+pub use crate::plugins::ihex::write_ihex;
+pub use crate::plugins::srec::write_srec;
 pub use crate::utils::*;
@@ -17,51 +17,52 @@
 use crate::utils::*;
 use rtrees::ist::IST;
 use serde::{Deserialize, Serialize};
-use std::cmp::min;
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::mem;
 use std::sync::Arc;
 
-#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct RIOMap {
     pub paddr: u64,
     pub vaddr: u64,
     pub size: u64,
+    /// Access permissions enforced on virtual reads and writes.
+    #[serde(default)]
+    pub perm: MapPerm,
+    /// Name of the mapped region such as `.text`, empty if the region is not named.
+    #[serde(default)]
+    pub name: String,
+    /// Free-form labels attached to the mapped region.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Maps can overlap other maps of different priority, the one with highest priority is used.
+    #[serde(default)]
+    pub priority: u64,
 }
 
 impl RIOMap {
-    fn has_paddr(&self, paddr: u64) -> bool {
-        paddr >= self.paddr && paddr < self.paddr + self.size
-    }
-
     fn has_vaddr(&self, vaddr: u64) -> bool {
         vaddr >= self.vaddr && vaddr < self.vaddr + self.size
     }
 
-    fn envelop(&self, map: &RIOMap) -> bool {
-        self.has_paddr(map.paddr) && self.has_paddr(map.paddr + map.size - 1) && self.has_vaddr(map.vaddr) && self.has_vaddr(map.vaddr + map.size - 1)
+    // Maps of the same priority never overlap, so these fields identify a map even when maps and
+    // rev_maps hold different copies of it (as they do after deserialization).
+    fn same_map(&self, other: &RIOMap) -> bool {
+        self.paddr == other.paddr && self.vaddr == other.vaddr && self.size == other.size && self.priority == other.priority
     }
-    fn split(mut self, vaddr: u64) -> (RIOMap, RIOMap) {
-        let delta = vaddr - self.vaddr;
-        let new_map = RIOMap {
+
+    // Fragment of the same map covering [vaddr, vaddr + size).
+    fn fragment(&self, vaddr: u64, size: u64) -> RIOMap {
+        RIOMap {
             vaddr,
-            paddr: self.paddr + delta,
-            size: self.size - delta,
-        };
-        self.size = delta;
-        (self, new_map)
-    }
-    // This will only work IFF self.envelop(map) == true
-    fn remove_projection(mut self, map: &RIOMap) -> Vec<RIOMap> {
-        let mut maps = Vec::with_capacity(2);
-        if self.vaddr < map.vaddr {
-            let (clean, tainted) = self.split(map.vaddr);
-            maps.push(clean);
-            self = tainted;
-        }
-        if map.vaddr + map.size < self.vaddr + self.size {
-            let (_, clean) = self.split(map.vaddr + map.size);
-            maps.push(clean);
+            paddr: self.paddr + (vaddr - self.vaddr),
+            size,
+            perm: self.perm,
+            name: self.name.clone(),
+            tags: self.tags.clone(),
+            priority: self.priority,
         }
-        maps
     }
 }
 
@@ -77,10 +78,59 @@ impl PartialEq<Arc<RIOMap>> for RIOMap {
     }
 }
 
+// Splits [vaddr, end) into fragments of the maps with highest priority, gaps are skipped.
+fn effective_fragments(maps: &[Arc<RIOMap>], vaddr: u64, end: u64) -> Vec<RIOMap> {
+    let mut bounds = vec![vaddr, end];
+    for map in maps {
+        bounds.push(max(map.vaddr, vaddr));
+        bounds.push(min(map.vaddr + map.size, end));
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+    let mut fragments: Vec<(&Arc<RIOMap>, RIOMap)> = Vec::new();
+    for range in bounds.windows(2) {
+        let (lo, hi) = (range[0], range[1]);
+        let top = match maps.iter().filter(|map| map.has_vaddr(lo)).max_by_key(|map| map.priority) {
+            Some(top) => top,
+            None => continue,
+        };
+        match fragments.last_mut() {
+            Some((owner, frag)) if Arc::ptr_eq(owner, top) && frag.vaddr + frag.size == lo => frag.size += hi - lo,
+            _ => fragments.push((top, top.fragment(lo, hi - lo))),
+        }
+    }
+    fragments.into_iter().map(|(_, frag)| frag).collect()
+}
+
+// Maps of a bank that is not currently used for address translation.
 #[derive(Default, Serialize, Deserialize)]
+struct MapBank {
+    maps: IST<u64, Arc<RIOMap>>,
+    rev_maps: IST<u64, Arc<RIOMap>>,
+}
+
+fn default_bank() -> String {
+    "default".to_string()
+}
+
+#[derive(Serialize, Deserialize)]
 pub(super) struct RIOMapQuery {
     maps: IST<u64, Arc<RIOMap>>,     //key = virtual address
     rev_maps: IST<u64, Arc<RIOMap>>, // key = physiscal address
+    // maps whose backing file was closed, they are not used for address translation.
+    #[serde(default)]
+    dangling: Vec<RIOMap>,
+    // name of the active bank, its maps are stored in maps and rev_maps.
+    #[serde(default = "default_bank")]
+    bank: String,
+    #[serde(default)]
+    banks: BTreeMap<String, MapBank>,
+}
+
+impl Default for RIOMapQuery {
+    fn default() -> Self {
+        RIOMapQuery::new()
+    }
 }
 
 impl RIOMapQuery {
@@ -88,41 +138,32 @@ impl RIOMapQuery {
         RIOMapQuery {
             maps: IST::new(),
             rev_maps: IST::new(),
+            dangling: Vec::new(),
+            bank: default_bank(),
+            banks: BTreeMap::new(),
         }
     }
     pub fn map(&mut self, paddr: u64, vaddr: u64, size: u64) -> Result<(), IoError> {
-        // check if vaddr is previosly used or not
-        if !self.maps.overlap(vaddr, vaddr + size - 1).is_empty() {
+        self.map_region(RIOMap {
+            paddr,
+            vaddr,
+            size,
+            ..Default::default()
+        })
+    }
+    pub fn map_region(&mut self, map: RIOMap) -> Result<(), IoError> {
+        // only maps of different priorities can overlap
+        if self.maps.overlap(map.vaddr, map.vaddr + map.size - 1).iter().any(|m| m.priority == map.priority) {
             return Err(IoError::AddressesOverlapError);
         }
-        let mapping = Arc::new(RIOMap { paddr, vaddr, size });
-        self.maps.insert(vaddr, vaddr + size - 1, mapping.clone());
-        self.rev_maps.insert(paddr, paddr + size - 1, mapping);
+        let mapping = Arc::new(map);
+        self.maps.insert(mapping.vaddr, mapping.vaddr + mapping.size - 1, mapping.clone());
+        self.rev_maps.insert(mapping.paddr, mapping.paddr + mapping.size - 1, mapping);
         Ok(())
     }
     pub fn split_vaddr_range(&self, vaddr: u64, size: u64) -> Option<Vec<RIOMap>> {
-        let maps: Vec<Arc<RIOMap>> = self.maps.overlap(vaddr, vaddr + size - 1).iter().map(|&x| x.clone()).collect();
-        if maps.is_empty() {
-            return None;
-        }
-        let mut ranges = Vec::with_capacity(maps.len());
-        let mut start = vaddr;
-        let mut remaining = size;
-        for map in maps {
-            if start < map.vaddr {
-                return None;
-            }
-            let delta = min(remaining, map.size - (start - map.vaddr));
-            let frag = RIOMap {
-                paddr: map.paddr + (start - map.vaddr),
-                vaddr: start,
-                size: delta,
-            };
-            ranges.push(frag);
-            start += delta;
-            remaining -= delta;
-        }
-        if remaining != 0 {
+        let ranges = self.split_vaddr_sparce_range(vaddr, size);
+        if ranges.iter().map(|map| map.size).sum::<u64>() != size {
             return None;
         }
         Some(ranges)
@@ -139,57 +180,129 @@ impl RIOMapQuery {
         if maps.is_empty() {
             return Vec::new();
         }
-        let mut ranged_hndl = Vec::with_capacity(maps.len());
-        let mut start = vaddr;
-        let mut remaining = size;
-        for map in maps {
-            if start < map.vaddr {
-                remaining -= map.vaddr - start;
-                start = map.vaddr;
+        effective_fragments(&maps, vaddr, vaddr + size)
+    }
+    // Fragments of all maps that are used for address translation, sorted by virtual address.
+    pub fn effective_maps(&self) -> Vec<RIOMap> {
+        let maps: Vec<Arc<RIOMap>> = self.into_iter().collect();
+        let start = maps.iter().map(|map| map.vaddr).min();
+        let end = maps.iter().map(|map| map.vaddr + map.size).max();
+        match (start, end) {
+            (Some(start), Some(end)) => effective_fragments(&maps, start, end),
+            _ => Vec::new(),
+        }
+    }
+    // Removes the part of *map* that lies inside virtual range [vaddr, vaddr + size).
+    fn unmap_fragment(&mut self, map: &Arc<RIOMap>, vaddr: u64, size: u64) -> RIOMap {
+        let start = max(map.vaddr, vaddr);
+        let end = min(map.vaddr + map.size, vaddr + size);
+        // other maps inside the deleted ranges must be kept, only map itself is removed.
+        for other in self.maps.delete_envelop(map.vaddr, map.vaddr + map.size - 1) {
+            if !other.same_map(map) {
+                self.maps.insert(other.vaddr, other.vaddr + other.size - 1, other);
             }
-            let delta = min(remaining, map.size - (start - map.vaddr));
-            let frag = RIOMap {
-                paddr: map.paddr + (start - map.vaddr),
-                vaddr: start,
-                size: delta,
-            };
-            ranged_hndl.push(frag);
-            start += delta;
-            remaining -= delta;
         }
-        ranged_hndl
+        for other in self.rev_maps.delete_envelop(map.paddr, map.paddr + map.size - 1) {
+            if !other.same_map(map) {
+                self.rev_maps.insert(other.paddr, other.paddr + other.size - 1, other);
+            }
+        }
+        let mut remaining = Vec::with_capacity(2);
+        if map.vaddr < start {
+            remaining.push(map.fragment(map.vaddr, start - map.vaddr));
+        }
+        if end < map.vaddr + map.size {
+            remaining.push(map.fragment(end, map.vaddr + map.size - end));
+        }
+        for m in remaining {
+            let m = Arc::new(m);
+            self.maps.insert(m.vaddr, m.vaddr + m.size - 1, m.clone());
+            self.rev_maps.insert(m.paddr, m.paddr + m.size - 1, m);
+        }
+        map.fragment(start, end - start)
     }
     pub fn unmap(&mut self, vaddr: u64, size: u64) -> Result<(), IoError> {
-        let fragments = self.split_vaddr_range(vaddr, size);
-        if fragments.is_none() {
+        if self.split_vaddr_range(vaddr, size).is_none() {
             return Err(IoError::AddressNotFound);
         }
-        for frag in fragments.unwrap() {
-            let old_map = self.maps.delete_envelop(frag.vaddr, frag.vaddr + frag.size - 1)[0].clone();
-            let old_rev_maps = self.rev_maps.delete_envelop(frag.paddr, frag.paddr + frag.size - 1);
-            // we will get 1 normal map and maybe many rev_maps,
-            // The reason is that 1 vaddr can only point to 1 paddr
-            // but 1 paddr can be pointed to by many vaddrs
-            old_map
-                .remove_projection(&frag)
-                .into_iter()
-                .map(|m| m)
-                .for_each(|m| self.maps.insert(m.vaddr, m.vaddr + m.size - 1, Arc::new(m)));
-            for map in old_rev_maps {
-                if map.envelop(&frag) {
-                    map.remove_projection(&frag)
-                        .into_iter()
-                        .map(|m| m)
-                        .for_each(|m| self.rev_maps.insert(m.paddr, m.paddr + m.size - 1, Arc::new(m)));
-                } else {
-                    self.rev_maps.insert(map.paddr, map.paddr + map.size - 1, map)
-                }
-            }
+        let maps: Vec<Arc<RIOMap>> = self.maps.overlap(vaddr, vaddr + size - 1).iter().map(|&x| x.clone()).collect();
+        for map in maps {
+            self.unmap_fragment(&map, vaddr, size);
         }
         Ok(())
     }
 }
 
+impl RIOMapQuery {
+    // Unmaps every part of the virtual address space that is backed by physical range
+    // [paddr, paddr + size) and returns the removed fragments sorted by virtual address.
+    // Maps of inactive banks are removed as well but they are not returned.
+    pub fn unmap_paddr_range(&mut self, paddr: u64, size: u64) -> Vec<RIOMap> {
+        let active = self.bank.clone();
+        let inactive: Vec<String> = self.banks.keys().cloned().collect();
+        for bank in inactive {
+            self.switch_bank(&bank);
+            self.unmap_active_paddr_range(paddr, size);
+        }
+        self.switch_bank(&active);
+        self.unmap_active_paddr_range(paddr, size)
+    }
+    fn unmap_active_paddr_range(&mut self, paddr: u64, size: u64) -> Vec<RIOMap> {
+        let maps: Vec<Arc<RIOMap>> = self.rev_maps.overlap(paddr, paddr + size - 1).iter().map(|&x| x.clone()).collect();
+        let mut removed = Vec::with_capacity(maps.len());
+        for map in maps {
+            let start = max(map.paddr, paddr);
+            let end = min(map.paddr + map.size, paddr + size);
+            removed.push(self.unmap_fragment(&map, map.vaddr + (start - map.paddr), end - start));
+        }
+        removed.sort_by_key(|map| map.vaddr);
+        removed
+    }
+    pub fn bank(&self) -> &str {
+        &self.bank
+    }
+    pub fn banks(&self) -> Vec<&str> {
+        let mut banks: Vec<&str> = self.banks.keys().map(|bank| &**bank).collect();
+        banks.push(&self.bank);
+        banks.sort_unstable();
+        banks
+    }
+    // Makes *name* the active bank, new empty bank is created if it doesn't exist.
+    pub fn switch_bank(&mut self, name: &str) {
+        if name == self.bank {
+            return;
+        }
+        let bank = self.banks.remove(name).unwrap_or_default();
+        let old = MapBank {
+            maps: mem::replace(&mut self.maps, bank.maps),
+            rev_maps: mem::replace(&mut self.rev_maps, bank.rev_maps),
+        };
+        let old_name = mem::replace(&mut self.bank, name.to_string());
+        self.banks.insert(old_name, old);
+    }
+    pub fn remove_bank(&mut self, name: &str) -> Result<(), IoError> {
+        if name == self.bank {
+            return Err(IoError::Custom("Cannot remove the active bank".to_string()));
+        }
+        match self.banks.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(IoError::Custom(format!("Bank `{}` does not exist", name))),
+        }
+    }
+    pub fn add_dangling(&mut self, maps: &[RIOMap]) {
+        self.dangling.extend_from_slice(maps);
+        self.dangling.sort_by_key(|map| map.vaddr);
+    }
+    pub fn remove_dangling(&mut self, vaddr: u64, size: u64) -> Vec<RIOMap> {
+        let (removed, kept) = self.dangling.drain(..).partition(|map| map.vaddr < vaddr + size && vaddr < map.vaddr + map.size);
+        self.dangling = kept;
+        removed
+    }
+    pub fn dangling_iter(&self) -> impl Iterator<Item = &RIOMap> {
+        self.dangling.iter()
+    }
+}
+
 impl<'a> IntoIterator for &'a RIOMapQuery {
     type Item = Arc<RIOMap>;
     type IntoIter = Box<dyn Iterator<Item = Arc<RIOMap>> + 'a>;
@@ -234,13 +347,22 @@ mod maps_query_test {
         map_query.unmap(0x1100, 0x100).unwrap();
         assert_eq!(map_query.maps.size(), 2);
 
-        assert_eq!(map_query.split_vaddr_range(0x1000, 0x100).unwrap(), vec![RIOMap { vaddr: 0x1000, paddr: 0, size: 0x100 }]);
+        assert_eq!(
+            map_query.split_vaddr_range(0x1000, 0x100).unwrap(),
+            vec![RIOMap {
+                vaddr: 0x1000,
+                paddr: 0,
+                size: 0x100,
+                ..Default::default()
+            }]
+        );
         assert_eq!(
             map_query.split_vaddr_range(0x1200, 0x100).unwrap(),
             vec![RIOMap {
                 vaddr: 0x1200,
                 paddr: 0x200,
-                size: 0x100
+                size: 0x100,
+                ..Default::default()
             }]
         );
         assert_eq!(map_query.split_vaddr_range(0x1100, 0x100), None);
@@ -270,7 +392,8 @@ mod maps_query_test {
             RIOMap {
                 paddr: 0x200,
                 vaddr: 0x2000,
-                size: 0x100
+                size: 0x100,
+                ..Default::default()
             },
             iter.next().unwrap()
         );
@@ -278,16 +401,26 @@ mod maps_query_test {
             RIOMap {
                 paddr: 0x300,
                 vaddr: 0x3000,
-                size: 0x100
+                size: 0x100,
+                ..Default::default()
+            },
+            iter.next().unwrap()
+        );
+        assert_eq!(
+            RIOMap {
+                paddr: 0,
+                vaddr: 0x4000,
+                size: 0x100,
+                ..Default::default()
             },
             iter.next().unwrap()
         );
-        assert_eq!(RIOMap { paddr: 0, vaddr: 0x4000, size: 0x100 }, iter.next().unwrap());
         assert_eq!(
             RIOMap {
                 paddr: 0x100,
                 vaddr: 0x5000,
-                size: 0x100
+                size: 0x100,
+                ..Default::default()
             },
             iter.next().unwrap()
         );
@@ -307,22 +440,26 @@ mod maps_query_test {
                 RIOMap {
                     paddr: 0x200,
                     vaddr: 0x2000,
-                    size: 0x90
+                    size: 0x90,
+                    ..Default::default()
                 },
                 RIOMap {
                     paddr: 0x300,
                     vaddr: 0x3000,
-                    size: 0x90
+                    size: 0x90,
+                    ..Default::default()
                 },
                 RIOMap {
                     paddr: 0x0,
                     vaddr: 0x4000,
-                    size: 0x90
+                    size: 0x90,
+                    ..Default::default()
                 },
                 RIOMap {
                     paddr: 0x100,
                     vaddr: 0x5000,
-                    size: 0x90
+                    size: 0x90,
+                    ..Default::default()
                 }
             ]
         );
@@ -343,4 +480,84 @@ mod maps_query_test {
         assert_eq!(map_query.rev_query(0x145), vec![0x5045]);
         assert_eq!(map_query.rev_query(700), Vec::<u64>::new());
     }
+    #[test]
+    fn test_priority() {
+        let mut map_query = RIOMapQuery::new();
+        map_query.map(0, 0x1000, 0x100).unwrap();
+        let shadow = RIOMap {
+            paddr: 0x500,
+            vaddr: 0x1080,
+            size: 0x100,
+            priority: 1,
+            ..Default::default()
+        };
+        map_query.map_region(shadow.clone()).unwrap();
+        assert_eq!(map_query.map_region(shadow.clone()).err().unwrap(), IoError::AddressesOverlapError);
+        let frag = |paddr, vaddr, size, priority| RIOMap {
+            paddr,
+            vaddr,
+            size,
+            priority,
+            ..Default::default()
+        };
+        assert_eq!(map_query.split_vaddr_range(0x1070, 0x20).unwrap(), vec![frag(0x70, 0x1070, 0x10, 0), frag(0x500, 0x1080, 0x10, 1)]);
+        assert_eq!(map_query.effective_maps(), vec![frag(0, 0x1000, 0x80, 0), frag(0x500, 0x1080, 0x100, 1)]);
+        // removing the shadowing map reveals the map below it.
+        assert_eq!(map_query.unmap_paddr_range(0x500, 0x40), vec![frag(0x500, 0x1080, 0x40, 1)]);
+        assert_eq!(map_query.effective_maps(), vec![frag(0, 0x1000, 0xc0, 0), frag(0x540, 0x10c0, 0xc0, 1)]);
+        // unmap removes all overlapping maps.
+        map_query.unmap(0x10c0, 0x20).unwrap();
+        assert_eq!(map_query.effective_maps(), vec![frag(0, 0x1000, 0xc0, 0), frag(0x560, 0x10e0, 0xa0, 1)]);
+        assert_eq!(map_query.split_vaddr_range(0x10c0, 0x20), None);
+        map_query.unmap(0x10e0, 0x20).unwrap();
+        assert_eq!(map_query.split_vaddr_range(0x10e0, 0x1), None);
+    }
+    #[test]
+    fn test_unmap_deserialized() {
+        let mut map_query = RIOMapQuery::new();
+        map_query.map(0x0, 0x1000, 0x10).unwrap();
+        map_query.map(0x20, 0x2000, 0x10).unwrap();
+        let data = serde_json::to_string(&map_query).unwrap();
+        let mut map_query: RIOMapQuery = serde_json::from_str(&data).unwrap();
+        map_query.unmap(0x1000, 0x10).unwrap();
+        assert!(map_query.rev_query(0x5).is_empty());
+        assert_eq!(map_query.rev_maps.size(), 1);
+        let removed = map_query.unmap_paddr_range(0x0, 0x30);
+        assert_eq!(
+            removed,
+            vec![RIOMap {
+                paddr: 0x20,
+                vaddr: 0x2000,
+                size: 0x10,
+                ..Default::default()
+            }]
+        );
+        assert_eq!(map_query.maps.size(), 0);
+        assert_eq!(map_query.rev_maps.size(), 0);
+    }
+
+    #[test]
+    fn test_banks() {
+        let mut map_query = RIOMapQuery::new();
+        map_query.map(0, 0x1000, 0x100).unwrap();
+        map_query.switch_bank("rom1");
+        assert_eq!(map_query.bank(), "rom1");
+        assert_eq!(map_query.split_vaddr_range(0x1000, 0x10), None);
+        map_query.map(0x200, 0x1000, 0x100).unwrap();
+        map_query.switch_bank("rom2");
+        map_query.map(0x300, 0x1000, 0x100).unwrap();
+        assert_eq!(map_query.banks(), vec!["default", "rom1", "rom2"]);
+        map_query.switch_bank("rom1");
+        assert_eq!(map_query.split_vaddr_range(0x1000, 0x10).unwrap()[0].paddr, 0x200);
+        // closing file removes its maps from all banks.
+        assert_eq!(map_query.unmap_paddr_range(0, 0x400).len(), 1);
+        map_query.switch_bank("default");
+        assert_eq!(map_query.split_vaddr_range(0x1000, 0x10), None);
+        assert_eq!(map_query.remove_bank("default").err().unwrap(), IoError::Custom("Cannot remove the active bank".to_string()));
+        map_query.remove_bank("rom2").unwrap();
+        assert_eq!(map_query.remove_bank("rom2").err().unwrap(), IoError::Custom("Bank `rom2` does not exist".to_string()));
+        let serialized = serde_json::to_string(&map_query).unwrap();
+        let map_query: RIOMapQuery = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(map_query.banks(), vec!["default", "rom1"]);
+    }
 }
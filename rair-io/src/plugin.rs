@@ -41,9 +41,26 @@ pub trait RIOPlugin {
 pub trait RIOPluginOperations {
     fn read(&mut self, raddr: usize, buffer: &mut [u8]) -> Result<(), IoError>;
     fn write(&mut self, raddr: usize, buffer: &[u8]) -> Result<(), IoError>;
+    /// Returns state that can't be recovered by opening the same uri again (for example
+    /// contents of memory based files), it is stored in projects alongside the file descriptor.
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Restores state returned by [RIOPluginOperations::save_state] after the file is reopened.
+    fn restore_state(&mut self, _state: &[u8]) -> Result<(), IoError> {
+        Ok(())
+    }
+    /// Returns problems that were tolerated while loading the file.
+    fn warnings(&self) -> &[String] {
+        &[]
+    }
 }
 
-struct DefPluginOperations();
+// Operations of deserialized file descriptor, it only keeps the saved plugin
+// state until the file is reopened.
+pub(crate) struct DefPluginOperations {
+    pub(crate) state: Option<Vec<u8>>,
+}
 impl RIOPluginOperations for DefPluginOperations {
     fn read(&mut self, _raddr: usize, _buffer: &mut [u8]) -> Result<(), IoError> {
         Ok(())
@@ -51,10 +68,13 @@ impl RIOPluginOperations for DefPluginOperations {
     fn write(&mut self, _raddr: usize, _buffer: &[u8]) -> Result<(), IoError> {
         Ok(())
     }
+    fn save_state(&self) -> Option<Vec<u8>> {
+        self.state.clone()
+    }
 }
 
 impl Default for Box<dyn RIOPluginOperations + Sync + Send> {
     fn default() -> Self {
-        Box::new(DefPluginOperations())
+        Box::new(DefPluginOperations { state: None })
     }
 }
@@ -16,6 +16,7 @@
  */
 use super::defaultplugin;
 use super::dummy::Dummy;
+use super::records::*;
 use crate::plugin::*;
 use crate::utils::*;
 use nom::bytes::complete::tag;
@@ -25,7 +26,6 @@ use nom::sequence::tuple;
 use nom::IResult;
 use std::collections::BTreeMap;
 use std::fmt::Write as FmtWrite;
-use std::fs::File;
 use std::fs::OpenOptions;
 use std::io;
 use std::io::Write as IoWrite;
@@ -50,6 +50,8 @@ struct FileInternals {
     prot: IoMode,
     ssa: Option<u32>, // used for Record 03
     sla: Option<u32>, // used for Record 05
+    lenient: bool,
+    warnings: Vec<String>,
 }
 named!(parse_newline, alt!(tag!("\r\n") | tag!("\n") | tag!("\r")));
 
@@ -151,6 +153,65 @@ fn parse_record05(input: &[u8]) -> IResult<&[u8], Record> {
     Ok((input, Record::SLA(addr)))
 }
 
+// Writes single record, the checksum is the two's complement of the sum of all other bytes of the record.
+fn write_record(file: &mut dyn IoWrite, kind: u8, offset: u16, data: &[u8]) -> Result<(), IoError> {
+    let mut record = format!(":{:02x}{:04x}{:02x}", data.len(), offset, kind);
+    let mut checksum = (data.len() as u8).wrapping_add((offset >> 8) as u8).wrapping_add(offset as u8).wrapping_add(kind);
+    for byte in data {
+        write!(record, "{:02x}", byte).unwrap();
+        checksum = checksum.wrapping_add(*byte);
+    }
+    writeln!(file, "{}{:02x}", record, checksum.wrapping_neg())?;
+    Ok(())
+}
+
+// Writes Record 02 or Record 04 so that *addr* can be reached by a 16 bits offset.
+fn write_extended_address(file: &mut dyn IoWrite, addr: u64) -> Result<(), IoError> {
+    if addr > 0xfffff {
+        write_record(file, 4, 0, &((addr >> 16) as u16).to_be_bytes())
+    } else {
+        write_record(file, 2, 0, &((addr >> 4) as u16 & 0xf000).to_be_bytes())
+    }
+}
+
+/// Writes sparce array of *bytes* to *file* as Intel HEX using records of at most 16 bytes, followed by the
+/// start segment address *ssa* and the start linear address *sla* if given and the EOF record.
+pub fn write_ihex(file: &mut dyn IoWrite, bytes: &BTreeMap<u64, u8>, ssa: Option<u32>, sla: Option<u32>) -> Result<(), IoError> {
+    if let Some((k, _)) = bytes.iter().next_back() {
+        if *k > 0xffff_ffff {
+            return Err(IoError::Custom("Intel HEX addresses cannot exceed 0xffffffff".to_string()));
+        }
+    }
+    if let Some(ssa) = ssa {
+        write_record(file, 3, 0, &ssa.to_be_bytes())?;
+    }
+    if let Some(sla) = sla {
+        write_record(file, 5, 0, &sla.to_be_bytes())?;
+    }
+    let mut base = 0;
+    let mut start = 0;
+    let mut data = Vec::with_capacity(0x10);
+    for (k, v) in bytes.iter() {
+        // records can neither have holes nor cross 64KB boundary.
+        if !data.is_empty() && (data.len() == 0x10 || *k != start + data.len() as u64 || *k & 0xffff == 0) {
+            write_record(file, 0, start as u16, &data)?;
+            data.clear();
+        }
+        if data.is_empty() {
+            if *k & !0xffff != base {
+                base = *k & !0xffff;
+                write_extended_address(file, *k)?;
+            }
+            start = *k;
+        }
+        data.push(*v);
+    }
+    if !data.is_empty() {
+        write_record(file, 0, start as u16, &data)?;
+    }
+    write_record(file, 1, 0, &[])
+}
+
 impl FileInternals {
     fn parse_ihex(&mut self, input: &[u8]) -> Result<(), IoError> {
         named!(parse_record(&[u8]) -> Record, alt!(parse_record00 | parse_record01 | parse_record02 | parse_record03 | parse_record04 | parse_record05));
@@ -158,6 +219,20 @@ impl FileInternals {
         let mut base = 0u64;
         let mut line = 1;
         loop {
+            let (text, rest) = split_line(input);
+            if let Some(text) = text.strip_prefix(b":") {
+                if let Err(problem) = verify(line, text, 5, u8::wrapping_neg) {
+                    if !self.lenient {
+                        return Err(IoError::Custom(problem.to_string()));
+                    }
+                    self.warnings.push(problem.to_string());
+                    if problem.skip() {
+                        input = rest;
+                        line += 1;
+                        continue;
+                    }
+                }
+            }
             let x = match parse_record(input) {
                 Ok(x) => x,
                 Err(_) => return Err(IoError::Custom(format!("Invalid Ihex entry at line: {}", line))),
@@ -178,99 +253,10 @@ impl FileInternals {
         }
         Ok(())
     }
-    fn write_sa(&self, file: &mut File) -> Result<(), IoError> {
-        if let Some(ssa) = self.ssa {
-            let mut checksum: u16 = 4 + 3;
-            for byte in ssa.to_be_bytes().iter() {
-                checksum = (checksum + *byte as u16) & 0xFF;
-            }
-            checksum = 256 - checksum;
-            writeln!(file, ":04000003{:08x}{:02x}", ssa, checksum)?;
-        }
-        if let Some(sla) = self.sla {
-            let mut checksum: u16 = 4 + 5;
-            for byte in sla.to_be_bytes().iter() {
-                checksum = (checksum + *byte as u16) & 0xFF;
-            }
-            checksum = 256 - checksum;
-            writeln!(file, ":04000005{:08x}{:02x}", sla, checksum)?;
-        }
-        Ok(())
-    }
-    fn write_record04(&self, file: &mut File, addr: u64) -> Result<(), IoError> {
-        let addr = (addr >> 16) as u16;
-        let mut checksum = 6;
-        for byte in addr.to_be_bytes().iter() {
-            checksum = (checksum + *byte as u16) & 0xFF;
-        }
-        checksum = 256 - checksum;
-        writeln!(file, ":02000004{:04x}{:02x}", addr, checksum)?;
-        Ok(())
-    }
-
-    fn write_record02(&self, file: &mut File, addr: u64) -> Result<(), IoError> {
-        let addr = (addr >> 4) as u16 & 0xf000;
-        let mut checksum = 4;
-        for byte in addr.to_be_bytes().iter() {
-            checksum = (checksum + *byte as u16) & 0xFF;
-        }
-        checksum = 256 - checksum;
-        writeln!(file, ":02000002{:04x}{:02x}", addr, checksum)?;
-        Ok(())
-    }
-
-    fn write_data(&self, file: &mut File) -> Result<(), IoError> {
-        let mut checksum: u16 = 0x10;
-        let mut addr = self.base();
-        let mut data = String::new();
-        let mut i = 0;
-        for (k, v) in self.bytes.iter() {
-            if i != 0 {
-                if i == 0x10 || *k != addr + 1 {
-                    writeln!(file, ":{:02x}{}{:02x}", i, data, (256 - checksum) & 0xff)?;
-                    data.clear();
-                    checksum = 0x10;
-                    i = 0;
-                } else {
-                    // we know that *k == addr + 1
-                    addr = *k;
-                    write!(data, "{:02x}", *v).unwrap();
-                    checksum = (checksum + *v as u16) & 0xff;
-                }
-            }
-            if i == 0 {
-                if *k > 0xfffff {
-                    // record 04
-                    self.write_record04(file, *k)?;
-                } else if *k > 0xffff {
-                    // record 02
-                    self.write_record02(file, *k)?;
-                }
-                let offset = (*k & 0xffff) as u16;
-                for byte in offset.to_be_bytes().iter() {
-                    checksum = (checksum + *byte as u16) & 0xff;
-                }
-                addr = *k;
-                write!(data, "{:04x}00{:02x}", offset, *v).unwrap();
-                checksum = (checksum + *v as u16) & 0xff;
-            }
-            i += 1;
-        }
-        if !data.is_empty() {
-            writeln!(file, ":{:02x}{}{:02x}", i, data, 256 - checksum)?;
-        }
-        Ok(())
-    }
     fn save_ihex(&self) -> Result<(), IoError> {
         // truncate the current file.
         let mut file = OpenOptions::new().write(true).truncate(true).open(IHexPlugin::uri_to_path(&self.uri))?;
-        //write ssa and sla
-        self.write_sa(&mut file)?;
-        //write data
-        self.write_data(&mut file)?;
-        // write EOF
-        writeln!(file, ":00000001FF")?;
-        Ok(())
+        write_ihex(&mut file, &self.bytes, self.ssa, self.sla)
     }
     fn size(&self) -> u64 {
         let min = if let Some((k, _)) = self.bytes.iter().next() {
@@ -328,6 +314,10 @@ impl RIOPluginOperations for FileInternals {
         }
         Ok(())
     }
+
+    fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
 }
 
 struct IHexPlugin {
@@ -336,7 +326,7 @@ struct IHexPlugin {
 
 impl IHexPlugin {
     fn uri_to_path(uri: &str) -> &Path {
-        let path = uri.trim_start_matches("ihex://");
+        let path = strip_lenient(uri).0.trim_start_matches("ihex://");
         Path::new(path)
     }
     fn new() -> IHexPlugin {
@@ -361,6 +351,8 @@ impl RIOPlugin for IHexPlugin {
             sla: None,
             prot: flags,
             uri: uri.to_string(),
+            lenient: strip_lenient(uri).1,
+            warnings: Vec::new(),
         };
         let mut data = vec![0; def_desc.size as usize];
         internal.file.read(0x0, &mut data)?;
@@ -635,9 +627,71 @@ mod test_ihex {
         assert_eq!(err, IoError::Custom("Invalid Ihex entry at line: 4".to_string()));
     }
     #[test]
+    fn test_write_ihex() {
+        let mut bytes: BTreeMap<u64, u8> = b"address gap".iter().enumerate().map(|(i, b)| (0x10 + i as u64, *b)).collect();
+        bytes.insert(0x1234_fffe, 0x11);
+        bytes.insert(0x1234_ffff, 0x22);
+        bytes.insert(0x1235_0000, 0x33);
+        let mut out = Vec::new();
+        write_ihex(&mut out, &bytes, None, Some(0x10)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ":0400000500000010e7\n\
+             :0b0010006164647265737320676170a7\n\
+             :020000041234b4\n\
+             :02fffe001122ce\n\
+             :020000041235b3\n\
+             :0100000033cc\n\
+             :00000001ff\n"
+        );
+        bytes.insert(0x1_0000_0000, 0);
+        let err = write_ihex(&mut Vec::new(), &bytes, None, None).err().unwrap();
+        assert_eq!(err, IoError::Custom("Intel HEX addresses cannot exceed 0xffffffff".to_string()));
+    }
+    fn write_ihex_cb(path: &Path) {
+        let bytes: BTreeMap<u64, u8> = (0..0x30).map(|i| (0x2fff0 + i, i as u8)).chain((0..4).map(|i| (0x50000 + i, 0xff))).collect();
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        write_ihex(&mut file, &bytes, Some(0x1234), None).unwrap();
+        drop(file);
+        let mut p = plugin();
+        let mut file = p.open(&(String::from("ihex://") + &path.to_string_lossy()), IoMode::READ).unwrap();
+        assert_eq!(file.raddr, 0x2fff0);
+        assert_eq!(file.size, 0x20014);
+        let mut buffer = vec![0; 0x30];
+        file.plugin_operations.read(0x2fff0, &mut buffer).unwrap();
+        assert_eq!(buffer, (0..0x30).collect::<Vec<u8>>());
+        file.plugin_operations.read(0x50000, &mut buffer[..4]).unwrap();
+        assert_eq!(buffer[..4], [0xff; 4]);
+    }
+    #[test]
+    fn test_write_ihex_reopen() {
+        operate_on_file(&write_ihex_cb, &[]);
+    }
+    #[test]
     fn test_empty() {
         let mut p = plugin();
         let f = p.open("ihex://../../testing_binaries/rio/ihex/empty.hex", IoMode::READ).unwrap();
         assert_eq!(f.size, 0);
     }
+
+    fn lenient_cb(path: &Path) {
+        let mut p = plugin();
+        let uri = String::from("ihex://") + &path.to_string_lossy();
+        let err = p.open(&uri, IoMode::READ).err().unwrap();
+        assert_eq!(err, IoError::Custom("Invalid byte count at line 1: expected 0x03, found 0x04".to_string()));
+        let mut file = p.open(&(uri + "?lenient"), IoMode::READ).unwrap();
+        assert_eq!(file.raddr, 0x30);
+        assert_eq!(file.size, 3);
+        assert_eq!(
+            file.plugin_operations.warnings(),
+            ["Invalid byte count at line 1: expected 0x03, found 0x04", "Invalid checksum at line 2: expected 0x1e, found 0x1f"]
+        );
+        let mut buffer = vec![0; 3];
+        file.plugin_operations.read(0x30, &mut buffer).unwrap();
+        assert_eq!(buffer, [0x02, 0x33, 0x7a]);
+    }
+    #[test]
+    fn test_lenient() {
+        operate_on_file(&lenient_cb, b":0400300002337A1E\n:0300300002337A1F\n:00000001FF\n");
+    }
 }
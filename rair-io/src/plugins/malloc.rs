@@ -55,6 +55,18 @@ impl RIOPluginOperations for MallocInternal {
         self.data[raddr..raddr + buf.len()].copy_from_slice(buf);
         Ok(())
     }
+
+    fn save_state(&self) -> Option<Vec<u8>> {
+        Some(self.data.clone())
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), IoError> {
+        if state.len() != self.len() {
+            return Err(IoError::Custom("Saved memory contents don't match the file size".to_string()));
+        }
+        self.data.copy_from_slice(state);
+        Ok(())
+    }
 }
 
 struct MallocPlugin {}
@@ -161,4 +173,19 @@ mod test_malloc {
         err = file.plugin_operations.write(0, &buffer).err().unwrap();
         assert_eq!(err, IoError::Parse(io::Error::new(io::ErrorKind::UnexpectedEof, "BufferOverflow")));
     }
+
+    #[test]
+    fn test_malloc_state() {
+        let mut p = plugin();
+        let mut file = p.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        file.plugin_operations.write(0x4, &[0xab; 4]).unwrap();
+        let state = file.plugin_operations.save_state().unwrap();
+        let mut file = p.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        file.plugin_operations.restore_state(&state).unwrap();
+        let mut buffer = [1; 0x10];
+        file.plugin_operations.read(0, &mut buffer).unwrap();
+        assert_eq!(buffer, [0, 0, 0, 0, 0xab, 0xab, 0xab, 0xab, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let err = file.plugin_operations.restore_state(&state[1..]).err().unwrap();
+        assert_eq!(err, IoError::Custom("Saved memory contents don't match the file size".to_string()));
+    }
 }
@@ -20,6 +20,7 @@ pub mod defaultplugin;
 pub mod dummy;
 pub mod ihex;
 pub mod malloc;
+mod records;
 pub mod srec;
 pub(crate) fn load_plugins(io: &mut RIO) {
     io.load_plugin(defaultplugin::plugin());
@@ -0,0 +1,125 @@
+/*
+ * records.rs: Verification shared by line based record formats (Intel HEX and S-records).
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::fmt;
+use std::str;
+
+// URI query that loads records with invalid byte count or checksum instead of failing.
+const LENIENT: &str = "?lenient";
+
+// Splits *uri* into the uri without the lenient query and whether the query was there.
+pub(crate) fn strip_lenient(uri: &str) -> (&str, bool) {
+    match uri.strip_suffix(LENIENT) {
+        Some(uri) => (uri, true),
+        None => (uri, false),
+    }
+}
+
+// Splits *input* into the first line without its terminator and whatever comes after the terminator.
+pub(crate) fn split_line(input: &[u8]) -> (&[u8], &[u8]) {
+    let end = input.iter().position(|c| *c == b'\n' || *c == b'\r').unwrap_or(input.len());
+    let mut rest = &input[end..];
+    if rest.starts_with(b"\r\n") {
+        rest = &rest[2..];
+    } else if !rest.is_empty() {
+        rest = &rest[1..];
+    }
+    (&input[..end], rest)
+}
+
+fn decode_hex(text: &[u8]) -> Option<Vec<u8>> {
+    text.chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) enum Problem {
+    ByteCount { line: u64, expected: u64, found: u8 },
+    Checksum { line: u64, expected: u8, found: u8 },
+}
+
+impl Problem {
+    // Records with wrong byte count cannot be parsed so they are skipped in lenient mode.
+    pub(crate) fn skip(&self) -> bool {
+        match self {
+            Problem::ByteCount { .. } => true,
+            Problem::Checksum { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::ByteCount { line, expected, found } => write!(f, "Invalid byte count at line {}: expected 0x{:02x}, found 0x{:02x}", line, expected, found),
+            Problem::Checksum { line, expected, found } => write!(f, "Invalid checksum at line {}: expected 0x{:02x}, found 0x{:02x}", line, expected, found),
+        }
+    }
+}
+
+// Verifies byte count and checksum of record at *line*. *text* is the hex encoded part of the record
+// starting with the byte count and ending with the checksum, *overhead* is the number of bytes in that
+// part that are not covered by the byte count, and *checksum* computes the checksum from the sum of
+// all bytes except the checksum itself. Records that cannot be decoded are left for the parser to reject.
+pub(crate) fn verify(line: u64, text: &[u8], overhead: usize, checksum: fn(u8) -> u8) -> Result<(), Problem> {
+    let bytes = match decode_hex(text) {
+        Some(bytes) if bytes.len() >= overhead => bytes,
+        _ => return Ok(()),
+    };
+    let count = bytes[0];
+    if bytes.len() != count as usize + overhead {
+        let expected = (bytes.len() - overhead) as u64;
+        return Err(Problem::ByteCount { line, expected, found: count });
+    }
+    let (found, bytes) = bytes.split_last().unwrap();
+    let expected = checksum(bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+    if expected != *found {
+        return Err(Problem::Checksum { line, expected, found: *found });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_records {
+    use super::*;
+
+    #[test]
+    fn test_split_line() {
+        assert_eq!(split_line(b":00000001FF\r\n:01"), (&b":00000001FF"[..], &b":01"[..]));
+        assert_eq!(split_line(b"S5\rS9\n"), (&b"S5"[..], &b"S9\n"[..]));
+        assert_eq!(split_line(b"S9"), (&b"S9"[..], &b""[..]));
+        assert_eq!(strip_lenient("ihex://a.hex?lenient"), ("ihex://a.hex", true));
+        assert_eq!(strip_lenient("ihex://a.hex"), ("ihex://a.hex", false));
+    }
+
+    #[test]
+    fn test_verify() {
+        let ihex = |sum: u8| sum.wrapping_neg();
+        assert!(verify(1, b"0300300002337A1E", 5, ihex).is_ok());
+        assert_eq!(
+            verify(2, b"0300300002337A1F", 5, ihex).err().unwrap().to_string(),
+            "Invalid checksum at line 2: expected 0x1e, found 0x1f"
+        );
+        let problem = verify(3, b"0400300002337A1E", 5, ihex).err().unwrap();
+        assert!(problem.skip());
+        assert_eq!(problem.to_string(), "Invalid byte count at line 3: expected 0x03, found 0x04");
+        // left for the parser
+        assert!(verify(4, b"03003G", 5, ihex).is_ok());
+    }
+}
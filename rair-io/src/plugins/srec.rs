@@ -17,6 +17,7 @@
 
 use super::defaultplugin;
 use super::dummy::Dummy;
+use super::records::*;
 use crate::plugin::*;
 use crate::utils::*;
 use nom::bytes::complete::tag;
@@ -26,7 +27,7 @@ use nom::sequence::tuple;
 use nom::IResult;
 use std::collections::BTreeMap;
 use std::fmt::Write as FmtWrite;
-use std::fs::{File, OpenOptions};
+use std::fs::OpenOptions;
 use std::io;
 use std::io::Write as IoWrite;
 use std::path::Path;
@@ -50,6 +51,8 @@ struct SrecInternal {
     prot: IoMode,
     start_address: Option<u64>, // I am not sure if this will always exist or not
     header: Vec<u8>,
+    lenient: bool,
+    warnings: Vec<String>,
 }
 enum Record {
     Header(Vec<u8>),    // Record S0 (header data)
@@ -182,11 +185,87 @@ fn parse_record9(input: &[u8]) -> IResult<&[u8], Record> {
 }
 named!(parse_record(&[u8]) -> Record, alt!(parse_record0 | parse_record1 | parse_record2 | parse_record3 | parse_record5 | parse_record6 | parse_record7 | parse_record8 | parse_record9));
 
+// Writes single record whose address field is *addr_size* bytes long, the checksum is the one's complement
+// of the sum of the count, address and data bytes.
+fn write_record(file: &mut dyn IoWrite, kind: u8, addr: u64, addr_size: usize, data: &[u8]) -> Result<(), IoError> {
+    let count = (addr_size + data.len() + 1) as u8;
+    let mut record = format!("S{}{:02x}{:0width$x}", kind, count, addr, width = addr_size * 2);
+    let mut checksum = count;
+    for byte in addr.to_be_bytes()[8 - addr_size..].iter().chain(data) {
+        checksum = checksum.wrapping_add(*byte);
+    }
+    for byte in data {
+        write!(record, "{:02x}", byte).unwrap();
+    }
+    writeln!(file, "{}{:02x}", record, !checksum)?;
+    Ok(())
+}
+
+/// Writes sparce array of *bytes* to *file* as Motorola S-records, starting with *header* in S0 record and
+/// ending with *start* address in S7, S8 or S9 record. Data records hold at most 16 bytes and use the
+/// smallest of S1, S2 and S3 records that can address them.
+pub fn write_srec(file: &mut dyn IoWrite, bytes: &BTreeMap<u64, u8>, header: &[u8], start: u64) -> Result<(), IoError> {
+    if header.len() + 3 > 0xff {
+        return Err(IoError::Custom("Cannot write S0 Entry with size > 0xff".to_string()));
+    }
+    let last = bytes.iter().next_back().map_or(0, |(k, _)| *k);
+    if last > 0xffff_ffff || start > 0xffff_ffff {
+        return Err(IoError::Custom("S-record addresses cannot exceed 0xffffffff".to_string()));
+    }
+    write_record(file, 0, 0, 2, header)?;
+    let write_data = |file: &mut dyn IoWrite, addr: u64, data: &[u8]| {
+        let end = addr + data.len() as u64 - 1;
+        if end > 0x00ff_ffff {
+            write_record(file, 3, addr, 4, data)
+        } else if end > 0xffff {
+            write_record(file, 2, addr, 3, data)
+        } else {
+            write_record(file, 1, addr, 2, data)
+        }
+    };
+    let mut addr = 0;
+    let mut data = Vec::with_capacity(0x10);
+    for (k, v) in bytes.iter() {
+        if !data.is_empty() && (data.len() == 0x10 || *k != addr + data.len() as u64) {
+            write_data(file, addr, &data)?;
+            data.clear();
+        }
+        if data.is_empty() {
+            addr = *k;
+        }
+        data.push(*v);
+    }
+    if !data.is_empty() {
+        write_data(file, addr, &data)?;
+    }
+    if start > 0x00ff_ffff {
+        write_record(file, 7, start, 4, &[])
+    } else if start > 0xffff {
+        write_record(file, 8, start, 3, &[])
+    } else {
+        write_record(file, 9, start, 2, &[])
+    }
+}
+
 impl SrecInternal {
     fn parse_srec(&mut self, input: &[u8]) -> Result<(), IoError> {
         let mut input = input;
         let mut line = 1;
         loop {
+            let (text, rest) = split_line(input);
+            if text.len() > 1 && text[0] == b'S' {
+                if let Err(problem) = verify(line, &text[2..], 1, |sum| !sum) {
+                    if !self.lenient {
+                        return Err(IoError::Custom(problem.to_string()));
+                    }
+                    self.warnings.push(problem.to_string());
+                    if problem.skip() {
+                        input = rest;
+                        line += 1;
+                        continue;
+                    }
+                }
+            }
             let x = match parse_record(input) {
                 Ok(x) => x,
                 Err(_) => return Err(IoError::Custom(format!("Invalid S-record at line: {}", line))),
@@ -229,108 +308,9 @@ impl SrecInternal {
             0
         }
     }
-    fn write_header(&mut self, file: &mut File) -> Result<(), IoError> {
-        if self.header.len() > 0xff {
-            return Err(IoError::Custom("Cannot write S0 Entry with size > 0xff".to_string()));
-        }
-        write!(file, "S0{:02x}0000", self.header.len() + 3).unwrap();
-        let mut checksum = self.header.len() as u16;
-        for byte in self.header.iter() {
-            checksum = (checksum + *byte as u16) & 0xff;
-            write!(file, "{:02x}", byte).unwrap();
-        }
-        writeln!(file, "{:02x}", !((checksum & 0xff) as u8)).unwrap();
-        Ok(())
-    }
-    fn write_data(&mut self, file: &mut File) -> Result<(), IoError> {
-        let mut checksum: u16 = 0x10;
-        let mut data = String::new();
-        let mut record: &str = "S1";
-        let mut addr = 0;
-        let mut i = 0;
-        let mut extra_data = 0;
-        for (k, v) in self.bytes.iter() {
-            if i != 0 {
-                if i == 0x10 || *k != addr + 1 {
-                    let size = i + extra_data;
-                    checksum = (!(checksum + size)) & 0xff;
-                    writeln!(file, "{}{:02x}{}{:02x}", record, size, data, checksum)?;
-
-                    data.clear();
-                    checksum = 0;
-                    i = 0;
-                } else {
-                    // we know that *k == addr + 1
-                    addr = *k;
-                    write!(data, "{:02x}", *v).unwrap();
-                    checksum = (checksum + *v as u16) & 0xff;
-                }
-            }
-            if i == 0 {
-                if *k > 0x00ff_ffff {
-                    // record S3
-                    record = "S3";
-                    extra_data = 5;
-                    write!(data, "{:08x}", *k).unwrap();
-                } else if *k > 0xffff {
-                    // record S2
-                    record = "S2";
-                    extra_data = 4;
-                    write!(data, "{:06x}", *k).unwrap();
-                } else {
-                    // record S1
-                    record = "S1";
-                    extra_data = 3;
-                    write!(data, "{:04x}", *k).unwrap();
-                }
-                for byte in k.to_be_bytes().iter() {
-                    checksum = (checksum + *byte as u16) & 0xff;
-                }
-                write!(data, "{:02x}", *v).unwrap();
-                checksum = (checksum + *v as u16) & 0xff;
-                addr = *k;
-            }
-            i += 1;
-        }
-        if !data.is_empty() {
-            let size = i + extra_data;
-            checksum = (!(checksum + size)) & 0xff;
-            writeln!(file, "{}{:02x}{}{:02x}", record, size, data, checksum)?;
-        }
-
-        Ok(())
-    }
-    fn write_eof(&mut self, file: &mut File) -> Result<(), IoError> {
-        let start = match self.start_address {
-            Some(start) => start,
-            None => return Ok(()),
-        };
-        let mut checksum: u16;
-        if start > 0x00ff_ffff {
-            // record S7
-            write!(file, "S705{:08x}", start)?;
-            checksum = 0x5;
-        } else if start > 0xffff {
-            //record S8
-            write!(file, "S804{:06x}", start)?;
-            checksum = 0x4;
-        } else {
-            // record S9
-            write!(file, "S903{:04x}", start)?;
-            checksum = 0x3;
-        }
-        for byte in start.to_be_bytes().iter() {
-            checksum = (checksum + *byte as u16) & 0xff;
-        }
-        writeln!(file, "{:02x}", checksum).unwrap();
-        Ok(())
-    }
     fn save_srec(&mut self) -> Result<(), IoError> {
         let mut file = OpenOptions::new().write(true).truncate(true).open(SrecPlugin::uri_to_path(&self.uri))?;
-        self.write_header(&mut file)?;
-        self.write_data(&mut file)?;
-        self.write_eof(&mut file)?;
-        Ok(())
+        write_srec(&mut file, &self.bytes, &self.header, self.start_address.unwrap_or(0))
     }
 }
 
@@ -368,6 +348,10 @@ impl RIOPluginOperations for SrecInternal {
         }
         Ok(())
     }
+
+    fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
 }
 
 struct SrecPlugin {
@@ -376,7 +360,7 @@ struct SrecPlugin {
 
 impl SrecPlugin {
     fn uri_to_path(uri: &str) -> &Path {
-        let path = uri.trim_start_matches("srec://");
+        let path = strip_lenient(uri).0.trim_start_matches("srec://");
         Path::new(path)
     }
     fn new() -> Self {
@@ -401,6 +385,8 @@ impl RIOPlugin for SrecPlugin {
             uri: uri.to_string(),
             start_address: None,
             header: Vec::new(),
+            lenient: strip_lenient(uri).1,
+            warnings: Vec::new(),
         };
         let mut data = vec![0; def_desc.size as usize];
         internal.file.read(0x0, &mut data)?;
@@ -665,10 +651,75 @@ mod test_srec {
         operate_on_copy(&write_s3_cb, "../../testing_binaries/rio/srec/record_0_3_7.srec");
     }
 
+    #[test]
+    fn test_write_srec() {
+        let mut bytes: BTreeMap<u64, u8> = (0..0x10).map(|i| (0x7af0 + i, 0)).collect();
+        bytes.insert(0x7af0, 0xa);
+        bytes.insert(0x7af1, 0xa);
+        bytes.insert(0x7af2, 0xd);
+        bytes.insert(0x12_3456, 0x11);
+        bytes.insert(0x1234_5678, 0x22);
+        let mut out = Vec::new();
+        write_srec(&mut out, &bytes, b"hello     \0\0", 0).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "S00f000068656c6c6f202020202000003c\n\
+             S1137af00a0a0d0000000000000000000000000061\n\
+             S205123456114d\n\
+             S3061234567822c3\n\
+             S9030000fc\n"
+        );
+        out = Vec::new();
+        write_srec(&mut out, &BTreeMap::new(), b"", 0x1234_5678).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "S0030000fc\nS70512345678e6\n");
+        let err = write_srec(&mut Vec::new(), &bytes, &[0; 0xfd], 0).err().unwrap();
+        assert_eq!(err, IoError::Custom("Cannot write S0 Entry with size > 0xff".to_string()));
+        let err = write_srec(&mut Vec::new(), &bytes, b"", 0x1_0000_0000).err().unwrap();
+        assert_eq!(err, IoError::Custom("S-record addresses cannot exceed 0xffffffff".to_string()));
+    }
+    fn write_srec_cb(path: &Path) {
+        let bytes: BTreeMap<u64, u8> = (0..0x30).map(|i| (0xfff0 + i, i as u8)).collect();
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        write_srec(&mut file, &bytes, b"rair", 0x10000).unwrap();
+        drop(file);
+        let mut p = plugin();
+        let mut file = p.open(&(String::from("srec://") + &path.to_string_lossy()), IoMode::READ).unwrap();
+        assert_eq!(file.raddr, 0xfff0);
+        assert_eq!(file.size, 0x30);
+        let mut buffer = vec![0; 0x30];
+        file.plugin_operations.read(0xfff0, &mut buffer).unwrap();
+        assert_eq!(buffer, (0..0x30).collect::<Vec<u8>>());
+    }
+    #[test]
+    fn test_write_srec_reopen() {
+        operate_on_file(&write_srec_cb, &[]);
+    }
+
     #[test]
     fn test_corrupted() {
         let mut p = plugin();
         let err = p.open("srec://../../testing_binaries/rio/srec/corrupted.srec", IoMode::READ).err().unwrap();
         assert_eq!(err, IoError::Custom("Invalid S-record at line: 2".to_string()));
     }
+
+    fn lenient_cb(path: &Path) {
+        let mut p = plugin();
+        let uri = String::from("srec://") + &path.to_string_lossy();
+        let err = p.open(&uri, IoMode::READ).err().unwrap();
+        assert_eq!(err, IoError::Custom("Invalid byte count at line 1: expected 0x05, found 0x06".to_string()));
+        let mut file = p.open(&(uri + "?lenient"), IoMode::READ).unwrap();
+        assert_eq!(file.raddr, 0x1000);
+        assert_eq!(file.size, 2);
+        assert_eq!(
+            file.plugin_operations.warnings(),
+            ["Invalid byte count at line 1: expected 0x05, found 0x06", "Invalid checksum at line 2: expected 0x41, found 0x00"]
+        );
+        let mut buffer = vec![0; 2];
+        file.plugin_operations.read(0x1000, &mut buffer).unwrap();
+        assert_eq!(buffer, [0xcc, 0xdd]);
+    }
+    #[test]
+    fn test_lenient() {
+        operate_on_file(&lenient_cb, b"S1060000AABB91\nS1051000CCDD00\nS9030000FC\n");
+    }
 }
@@ -27,6 +27,31 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(Serialize, Deserialize)]
+    pub struct MapPerm: u64 {
+    const EXEC = 1;
+    const WRITE = 2;
+    const READ = 4;
+    }
+}
+
+// Memory maps are fully accessible unless explicitly restricted.
+impl Default for MapPerm {
+    fn default() -> Self {
+        MapPerm::all()
+    }
+}
+
+impl fmt::Display for MapPerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = if self.contains(MapPerm::READ) { 'r' } else { '-' };
+        let w = if self.contains(MapPerm::WRITE) { 'w' } else { '-' };
+        let x = if self.contains(MapPerm::EXEC) { 'x' } else { '-' };
+        write!(f, "{}{}{}", r, w, x)
+    }
+}
+
 #[derive(Debug)]
 pub enum IoError {
     AddressNotFound,
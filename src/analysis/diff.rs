@@ -0,0 +1,271 @@
+/*
+ * diff.rs: commands for comparing two address ranges.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use rair_io::IoError;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::Write;
+use yansi::Paint;
+
+// Maximum number of bytes read from each range at a time.
+const CHUNK_SIZE: u64 = 0x10000;
+// Number of bytes shown from each range per line of the side by side view.
+const ROW_SIZE: u64 = 8;
+
+/// Run of consecutive bytes that differ between two ranges, as returned by [diff].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DiffRun {
+    /// Address of the run inside the first range.
+    pub addr1: u64,
+    /// Address of the run inside the second range.
+    pub addr2: u64,
+    /// Number of bytes in the run.
+    pub size: u64,
+}
+
+fn read_sparce(core: &mut Core, addr: u64, size: u64) -> Result<BTreeMap<u64, u8>, IoError> {
+    match core.mode {
+        AddrMode::Phy => core.io.pread_sparce(addr, size),
+        AddrMode::Vir => core.io.vread_sparce(addr, size),
+    }
+}
+
+/// Compare *size* bytes starting at *addr1* with *size* bytes starting at *addr2* in the
+/// address space selected by [Core::mode] and return runs of bytes that differ.
+/// A byte that is mapped in only one of the ranges counts as different.
+pub fn diff(core: &mut Core, addr1: u64, addr2: u64, size: u64) -> Result<Vec<DiffRun>, IoError> {
+    let mut runs: Vec<DiffRun> = Vec::new();
+    let mut start = 0;
+    while start < size {
+        let len = cmp::min(CHUNK_SIZE, size - start);
+        let data1 = read_sparce(core, addr1 + start, len)?;
+        let data2 = read_sparce(core, addr2 + start, len)?;
+        for i in start..start + len {
+            if data1.get(&(addr1 + i)) == data2.get(&(addr2 + i)) {
+                continue;
+            }
+            if let Some(run) = runs.last_mut() {
+                if run.addr1 + run.size == addr1 + i {
+                    run.size += 1;
+                    continue;
+                }
+            }
+            runs.push(DiffRun {
+                addr1: addr1 + i,
+                addr2: addr2 + i,
+                size: 1,
+            });
+        }
+        start += len;
+    }
+    Ok(runs)
+}
+
+#[derive(Default)]
+pub struct Diff {}
+
+impl Diff {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    fn summary(&self, core: &mut Core, addr: u64, size: u64) {
+        let loc = core.get_loc();
+        let runs = match diff(core, loc, addr, size) {
+            Ok(runs) => runs,
+            Err(e) => return error_msg(core, "Read Failed", &e.to_string()),
+        };
+        for run in runs {
+            writeln!(core.stdout, "0x{:08x} 0x{:08x} 0x{:x}", run.addr1, run.addr2, run.size).unwrap();
+        }
+    }
+    fn side_by_side(&self, core: &mut Core, addr: u64, size: u64) {
+        let loc = core.get_loc();
+        let data1 = read_sparce(core, loc, size);
+        let data2 = read_sparce(core, addr, size);
+        let (data1, data2) = match (data1, data2) {
+            (Ok(data1), Ok(data2)) => (data1, data2),
+            (Err(e), _) | (_, Err(e)) => return error_msg(core, "Read Failed", &e.to_string()),
+        };
+        let env = core.env.read();
+        let banner = env.get_color(env.get_str("printHex.headerColor").unwrap()).unwrap();
+        let changed = env.get_color(env.get_str("printHex.nonPrintColor").unwrap()).unwrap();
+        let gap = env.get_str("printHex.gapReplace").unwrap();
+        let header = "- offset -  0  1  2  3  4  5  6  7";
+        writeln!(core.stdout, "{}", Paint::rgb(banner.0, banner.1, banner.2, format!("{}  {}", header, header))).unwrap();
+        for i in (0..size).step_by(ROW_SIZE as usize) {
+            let mut columns = Vec::with_capacity(2);
+            for (base, data, other, other_base) in &[(loc, &data1, &data2, addr), (addr, &data2, &data1, loc)] {
+                let mut column = format!("{}", Paint::rgb(banner.0, banner.1, banner.2, format!("0x{:08x}", base + i)));
+                for j in i..cmp::min(i + ROW_SIZE, size) {
+                    let byte = data.get(&(base + j));
+                    let text = match byte {
+                        Some(b) => format!("{:02x}", b),
+                        None => format!("{}{}", gap, gap),
+                    };
+                    if byte != other.get(&(other_base + j)) {
+                        column += &format!(" {}", Paint::rgb(changed.0, changed.1, changed.2, text));
+                    } else {
+                        column += &format!(" {}", text);
+                    }
+                }
+                // keep the second column aligned when the last row is incomplete
+                if columns.is_empty() {
+                    for _ in size..i + ROW_SIZE {
+                        column += "   ";
+                    }
+                }
+                columns.push(column);
+            }
+            writeln!(core.stdout, "{}  {}", columns[0], columns[1]).unwrap();
+        }
+    }
+}
+
+impl Cmd for Diff {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        let summary = !args.is_empty() && args[0] == "summary";
+        let args = if summary { &args[1..] } else { args };
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        let addr = match eval(core, &args[0]) {
+            Ok(addr) => addr,
+            Err(e) => return error_msg(core, "Failed to parse address", &e.to_string()),
+        };
+        let size = match eval(core, &args[1]) {
+            Ok(size) => size,
+            Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+        };
+        if summary {
+            self.summary(core, addr, size);
+        } else {
+            self.side_by_side(core, addr, size);
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"diff",
+            &"",
+            vec![
+                ("[addr] [size]", "Show [size] bytes at current location and at [addr] side by side highlighting differences."),
+                ("summary [addr] [size]", "List runs of bytes that differ between current location and [addr]."),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_diff {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("diff");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [diff]\n\n\
+             Usage:\n\
+             diff [addr] [size]\tShow [size] bytes at current location and at [addr] side by side highlighting differences.\n\
+             diff summary [addr] [size]\tList runs of bytes that differ between current location and [addr].\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_diff_api() {
+        let mut core = Core::new_no_colors();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x0, b"hello world, bye").unwrap();
+        core.io.pwrite(0x20, b"hallo world. bye").unwrap();
+        core.io.map(0x0, 0x1000, 0x20).unwrap();
+        core.io.map(0x20, 0x2000, 0x8).unwrap();
+        assert_eq!(
+            diff(&mut core, 0x0, 0x20, 0x10).unwrap(),
+            vec![DiffRun { addr1: 0x1, addr2: 0x21, size: 1 }, DiffRun { addr1: 0xb, addr2: 0x2b, size: 1 }]
+        );
+        core.mode = AddrMode::Vir;
+        // bytes from 0x2008 are not mapped
+        assert_eq!(
+            diff(&mut core, 0x1000, 0x2000, 0x10).unwrap(),
+            vec![
+                DiffRun {
+                    addr1: 0x1001,
+                    addr2: 0x2001,
+                    size: 1
+                },
+                DiffRun {
+                    addr1: 0x1008,
+                    addr2: 0x2008,
+                    size: 8
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open_at("malloc://0x8", IoMode::READ | IoMode::WRITE, 0x20).unwrap();
+        core.io.pwrite(0x0, &[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99]).unwrap();
+        core.io.pwrite(0x20, &[0x00, 0x11, 0x21, 0x31, 0x44, 0x55, 0x66, 0x77]).unwrap();
+        core.run("diff", &["0x20".to_string(), "0xa".to_string()]);
+        core.run("diff", &["summary".to_string(), "0x20".to_string(), "0xa".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "- offset -  0  1  2  3  4  5  6  7  - offset -  0  1  2  3  4  5  6  7\n\
+             0x00000000 00 11 22 33 44 55 66 77  0x00000020 00 11 21 31 44 55 66 77\n\
+             0x00000008 88 99                    0x00000028 ## ##\n\
+             0x00000002 0x00000022 0x2\n\
+             0x00000008 0x00000028 0x2\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_diff_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("diff", &["0x20".to_string()]);
+        core.run("diff", &["summary".to_string(), "0x20".to_string()]);
+        core.run("diff", &["0x2g".to_string(), "0x20".to_string()]);
+        core.run("diff", &["summary".to_string(), "0x20".to_string(), "0x2g".to_string()]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected 2 argument(s), found 1.\n\
+             Arguments Error: Expected 2 argument(s), found 1.\n\
+             Error: Failed to parse address\n\
+             invalid digit found in string\n\
+             Error: Failed to parse size\n\
+             invalid digit found in string\n"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+/*
+ * entropy.rs: commands for computing entropy and byte statistics.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use rair_io::IoError;
+use std::cmp;
+use std::io::Write;
+use yansi::Paint;
+
+// Number of cells used to visualize entropy and histogram.
+const BAR_WIDTH: usize = 16;
+// Characters used to draw histogram buckets from empty to full.
+const HIST_LEVELS: &[u8] = b" .:-=+*#%@";
+
+/// Statistics of a single block computed by [entropy_blocks].
+pub struct BlockStats {
+    /// Address of the first byte in the block.
+    pub addr: u64,
+    /// Size of the block including unmapped bytes.
+    pub size: u64,
+    /// Number of occurrences of each byte value among mapped bytes.
+    pub histogram: [u64; 256],
+    /// List of (address, size) of unmapped gaps inside the block.
+    pub gaps: Vec<(u64, u64)>,
+}
+
+impl BlockStats {
+    fn new(addr: u64, size: u64) -> Self {
+        BlockStats {
+            addr,
+            size,
+            histogram: [0; 256],
+            gaps: Vec::new(),
+        }
+    }
+    /// Number of mapped bytes in the block.
+    pub fn mapped(&self) -> u64 {
+        self.histogram.iter().sum()
+    }
+    /// Shannon entropy of mapped bytes in bits per byte, ranges from 0 to 8.
+    pub fn entropy(&self) -> f64 {
+        let total = self.mapped() as f64;
+        if total == 0.0 {
+            return 0.0;
+        }
+        self.histogram
+            .iter()
+            .filter(|c| **c != 0)
+            .map(|c| {
+                let p = *c as f64 / total;
+                p * (1.0 / p).log2()
+            })
+            .sum()
+    }
+    /// Ratio of printable ASCII characters to mapped bytes.
+    pub fn printable_ratio(&self) -> f64 {
+        self.ratio(self.histogram[0x20..=0x7e].iter().sum())
+    }
+    /// Ratio of zero bytes to mapped bytes.
+    pub fn zero_ratio(&self) -> f64 {
+        self.ratio(self.histogram[0])
+    }
+    fn ratio(&self, count: u64) -> f64 {
+        let total = self.mapped();
+        if total == 0 {
+            0.0
+        } else {
+            count as f64 / total as f64
+        }
+    }
+}
+
+/// Split *size* bytes starting at *addr* in the address space selected by [Core::mode] into
+/// blocks of *block* bytes and compute [BlockStats] for each of them.
+pub fn entropy_blocks(core: &mut Core, addr: u64, size: u64, block: u64) -> Result<Vec<BlockStats>, IoError> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    while start < size {
+        let len = cmp::min(block, size - start);
+        let data = match core.mode {
+            AddrMode::Phy => core.io.pread_sparce(addr + start, len)?,
+            AddrMode::Vir => core.io.vread_sparce(addr + start, len)?,
+        };
+        let mut stats = BlockStats::new(addr + start, len);
+        let mut next = addr + start;
+        for (k, v) in data {
+            if k != next {
+                stats.gaps.push((next, k - next));
+            }
+            stats.histogram[v as usize] += 1;
+            next = k + 1;
+        }
+        if next != addr + start + len {
+            stats.gaps.push((next, addr + start + len - next));
+        }
+        blocks.push(stats);
+        start += len;
+    }
+    Ok(blocks)
+}
+
+// Compact histogram where each character summarizes 16 consecutive byte values.
+fn histogram_bar(histogram: &[u64; 256]) -> String {
+    let buckets: Vec<u64> = histogram.chunks(256 / BAR_WIDTH).map(|c| c.iter().sum()).collect();
+    let max = *buckets.iter().max().unwrap();
+    buckets
+        .iter()
+        .map(|b| {
+            let level = if *b == 0 { 0 } else { 1 + (*b * (HIST_LEVELS.len() as u64 - 2) / max) as usize };
+            HIST_LEVELS[level] as char
+        })
+        .collect()
+}
+
+pub struct Entropy {}
+
+impl Entropy {
+    pub fn new(core: &mut Core) -> Self {
+        let env = core.env.clone();
+        env.write()
+            .add_str_with_cb("entropy.lowColor", "color.9", "Color used for blocks with entropy less than 4 by `entropy` command", core, is_color)
+            .unwrap();
+        env.write()
+            .add_str_with_cb("entropy.midColor", "color.2", "Color used for blocks with entropy between 4 and 7 by `entropy` command", core, is_color)
+            .unwrap();
+        env.write()
+            .add_str_with_cb("entropy.highColor", "color.4", "Color used for blocks with entropy more than 7 by `entropy` command", core, is_color)
+            .unwrap();
+        env.write()
+            .add_str_with_cb("entropy.gapColor", "color.1", "Color used for unmapped gaps by `entropy` command", core, is_color)
+            .unwrap();
+        Entropy {}
+    }
+}
+
+impl Cmd for Entropy {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        let size = match eval(core, &args[0]) {
+            Ok(size) => size,
+            Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+        };
+        let block = match eval(core, &args[1]) {
+            Ok(0) => return error_msg(core, "Failed to parse block size", "Block size can't be zero."),
+            Ok(block) => block,
+            Err(e) => return error_msg(core, "Failed to parse block size", &e.to_string()),
+        };
+        let loc = core.get_loc();
+        let blocks = match entropy_blocks(core, loc, size, block) {
+            Ok(blocks) => blocks,
+            Err(e) => return error_msg(core, "Read Failed", &e.to_string()),
+        };
+        let env = core.env.read();
+        let low = env.get_color(env.get_str("entropy.lowColor").unwrap()).unwrap();
+        let mid = env.get_color(env.get_str("entropy.midColor").unwrap()).unwrap();
+        let high = env.get_color(env.get_str("entropy.highColor").unwrap()).unwrap();
+        let gap = env.get_color(env.get_str("entropy.gapColor").unwrap()).unwrap();
+        for stats in blocks {
+            if stats.mapped() != 0 {
+                let entropy = stats.entropy();
+                let (r, g, b) = if entropy < 4.0 {
+                    low
+                } else if entropy <= 7.0 {
+                    mid
+                } else {
+                    high
+                };
+                let filled = (entropy * BAR_WIDTH as f64 / 8.0).round() as usize;
+                writeln!(
+                    core.stdout,
+                    "0x{:08x} {:.3} [{}{}] printable: {:.2} zero: {:.2} histogram: [{}]",
+                    stats.addr,
+                    entropy,
+                    Paint::rgb(r, g, b, "#".repeat(filled)),
+                    ".".repeat(BAR_WIDTH - filled),
+                    stats.printable_ratio(),
+                    stats.zero_ratio(),
+                    histogram_bar(&stats.histogram)
+                )
+                .unwrap();
+            }
+            for (addr, size) in stats.gaps {
+                writeln!(core.stdout, "{}", Paint::rgb(gap.0, gap.1, gap.2, format!("0x{:08x} unmapped: 0x{:x} bytes", addr, size))).unwrap();
+            }
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"entropy",
+            &"",
+            vec![(
+                "[size] [block]",
+                "Split [size] bytes at current location into blocks and print entropy, printable ratio, zero ratio and histogram of each block.",
+            )],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_entropy {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("entropy");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [entropy]\n\n\
+             Usage:\n\
+             entropy [size] [block]\tSplit [size] bytes at current location into blocks and print entropy, printable ratio, zero ratio and histogram of each block.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_entropy_blocks() {
+        let mut core = Core::new_no_colors();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        let data: Vec<u8> = (0..=255).collect();
+        core.io.pwrite(0, &data).unwrap();
+        core.io.map(0x0, 0x1000, 0x80).unwrap();
+        core.io.map(0x80, 0x1100, 0x80).unwrap();
+        core.mode = AddrMode::Vir;
+        let blocks = entropy_blocks(&mut core, 0x1000, 0x180, 0x100).unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].mapped(), 0x80);
+        assert_eq!(blocks[0].entropy(), 7.0);
+        assert_eq!(blocks[0].zero_ratio(), 1.0 / 128.0);
+        assert_eq!(blocks[0].printable_ratio(), 95.0 / 128.0);
+        assert_eq!(blocks[0].gaps, vec![(0x1080, 0x80)]);
+        assert_eq!(blocks[1].entropy(), 7.0);
+        assert_eq!(blocks[1].printable_ratio(), 0.0);
+        assert_eq!(blocks[1].gaps, vec![]);
+    }
+
+    #[test]
+    fn test_entropy() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x40", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open_at("malloc://0x10", IoMode::READ | IoMode::WRITE, 0x50).unwrap();
+        core.io.pwrite(0x0, b"aaaaaaaaaaaaaaaaabababababababab").unwrap();
+        let data: Vec<u8> = (0..16).collect();
+        core.io.pwrite(0x50, &data).unwrap();
+        core.run("entropy", &["0x60".to_string(), "0x10".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x00000000 0.000 [................] printable: 1.00 zero: 0.00 histogram: [      @         ]\n\
+             0x00000010 1.000 [##..............] printable: 1.00 zero: 0.00 histogram: [      @         ]\n\
+             0x00000020 0.000 [................] printable: 0.00 zero: 1.00 histogram: [@               ]\n\
+             0x00000030 0.000 [................] printable: 0.00 zero: 1.00 histogram: [@               ]\n\
+             0x00000040 unmapped: 0x10 bytes\n\
+             0x00000050 4.000 [########........] printable: 0.00 zero: 0.06 histogram: [@               ]\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_entropy_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("entropy", &["0x10".to_string()]);
+        core.run("entropy", &["0x10".to_string(), "0".to_string()]);
+        core.run("entropy", &["0xz".to_string(), "1".to_string()]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected 2 argument(s), found 1.\n\
+             Error: Failed to parse block size\n\
+             Block size can't be zero.\n\
+             Error: Failed to parse size\n\
+             invalid digit found in string\n"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+/*
+ * analysis: commands for analysing contents of the address space.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+mod diff;
+mod entropy;
+
+use self::diff::*;
+pub use self::diff::{diff, DiffRun};
+use self::entropy::*;
+pub use self::entropy::{entropy_blocks, BlockStats};
+use crate::core::Core;
+use std::sync::Arc;
+
+pub fn register_analysis(core: &mut Core) {
+    let entropy = Arc::new(Entropy::new(core));
+    core.add_command("entropy", "", entropy);
+    core.add_command("diff", "", Arc::new(Diff::new()));
+}
@@ -18,33 +18,48 @@
 use crate::helper::*;
 use rtrees::bktree::SpellTree;
 use std::collections::BTreeMap; // for suffex search
+use std::ops::Bound;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct Commands {
     suggestions: SpellTree<()>,
-    search: BTreeMap<&'static str, MRc<dyn Cmd + Sync + Send>>,
+    search: BTreeMap<String, Arc<dyn Cmd + Sync + Send>>,
 }
 
 impl Commands {
     // Returns false if the command with the same name exists
-    pub fn add_command(&mut self, command_name: &'static str, functionality: MRc<dyn Cmd + Sync + Send>) -> bool {
+    pub fn add_command(&mut self, command_name: &str, functionality: Arc<dyn Cmd + Sync + Send>) -> bool {
         // first check that command_name doesn't exist
         if self.search.contains_key(command_name) {
             false
         } else {
-            self.suggestions.insert(command_name.to_string(), ());
-            self.search.insert(command_name, functionality);
+            // names of removed commands are never deleted from the suggestions tree.
+            if self.suggestions.find(&command_name.to_string(), 0).0.is_empty() {
+                self.suggestions.insert(command_name.to_string(), ());
+            }
+            self.search.insert(command_name.to_string(), functionality);
             true
         }
     }
 
-    pub fn find(&self, command: &str) -> Option<MRc<dyn Cmd + Sync + Send>> {
+    // Returns the removed command if it exists
+    pub fn remove_command(&mut self, command_name: &str) -> Option<Arc<dyn Cmd + Sync + Send>> {
+        self.search.remove(command_name)
+    }
+
+    pub fn find(&self, command: &str) -> Option<Arc<dyn Cmd + Sync + Send>> {
         self.search.get(command).cloned()
     }
     pub fn suggest(&self, command: &str, tolerance: u64) -> Vec<&String> {
-        self.suggestions.find(&command.to_string(), tolerance).1
+        let (_, similar) = self.suggestions.find(&command.to_string(), tolerance);
+        similar.into_iter().filter(|name| self.search.contains_key(name.as_str())).collect()
     }
-    pub fn prefix<'a>(&'a self, command: &'a str) -> Vec<&&str> {
-        self.search.range(command..).take_while(|(k, _)| k.starts_with(command)).map(|(k, _)| k).collect()
+    pub fn prefix<'a>(&'a self, command: &'a str) -> Vec<&'a String> {
+        self.search
+            .range::<str, _>((Bound::Included(command), Bound::Unbounded))
+            .take_while(|(k, _)| k.starts_with(command))
+            .map(|(k, _)| k)
+            .collect()
     }
 }
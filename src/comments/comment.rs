@@ -0,0 +1,229 @@
+/*
+ * comment.rs: Commands for attaching comments to address ranges.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::store::Comment;
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use std::io::Write;
+use yansi::Paint;
+
+fn comment_row(comment: &Comment, mode: AddrMode) -> String {
+    format!("0x{:x}\t0x{:x}\t{}\t{}", comment.addr, comment.size, mode, comment.text)
+}
+
+fn parse_error(core: &mut Core, title: &str, name: &str, err: &str) {
+    let name = Paint::default(name).bold();
+    let msg = format!("Failed to parse {}, {}.", name, err);
+    error_msg(core, title, &msg);
+}
+
+#[derive(Default)]
+pub struct AddComment {}
+
+impl AddComment {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+// Parses [addr] [size] arguments, reporting errors under *title*.
+fn parse_range(core: &mut Core, title: &str, args: &[String]) -> Option<(u64, u64)> {
+    let addr = match eval(core, &args[0]) {
+        Ok(addr) => addr,
+        Err(e) => {
+            parse_error(core, title, "addr", &e.to_string());
+            return None;
+        }
+    };
+    match eval(core, &args[1]) {
+        Ok(size) => Some((addr, size)),
+        Err(e) => {
+            parse_error(core, title, "size", &e.to_string());
+            None
+        }
+    }
+}
+
+impl Cmd for AddComment {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        let mode = core.mode;
+        if args.len() == 1 {
+            let addr = match eval(core, &args[0]) {
+                Ok(addr) => addr,
+                Err(e) => return parse_error(core, "Failed to find comment", "addr", &e.to_string()),
+            };
+            let rows: Vec<String> = core.comments.at(addr, mode).into_iter().map(|c| comment_row(c, mode)).collect();
+            for row in rows {
+                writeln!(core.stdout, "{}", row).unwrap();
+            }
+        } else if args.len() < 3 {
+            expect(core, args.len() as u64, 3);
+        } else if args[0] == "-" {
+            if args.len() != 3 {
+                expect(core, args.len() as u64, 3);
+                return;
+            }
+            if let Some((addr, size)) = parse_range(core, "Failed to remove comment", &args[1..]) {
+                if core.comments.remove(addr, size, mode).is_none() {
+                    let msg = format!("No comment is attached to 0x{:x} bytes at 0x{:x}.", size, addr);
+                    error_msg(core, "Failed to remove comment", &msg);
+                }
+            }
+        } else if let Some((addr, size)) = parse_range(core, "Failed to add comment", args) {
+            if let Err(e) = core.comments.set(addr, size, mode, &args[2..].join(" ")) {
+                error_msg(core, "Failed to add comment", &format!("{}.", e));
+            }
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"comment",
+            &"",
+            vec![
+                ("[addr]", "Print comments covering [addr] in the current address space."),
+                ("[addr] [size] [text]", "Attach [text] to [size] bytes at [addr], replacing the text of a comment on the same range."),
+                ("- [addr] [size]", "Remove the comment attached to [size] bytes at [addr]."),
+            ],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct ListComments {}
+
+impl ListComments {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for ListComments {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if !args.is_empty() {
+            expect(core, args.len() as u64, 0);
+            return;
+        }
+        let mut rows = Vec::new();
+        for mode in &[AddrMode::Phy, AddrMode::Vir] {
+            rows.extend(core.comments.iter(*mode).map(|c| comment_row(c, *mode)));
+        }
+        for row in rows {
+            writeln!(core.stdout, "{}", row).unwrap();
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(core, &"comments", &"", vec![("", "List all comments sorted by address space and address.")]);
+    }
+}
+
+#[cfg(test)]
+mod test_comment {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("comment");
+        core.help("comments");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [comment]\n\n\
+             Usage:\n\
+             comment [addr]\tPrint comments covering [addr] in the current address space.\n\
+             comment [addr] [size] [text]\tAttach [text] to [size] bytes at [addr], replacing the text of a comment on the same range.\n\
+             comment - [addr] [size]\tRemove the comment attached to [size] bytes at [addr].\n\
+             Command: [comments]\n\n\
+             Usage:\n\
+             comments\tList all comments sorted by address space and address.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("comment 0x10 0x20 file header; comment 0x14 4 'magic'; m vir; comment 0x14 4 entry; m phy");
+        core.run_line("comment 0x14 4 magic number; comment 0x15; comments; comment - 0x10 0x20; comment 0x15");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x10\t0x20\tPhy\tfile header\n\
+             0x14\t0x4\tPhy\tmagic number\n\
+             0x10\t0x20\tPhy\tfile header\n\
+             0x14\t0x4\tPhy\tmagic number\n\
+             0x14\t0x4\tVir\tentry\n\
+             0x14\t0x4\tPhy\tmagic number\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_comments_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("comment; comment 0x10 1; comment x; comment x 1 a; comment 0 y a; comment 0 0 a; comment -1 2 a");
+        core.run_line("comment - 0 1 a; comment - x 1; comment - 0 1; comments 0");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected 3 argument(s), found 0.\n\
+             Arguments Error: Expected 3 argument(s), found 2.\n\
+             Error: Failed to find comment\n\
+             Failed to parse addr, invalid digit found in string.\n\
+             Error: Failed to add comment\n\
+             Failed to parse addr, invalid digit found in string.\n\
+             Error: Failed to add comment\n\
+             Failed to parse size, invalid digit found in string.\n\
+             Error: Failed to add comment\n\
+             Cannot comment empty range.\n\
+             Error: Failed to add comment\n\
+             Comment range overflows address space.\n\
+             Arguments Error: Expected 3 argument(s), found 4.\n\
+             Error: Failed to remove comment\n\
+             Failed to parse addr, invalid digit found in string.\n\
+             Error: Failed to remove comment\n\
+             No comment is attached to 0x1 bytes at 0x0.\n\
+             Arguments Error: Expected 0 argument(s), found 1.\n"
+        );
+    }
+
+    #[test]
+    fn test_print_hex_comments() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x40", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("comment 0x8 0x20 buffer; comment 0x12 2 len; comment 0x14 1 flags; px 0x13 @ 0x10; px 0x20");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "- offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000010 0000 0000 0000 0000 0000 0000 0000 0000  ................  ; buffer ; len ; flags\n\
+             0x00000020 0000 00                                  ...\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000000 0000 0000 0000 0000 0000 0000 0000 0000  ................  ; buffer\n\
+             0x00000010 0000 0000 0000 0000 0000 0000 0000 0000  ................  ; len ; flags\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+}
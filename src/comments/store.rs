@@ -0,0 +1,146 @@
+/*
+ * store.rs: Data structure for holding address comments.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::helper::AddrMode;
+use rtrees::ist::IST;
+use serde::{Deserialize, Serialize};
+
+/// Text attached to an address range.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Comment {
+    pub addr: u64,
+    pub size: u64,
+    pub text: String,
+}
+
+impl Comment {
+    // Last address covered by the comment.
+    fn hi(&self) -> u64 {
+        self.addr + self.size - 1
+    }
+}
+
+/// Comments indexed by the address range they are attached to, at most one comment per range.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Comments {
+    phy: IST<u64, Comment>,
+    vir: IST<u64, Comment>,
+}
+
+impl Comments {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    fn tree(&self, mode: AddrMode) -> &IST<u64, Comment> {
+        match mode {
+            AddrMode::Phy => &self.phy,
+            AddrMode::Vir => &self.vir,
+        }
+    }
+    fn tree_mut(&mut self, mode: AddrMode) -> &mut IST<u64, Comment> {
+        match mode {
+            AddrMode::Phy => &mut self.phy,
+            AddrMode::Vir => &mut self.vir,
+        }
+    }
+    /// Attach *text* to *size* bytes starting at *addr* in address space *mode*, replacing the text of
+    /// the comment previously attached to the exact same range.
+    pub fn set(&mut self, addr: u64, size: u64, mode: AddrMode, text: &str) -> Result<(), String> {
+        if size == 0 {
+            return Err("Cannot comment empty range".to_string());
+        }
+        let hi = match addr.checked_add(size - 1) {
+            Some(hi) => hi,
+            None => return Err("Comment range overflows address space".to_string()),
+        };
+        let tree = self.tree_mut(mode);
+        if let Some(comment) = tree.envelop_mut(addr, hi).into_iter().find(|c| c.addr == addr && c.size == size) {
+            comment.text = text.to_string();
+            return Ok(());
+        }
+        let comment = Comment { addr, size, text: text.to_string() };
+        tree.insert(addr, hi, comment);
+        Ok(())
+    }
+    /// Remove the comment attached to *size* bytes starting at *addr* in address space *mode*.
+    pub fn remove(&mut self, addr: u64, size: u64, mode: AddrMode) -> Option<Comment> {
+        let hi = addr.checked_add(size.checked_sub(1)?)?;
+        let tree = self.tree_mut(mode);
+        // the tree can only delete every range enveloping [addr, hi], so the other ones are inserted back.
+        let mut removed = None;
+        for comment in tree.delete_envelop(addr, hi) {
+            if comment.addr == addr && comment.size == size {
+                removed = Some(comment);
+            } else {
+                tree.insert(comment.addr, comment.hi(), comment);
+            }
+        }
+        removed
+    }
+    /// Returns all comments covering *addr* in address space *mode*.
+    pub fn at(&self, addr: u64, mode: AddrMode) -> Vec<&Comment> {
+        self.tree(mode).at(addr)
+    }
+    /// Returns all comments having at least one address in the range *[lo, hi]* in address space *mode*.
+    pub fn overlap(&self, lo: u64, hi: u64, mode: AddrMode) -> Vec<&Comment> {
+        self.tree(mode).overlap(lo, hi)
+    }
+    /// Iterate over all comments in address space *mode* sorted by address.
+    pub fn iter(&self, mode: AddrMode) -> impl Iterator<Item = &Comment> {
+        self.tree(mode).into_iter().map(|(_, _, comment)| comment)
+    }
+}
+
+#[cfg(test)]
+mod test_comments_store {
+    use super::*;
+
+    fn texts(comments: Vec<&Comment>) -> Vec<&str> {
+        comments.into_iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut comments = Comments::new();
+        comments.set(0x10, 0x20, AddrMode::Phy, "header").unwrap();
+        comments.set(0x14, 4, AddrMode::Phy, "magic").unwrap();
+        comments.set(0x14, 4, AddrMode::Vir, "entry").unwrap();
+        assert_eq!(comments.set(0x14, 0, AddrMode::Vir, "x").err().unwrap(), "Cannot comment empty range");
+        assert_eq!(comments.set(u64::MAX, 2, AddrMode::Vir, "x").err().unwrap(), "Comment range overflows address space");
+        assert_eq!(texts(comments.at(0x15, AddrMode::Phy)), vec!["header", "magic"]);
+        assert_eq!(texts(comments.at(0x20, AddrMode::Phy)), vec!["header"]);
+        assert_eq!(texts(comments.at(0x15, AddrMode::Vir)), vec!["entry"]);
+        assert_eq!(texts(comments.overlap(0x0, 0x10, AddrMode::Phy)), vec!["header"]);
+        comments.set(0x14, 4, AddrMode::Phy, "MZ").unwrap();
+        assert_eq!(texts(comments.iter(AddrMode::Phy).collect()), vec!["header", "MZ"]);
+        assert_eq!(comments.remove(0x14, 2, AddrMode::Phy), None);
+        assert_eq!(comments.remove(0x14, 4, AddrMode::Phy).unwrap().text, "MZ");
+        assert_eq!(comments.remove(0x14, 4, AddrMode::Phy), None);
+        assert_eq!(texts(comments.at(0x15, AddrMode::Phy)), vec!["header"]);
+        assert_eq!(comments.remove(0x14, 0, AddrMode::Phy), None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut comments = Comments::new();
+        comments.set(0x10, 0x20, AddrMode::Phy, "header").unwrap();
+        comments.set(0x14, 4, AddrMode::Vir, "entry").unwrap();
+        let data = serde_cbor::to_vec(&comments).unwrap();
+        let comments: Comments = serde_cbor::from_slice(&data).unwrap();
+        assert_eq!(texts(comments.at(0x2f, AddrMode::Phy)), vec!["header"]);
+        assert_eq!(comments.at(0x14, AddrMode::Vir)[0].size, 4);
+    }
+}
@@ -15,16 +15,25 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+use crate::analysis::register_analysis;
 use crate::commands::Commands;
+use crate::comments::{register_comments, Comments};
+use crate::expr::eval;
+use crate::flags::{register_flags, Flags};
+use crate::hash::register_hash;
 use crate::helper::*;
 use crate::io::*;
+use crate::loader::{register_loader, Binary};
 use crate::loc::*;
+use crate::parser::*;
+use crate::search::register_search;
 use crate::utils::register_utils;
 use crate::writer::Writer;
 use parking_lot::{Mutex, RwLock};
 use rair_env::*;
 use rair_io::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::io;
 use std::io::Write;
 use std::mem;
@@ -35,6 +44,24 @@ pub struct Core {
     pub mode: AddrMode,
     pub io: RIO,
     loc: u64,
+    /// Executables loaded using one of the loaders.
+    #[serde(default)]
+    pub binaries: Vec<Binary>,
+    /// User defined macros, maps macro name to the command line it expands to.
+    #[serde(default)]
+    pub macros: BTreeMap<String, String>,
+    /// User defined aliases, maps alias name to the command line it runs.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    /// Named addresses, usable wherever an address is accepted.
+    #[serde(default)]
+    pub flags: Flags,
+    /// Comments attached to address ranges.
+    #[serde(default)]
+    pub comments: Comments,
+    /// Names of the flags marking hits of the last search, they are replaced by the next search.
+    #[serde(default)]
+    pub hits: Vec<String>,
     // Every time you add some new serde(skip) variable
     // make sure that this variable is well initialized
     // in the projects commands.
@@ -46,6 +73,12 @@ pub struct Core {
     commands: Arc<Mutex<Commands>>,
     #[serde(skip)]
     pub env: Arc<RwLock<Environment<Core>>>,
+    // Number of commands currently running, commands running other commands nest.
+    #[serde(skip)]
+    depth: u64,
+    // Number of errors reported so far, used to detect failing commands.
+    #[serde(skip)]
+    pub(crate) errors: u64,
 }
 
 impl Default for Core {
@@ -56,8 +89,16 @@ impl Default for Core {
             stderr: Writer::new_write(Box::new(io::stderr())),
             io: RIO::new(),
             loc: 0,
+            binaries: Vec::new(),
+            macros: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            flags: Flags::new(),
+            comments: Comments::new(),
+            hits: Vec::new(),
             commands: Default::default(),
             env: Default::default(),
+            depth: 0,
+            errors: 0,
         }
     }
 }
@@ -73,7 +114,13 @@ impl Core {
     pub(crate) fn load_commands(&mut self) {
         register_io(self);
         register_loc(self);
+        register_search(self);
         register_utils(self);
+        register_hash(self);
+        register_analysis(self);
+        register_loader(self);
+        register_flags(self);
+        register_comments(self);
     }
     /// Returns list of all available commands in [Core].
     pub fn commands(&mut self) -> Arc<Mutex<Commands>> {
@@ -99,6 +146,7 @@ impl Core {
     fn new_settings(color: bool) -> Self {
         let mut core: Core = Default::default();
         core.init_colors(color);
+        core.env.write().add_u64("core.recursionLimit", 64, "Maximum depth of commands running other commands").unwrap();
         core.load_commands();
         core
     }
@@ -116,7 +164,7 @@ impl Core {
         self.loc
     }
 
-    pub fn add_command(&mut self, long: &'static str, short: &'static str, funcs: MRc<dyn Cmd + Sync + Send>) {
+    pub fn add_command(&mut self, long: &str, short: &str, funcs: Arc<dyn Cmd + Sync + Send>) {
         if !long.is_empty() && !self.commands.lock().add_command(long, funcs.clone()) {
             let msg = format!("Command {} already existed.", Paint::default(long).bold());
             error_msg(self, "Cannot add this command.", &msg);
@@ -143,16 +191,26 @@ impl Core {
         }
     }
 
+    /// Run *command* with *args*. Commands are free to run other commands (including
+    /// themselves) through [Core::run], up to a nesting depth of `core.recursionLimit`.
     pub fn run(&mut self, command: &str, args: &[String]) {
-        let cmds = self.commands.clone();
-        let cmds_ref = cmds.lock();
-        let cmd = cmds_ref.find(&command.to_string());
-        if let Some(cmd) = cmd {
-            cmd.lock().run(self, args);
-        } else {
-            drop(cmds_ref);
-            self.command_not_found(command)
+        // The lock on the commands is released before running the command so that
+        // the command itself can look up and run other commands.
+        let cmd = self.commands.lock().find(command);
+        let cmd = match cmd {
+            Some(cmd) => cmd,
+            None => return self.command_not_found(command),
+        };
+        let limit = self.env.read().get_u64("core.recursionLimit").unwrap();
+        let depth = self.depth;
+        if depth >= limit {
+            let msg = format!("Command {} exceeded the recursion limit of {}.", Paint::default(command).bold(), limit);
+            return error_msg(self, "Execution failed", &msg);
         }
+        self.depth = depth + 1;
+        cmd.run(self, args);
+        // restored rather than decremented since commands such as `load` replace the whole core.
+        self.depth = depth;
     }
 
     pub fn run_at(&mut self, command: &str, args: &[String], at: u64) {
@@ -161,15 +219,76 @@ impl Core {
         self.loc = old_loc;
     }
 
+    /// Parse *line* using [parse_line] and run all of its commands in order.
+    pub fn run_line(&mut self, line: &str) {
+        let commands = match parse_line(line) {
+            Ok(commands) => commands,
+            Err(e) => return error_msg(self, "Failed to parse command", &format!("{}.", e)),
+        };
+        for command in &commands {
+            self.run_parsed(command);
+        }
+    }
+
+    /// Run single command returned by [parse_line], taking care of its temporary seek,
+    /// output filtering and redirection.
+    pub fn run_parsed(&mut self, command: &ParsedCommand) {
+        let at = match &command.at {
+            Some(at) => match eval(self, at) {
+                Ok(at) => Some(at),
+                Err(e) => return error_msg(self, "Failed to parse address", &e.to_string()),
+            },
+            None => None,
+        };
+        // output file is opened first so that the command doesn't run if redirection fails.
+        let file = match &command.output {
+            Output::Write(path) => Some(Writer::new_file(path, false)),
+            Output::Append(path) => Some(Writer::new_file(path, true)),
+            _ => None,
+        };
+        let mut file = match file {
+            Some(Err(e)) => return error_msg(self, "Failed to open file", &e.to_string()),
+            Some(Ok(file)) => Some(file),
+            None => None,
+        };
+        if command.grep.is_none() && command.output == Output::Stdout {
+            return self.run_maybe_at(command, at);
+        }
+        let stdout = mem::take(&mut self.stdout);
+        self.run_maybe_at(command, at);
+        let mut data = mem::replace(&mut self.stdout, stdout).bytes().unwrap();
+        if let Some(pattern) = &command.grep {
+            data = grep(&data, pattern);
+        }
+        match (&command.output, &mut file) {
+            (Output::Pipe(shell), _) => match pipe(shell, data) {
+                Ok((out, err)) => {
+                    self.stdout.write_all(&out).unwrap();
+                    self.stderr.write_all(&err).unwrap();
+                }
+                Err(e) => error_msg(self, "Failed to run shell command", &e.to_string()),
+            },
+            (_, Some(file)) => {
+                if let Err(e) = file.write_all(&data) {
+                    error_msg(self, "Failed to write to file", &e.to_string());
+                }
+            }
+            _ => self.stdout.write_all(&data).unwrap(),
+        }
+    }
+
+    fn run_maybe_at(&mut self, command: &ParsedCommand, at: Option<u64>) {
+        match at {
+            Some(at) => self.run_at(&command.name, &command.args, at),
+            None => self.run(&command.name, &command.args),
+        }
+    }
+
     pub fn help(&mut self, command: &str) {
-        let cmds = self.commands.clone();
-        let cmds_ref = cmds.lock();
-        let cmd = cmds_ref.find(&command.to_string());
-        if let Some(cmd) = cmd {
-            cmd.as_ref().lock().help(self);
-        } else {
-            drop(cmds_ref);
-            self.command_not_found(command);
+        let cmd = self.commands.lock().find(command);
+        match cmd {
+            Some(cmd) => cmd.help(self),
+            None => self.command_not_found(command),
         }
     }
 }
@@ -178,7 +297,7 @@ impl Core {
 mod test_core {
     use super::*;
     use crate::utils::Quit;
-    use parking_lot::Mutex;
+    use std::fs;
     use std::sync::Arc;
     #[test]
     fn test_loc() {
@@ -191,16 +310,16 @@ mod test_core {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        core.add_command("a_non_existing_command", "a", Arc::new(Mutex::new(Quit::new())));
+        core.add_command("a_non_existing_command", "a", Arc::new(Quit::new()));
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        core.add_command("test_command", "s", Arc::new(Mutex::new(Quit::new())));
+        core.add_command("test_command", "s", Arc::new(Quit::new()));
         assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Cannot add this command.\nCommand s already existed.\n");
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        core.add_command("seek", "test_stuff", Arc::new(Mutex::new(Quit::new())));
+        core.add_command("seek", "test_stuff", Arc::new(Quit::new()));
         assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Cannot add this command.\nCommand seek already existed.\n");
     }
     #[test]
@@ -224,4 +343,86 @@ mod test_core {
             "Error: Execution failed\nCommand mep is not found.\nSimilar command: map, maps, m, e, er, eh.\n"
         );
     }
+    #[test]
+    fn test_run_line() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("wx 4142 @ 0x10; s 0x20; maps; map 0x0 0x1000 0x10 r-x text; map 0x10 0x2000 0x10 rw- data");
+        core.run_line("maps ~data; maps ~rw- | tr a-z A-Z");
+        assert_eq!(core.get_loc(), 0x20);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x2000              0x10                0x10                rw-     data\n\
+             0X2000              0X10                0X10                RW-     DATA\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        let mut data = [0; 2];
+        core.io.pread(0x10, &mut data).unwrap();
+        assert_eq!(&data, b"AB");
+    }
+    #[test]
+    fn test_run_line_redirect() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("map 0x0 0x1000 0x10 r-x text; maps ~text > core_redirect_test");
+        core.run_line("map 0x10 0x2000 0x10 rw- data; maps ~data >> core_redirect_test");
+        assert_eq!(
+            fs::read_to_string("core_redirect_test").unwrap(),
+            "0x1000              0x0                 0x10                r-x     text\n\
+             0x2000              0x10                0x10                rw-     data\n"
+        );
+        fs::remove_file("core_redirect_test").unwrap();
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+    #[test]
+    fn test_run_line_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("maps 'abc");
+        core.run_line("s 0x10 @ ff; maps > core_missing_dir/file");
+        assert_eq!(core.get_loc(), 0);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to parse command\n\
+             Missing closing '.\n\
+             Error: Failed to parse address\n\
+             invalid digit found in string\n\
+             Error: Failed to open file\n\
+             No such file or directory (os error 2)\n"
+        );
+    }
+    struct Nest {}
+    impl Cmd for Nest {
+        fn run(&self, core: &mut Core, args: &[String]) {
+            writeln!(core.stdout, "{}", args.len()).unwrap();
+            let mut args = args.to_vec();
+            args.push(String::new());
+            core.run("nest", &args);
+        }
+        fn help(&self, _: &mut Core) {}
+    }
+    #[test]
+    fn test_run_recursive() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.add_command("nest", "", Arc::new(Nest {}));
+        core.env.write().set_u64("core.recursionLimit", 3, &mut Core::new_no_colors()).unwrap();
+        core.run("nest", &[]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "0\n1\n2\n");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Execution failed\nCommand nest exceeded the recursion limit of 3.\n");
+        // depth is back to 0 once the outer most command returns.
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("nest", &[]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "0\n1\n2\n");
+    }
 }
@@ -0,0 +1,300 @@
+/*
+ * expr.rs: Evaluating numeric expressions used as command arguments.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::core::*;
+use crate::helper::*;
+use std::fmt;
+use std::num;
+
+#[derive(Debug, PartialEq)]
+pub enum ExprError {
+    /// Malformed number literal, names that can't be resolved are reported as malformed numbers as well.
+    InvalidNumber(num::ParseIntError),
+    /// `$` variable that doesn't exist.
+    UnknownVariable(String),
+    /// `$` variable that has no value at the current location.
+    Unresolved(String),
+    /// Unexpected token or end of expression.
+    Syntax(String),
+    /// Named address that has no equivalent in the address space of the current mode.
+    Untranslated(String),
+    DivisionByZero,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::InvalidNumber(e) => write!(f, "{}", e),
+            ExprError::UnknownVariable(v) => write!(f, "unknown variable `{}`", v),
+            ExprError::Unresolved(v) => write!(f, "`{}` cannot be resolved at current location", v),
+            ExprError::Syntax(s) => write!(f, "{}", s),
+            ExprError::Untranslated(v) => write!(f, "`{}` cannot be translated to current address space", v),
+            ExprError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+// Binary operators ordered from lowest to highest precedence.
+const PRECEDENCE: [&[&str]; 6] = [&["|"], &["^"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+struct Parser<'a> {
+    core: &'a Core,
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_spaces(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+    // Consumes op if it is the next token.
+    fn eat(&mut self, op: &str) -> bool {
+        self.skip_spaces();
+        let end = self.pos + op.chars().count();
+        if end <= self.chars.len() && self.chars[self.pos..end].iter().copied().eq(op.chars()) {
+            self.pos = end;
+            return true;
+        }
+        false
+    }
+    fn unexpected(&self) -> ExprError {
+        match self.chars.get(self.pos) {
+            Some(c) => ExprError::Syntax(format!("unexpected `{}`", c)),
+            None => ExprError::Syntax("unexpected end of expression".to_string()),
+        }
+    }
+    fn binary(&mut self, level: usize) -> Result<u64, ExprError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for op in PRECEDENCE[level] {
+                if self.eat(op) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = match *op {
+                        "|" => lhs | rhs,
+                        "^" => lhs ^ rhs,
+                        "&" => lhs & rhs,
+                        "<<" => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                        ">>" => lhs.checked_shr(rhs as u32).unwrap_or(0),
+                        "+" => lhs.wrapping_add(rhs),
+                        "-" => lhs.wrapping_sub(rhs),
+                        "*" => lhs.wrapping_mul(rhs),
+                        "/" => lhs.checked_div(rhs).ok_or(ExprError::DivisionByZero)?,
+                        _ => lhs.checked_rem(rhs).ok_or(ExprError::DivisionByZero)?,
+                    };
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+    fn unary(&mut self) -> Result<u64, ExprError> {
+        if self.eat("-") {
+            return Ok(self.unary()?.wrapping_neg());
+        }
+        if self.eat("~") {
+            return Ok(!self.unary()?);
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        self.primary()
+    }
+    fn primary(&mut self) -> Result<u64, ExprError> {
+        if self.eat("(") {
+            let value = self.binary(0)?;
+            if !self.eat(")") {
+                return Err(self.unexpected());
+            }
+            return Ok(value);
+        }
+        if self.eat("$$") {
+            return Ok(self.core.get_loc());
+        }
+        if self.eat("$") {
+            let start = self.pos;
+            while self.pos < self.chars.len() && is_name_char(self.chars[self.pos]) {
+                self.pos += 1;
+            }
+            let name: String = self.chars[start..self.pos].iter().collect();
+            return variable(self.core, &format!("${}", name));
+        }
+        let start = self.pos;
+        while self.pos < self.chars.len() && is_name_char(self.chars[self.pos]) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.unexpected());
+        }
+        let token: String = self.chars[start..self.pos].iter().collect();
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            return str_to_num(&token).map_err(ExprError::InvalidNumber);
+        }
+        match named_address(self.core, &token)? {
+            Some(addr) => Ok(addr),
+            None => Err(ExprError::InvalidNumber(str_to_num(&token).unwrap_err())),
+        }
+    }
+}
+
+// Physical address of the current location.
+fn loc_paddr(core: &Core) -> Option<u64> {
+    match core.mode {
+        AddrMode::Phy => Some(core.get_loc()),
+        AddrMode::Vir => core.io.vir_to_phy(core.get_loc(), 1).map(|maps| maps[0].paddr),
+    }
+}
+
+fn variable(core: &Core, name: &str) -> Result<u64, ExprError> {
+    let value = match name {
+        "$s" => loc_paddr(core).and_then(|paddr| core.io.uri_iter().find(|desc| desc.has_paddr(paddr)).map(|desc| desc.size())),
+        "$p" => loc_paddr(core),
+        "$v" => match core.mode {
+            AddrMode::Vir => Some(core.get_loc()),
+            AddrMode::Phy => core.io.phy_to_vir(core.get_loc()).first().copied(),
+        },
+        _ => return Err(ExprError::UnknownVariable(name.to_string())),
+    };
+    value.ok_or_else(|| ExprError::Unresolved(name.to_string()))
+}
+
+// Address *name* refers to along with the address space it belongs to. Flags are looked up first,
+// then names of symbols, sections and memory maps in that order, the later are all virtual addresses.
+fn lookup_name(core: &Core, name: &str) -> Option<(u64, AddrMode)> {
+    if let Some(flag) = core.flags.get(name) {
+        return Some((flag.addr, flag.mode));
+    }
+    if let Some(symbol) = core.binaries.iter().find_map(|bin| bin.symbol(name)) {
+        return Some((symbol.vaddr, AddrMode::Vir));
+    }
+    if let Some(section) = core.binaries.iter().find_map(|bin| bin.section(name)) {
+        return Some((section.vaddr, AddrMode::Vir));
+    }
+    core.io.map_iter().filter(|map| map.name == name).map(|map| map.vaddr).min().map(|vaddr| (vaddr, AddrMode::Vir))
+}
+
+/// Resolve *name* to an address in the address space of [Core::mode]. Flags are looked up first, then names
+/// of symbols, sections and memory maps in that order. Returns *None* if there is no such name and an error
+/// if the address cannot be translated to the current address space.
+pub fn named_address(core: &Core, name: &str) -> Result<Option<u64>, ExprError> {
+    let (addr, mode) = match lookup_name(core, name) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let addr = match (mode, core.mode) {
+        (AddrMode::Phy, AddrMode::Vir) => core.io.phy_to_vir(addr).first().copied(),
+        (AddrMode::Vir, AddrMode::Phy) => core.io.vir_to_phy(addr, 1).map(|maps| maps[0].paddr),
+        _ => Some(addr),
+    };
+    addr.map(Some).ok_or_else(|| ExprError::Untranslated(name.to_string()))
+}
+
+/// Evaluate numeric expression *expr*. Expressions support number literals (see [str_to_num]),
+/// named addresses (see [named_address]), `$$` (current location), `$s` (size of the file under
+/// current location), `$p` / `$v` (physical / virtual address of current location), parentheses,
+/// unary `-` and `~` and the binary operators `* / % + - << >> & ^ |` with C precedence.
+/// Arithmetic wraps around on overflow.
+pub fn eval(core: &Core, expr: &str) -> Result<u64, ExprError> {
+    let mut parser = Parser {
+        core,
+        chars: expr.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.binary(0)?;
+    parser.skip_spaces();
+    if parser.pos != parser.chars.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test_expr {
+    use super::*;
+    use rair_io::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let core = Core::new_no_colors();
+        assert_eq!(eval(&core, "0x4000+0x1c*4").unwrap(), 0x4070);
+        assert_eq!(eval(&core, "(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval(&core, "1 << 4 | 1 & 3 ^ 2").unwrap(), 0x13);
+        assert_eq!(eval(&core, "0b1010 >> 1").unwrap(), 5);
+        assert_eq!(eval(&core, "17 % 5 - 010 / 4").unwrap(), 0);
+        assert_eq!(eval(&core, "~0").unwrap(), u64::MAX);
+        assert_eq!(eval(&core, "-1").unwrap(), u64::MAX);
+        assert_eq!(eval(&core, "0 - 2 + 3").unwrap(), 1);
+        assert_eq!(eval(&core, "1 << 64").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let core = Core::new_no_colors();
+        assert_eq!(eval(&core, "ff").err().unwrap().to_string(), "invalid digit found in string");
+        assert_eq!(eval(&core, "08").err().unwrap().to_string(), "invalid digit found in string");
+        assert_eq!(eval(&core, "(1 + 2").err().unwrap().to_string(), "unexpected end of expression");
+        assert_eq!(eval(&core, "1 + 2)").err().unwrap().to_string(), "unexpected `)`");
+        assert_eq!(eval(&core, "1 +* 2").err().unwrap().to_string(), "unexpected `*`");
+        assert_eq!(eval(&core, "").err().unwrap().to_string(), "unexpected end of expression");
+        assert_eq!(eval(&core, "1 / 0").err().unwrap(), ExprError::DivisionByZero);
+        assert_eq!(eval(&core, "$x").err().unwrap().to_string(), "unknown variable `$x`");
+        assert_eq!(eval(&core, "$s").err().unwrap().to_string(), "`$s` cannot be resolved at current location");
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut core = Core::new_no_colors();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x200", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io
+            .map_region(RIOMap {
+                paddr: 0x110,
+                vaddr: 0x5000,
+                size: 0x10,
+                name: "flash".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        core.set_loc(0x120);
+        assert_eq!(eval(&core, "$$ + 1").unwrap(), 0x121);
+        assert_eq!(eval(&core, "$s").unwrap(), 0x200);
+        assert_eq!(eval(&core, "$p").unwrap(), 0x120);
+        assert_eq!(eval(&core, "$v").err().unwrap(), ExprError::Unresolved("$v".to_string()));
+        core.set_loc(0x118);
+        assert_eq!(eval(&core, "$v").unwrap(), 0x5008);
+        core.mode = AddrMode::Vir;
+        core.set_loc(0x5004);
+        assert_eq!(eval(&core, "$p").unwrap(), 0x114);
+        assert_eq!(eval(&core, "$v").unwrap(), 0x5004);
+        assert_eq!(eval(&core, "$s").unwrap(), 0x200);
+        assert_eq!(eval(&core, "flash+4").unwrap(), 0x5004);
+        core.flags.add("entry", 0x114, 1, AddrMode::Phy).unwrap();
+        core.flags.add("lost", 0x10, 1, AddrMode::Phy).unwrap();
+        assert_eq!(eval(&core, "entry").unwrap(), 0x5004);
+        assert_eq!(eval(&core, "lost").err().unwrap().to_string(), "`lost` cannot be translated to current address space");
+        core.mode = AddrMode::Phy;
+        assert_eq!(eval(&core, "flash+4").unwrap(), 0x114);
+        assert_eq!(eval(&core, "entry").unwrap(), 0x114);
+    }
+}
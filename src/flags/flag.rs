@@ -0,0 +1,332 @@
+/*
+ * flag.rs: Commands for adding, removing and listing flags.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::store::Flag;
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use std::io::Write;
+use yansi::Paint;
+
+fn flag_row(flag: &Flag) -> String {
+    format!("0x{:x}\t0x{:x}\t{}\t{}", flag.addr, flag.size, flag.mode, flag.name)
+}
+
+fn flag_error(core: &mut Core, name: &str, err: &str) {
+    let name = Paint::default(name).bold();
+    let msg = format!("Failed to parse {}, {}.", name, err);
+    error_msg(core, "Failed to add flag", &msg);
+}
+
+#[derive(Default)]
+pub struct AddFlag {}
+
+impl AddFlag {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for AddFlag {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() || args.len() > 3 {
+            expect_range(core, args.len() as u64, 1, 3);
+        } else if args[0] == "-" {
+            if args.len() != 2 {
+                expect(core, args.len() as u64, 2);
+                return;
+            }
+            if core.flags.remove(&args[1]).is_none() {
+                error_msg(core, "Failed to remove flag", &format!("Flag `{}` does not exist.", args[1]));
+            }
+        } else if args.len() == 1 {
+            match core.flags.get(&args[0]).map(flag_row) {
+                Some(row) => writeln!(core.stdout, "{}", row).unwrap(),
+                None => error_msg(core, "Failed to find flag", &format!("Flag `{}` does not exist.", args[0])),
+            }
+        } else {
+            let addr = match eval(core, &args[1]) {
+                Ok(addr) => addr,
+                Err(e) => return flag_error(core, "addr", &e.to_string()),
+            };
+            let size = match args.get(2).map(|size| eval(core, size)) {
+                Some(Ok(size)) => size,
+                Some(Err(e)) => return flag_error(core, "size", &e.to_string()),
+                None => 1,
+            };
+            let mode = core.mode;
+            if let Err(e) = core.flags.add(&args[0], addr, size, mode) {
+                error_msg(core, "Failed to add flag", &format!("{}.", e));
+            }
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"flag",
+            &"f",
+            vec![
+                ("[name]", "Print address, size and address space of flag [name]."),
+                ("[name] [addr] <size>", "Name [addr] in the current address space and flagspace, size defaults to 1."),
+                ("- [name]", "Remove flag [name]."),
+            ],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct ListFlags {}
+
+impl ListFlags {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for ListFlags {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() > 1 {
+            expect_range(core, args.len() as u64, 0, 1);
+            return;
+        }
+        let rows: Vec<String> = core.flags.iter().filter(|flag| args.is_empty() || flag.space == args[0]).map(flag_row).collect();
+        for row in rows {
+            writeln!(core.stdout, "{}", row).unwrap();
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"flags",
+            &"",
+            vec![("", "List all flags sorted by name."), ("[flagspace]", "List flags in [flagspace] sorted by name.")],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct RenameFlag {}
+
+impl RenameFlag {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for RenameFlag {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        if let Err(e) = core.flags.rename(&args[0], &args[1]) {
+            error_msg(core, "Failed to rename flag", &format!("{}.", e));
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(core, &"flagRename", &"fr", vec![("[old] [new]", "Rename flag [old] to [new].")]);
+    }
+}
+
+#[derive(Default)]
+pub struct FlagSpace {}
+
+impl FlagSpace {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for FlagSpace {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() {
+            for space in core.flags.spaces() {
+                let marker = if space == core.flags.space() { '*' } else { ' ' };
+                writeln!(core.stdout, "{} {}", marker, space).unwrap();
+            }
+        } else if args.len() == 1 {
+            core.flags.set_space(&args[0]);
+        } else if args.len() == 2 && args[0] == "remove" {
+            if let Err(e) = core.flags.remove_space(&args[1]) {
+                error_msg(core, "Failed to remove flagspace", &format!("{}.", e));
+            }
+        } else {
+            expect_range(core, args.len() as u64, 0, 2);
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"flagSpace",
+            &"fs",
+            vec![
+                ("", "List flagspaces, the current flagspace is marked with `*`."),
+                ("[name]", "Add new flags to flagspace [name], the flagspace is created if it doesn't exist."),
+                ("remove [name]", "Remove flagspace [name] with all of its flags."),
+            ],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct ClosestFlag {}
+
+impl ClosestFlag {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for ClosestFlag {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() > 1 {
+            expect_range(core, args.len() as u64, 0, 1);
+            return;
+        }
+        let addr = match args.first().map(|addr| eval(core, addr)) {
+            Some(Ok(addr)) => addr,
+            Some(Err(e)) => return error_msg(core, "Failed to parse addr", &e.to_string()),
+            None => core.get_loc(),
+        };
+        let closest = core.flags.closest(addr, core.mode).map(|flag| (flag.name.clone(), addr - flag.addr));
+        match closest {
+            Some((name, 0)) => writeln!(core.stdout, "{}", name).unwrap(),
+            Some((name, offset)) => writeln!(core.stdout, "{} + 0x{:x}", name, offset).unwrap(),
+            None => error_msg(core, "Failed to find flag", &format!("No flag at or before 0x{:x}.", addr)),
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"flagClosest",
+            &"fd",
+            vec![("", "Print the closest flag at or before current location."), ("[addr]", "Print the closest flag at or before [addr].")],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_flag {
+    use super::*;
+    use crate::writer::Writer;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("flag");
+        core.help("flags");
+        core.help("fr");
+        core.help("fs");
+        core.help("fd");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Commands: [flag | f]\n\n\
+             Usage:\n\
+             f [name]\tPrint address, size and address space of flag [name].\n\
+             f [name] [addr] <size>\tName [addr] in the current address space and flagspace, size defaults to 1.\n\
+             f - [name]\tRemove flag [name].\n\
+             Command: [flags]\n\n\
+             Usage:\n\
+             flags\tList all flags sorted by name.\n\
+             flags [flagspace]\tList flags in [flagspace] sorted by name.\n\
+             Commands: [flagRename | fr]\n\n\
+             Usage:\n\
+             fr [old] [new]\tRename flag [old] to [new].\n\
+             Commands: [flagSpace | fs]\n\n\
+             Usage:\n\
+             fs\tList flagspaces, the current flagspace is marked with `*`.\n\
+             fs [name]\tAdd new flags to flagspace [name], the flagspace is created if it doesn't exist.\n\
+             fs remove [name]\tRemove flagspace [name] with all of its flags.\n\
+             Commands: [flagClosest | fd]\n\n\
+             Usage:\n\
+             fd\tPrint the closest flag at or before current location.\n\
+             fd [addr]\tPrint the closest flag at or before [addr].\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("f main 0x1000 0x20; s 0x1010; f here $$; fs strings; f str.hi main+0x40 3");
+        core.run_line("flags; flags strings; fs; f here; fd; fd 0x1040; fd 0x1100");
+        core.run_line("fr here there; f - main; fs default; fs remove strings; flags; s main; s there+1");
+        assert_eq!(core.get_loc(), 0x1011);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x1010\t0x1\tPhy\there\n\
+             0x1000\t0x20\tPhy\tmain\n\
+             0x1040\t0x3\tPhy\tstr.hi\n\
+             0x1040\t0x3\tPhy\tstr.hi\n\
+             \x20 default\n\
+             * strings\n\
+             0x1010\t0x1\tPhy\there\n\
+             here\n\
+             str.hi\n\
+             str.hi + 0xc0\n\
+             0x1010\t0x1\tPhy\tthere\n"
+        );
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Seek Error\n\
+             invalid digit found in string\n"
+        );
+    }
+
+    #[test]
+    fn test_flags_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("f; f a b c d; f 1a 0x10; f a 0x10; f a 0x20; f b x; f b 0x10 y; f c; f - c; f - a b");
+        core.run_line("fr a; fr c d; fd; fd 1 2; fs remove default; fs remove x; fs a b c; flags a b");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected between 1 and 3 arguments, found 0.\n\
+             Arguments Error: Expected between 1 and 3 arguments, found 4.\n\
+             Error: Failed to add flag\n\
+             Invalid flag name `1a`.\n\
+             Error: Failed to add flag\n\
+             Flag `a` already exists.\n\
+             Error: Failed to add flag\n\
+             Failed to parse addr, invalid digit found in string.\n\
+             Error: Failed to add flag\n\
+             Failed to parse size, invalid digit found in string.\n\
+             Error: Failed to find flag\n\
+             Flag `c` does not exist.\n\
+             Error: Failed to remove flag\n\
+             Flag `c` does not exist.\n\
+             Arguments Error: Expected 2 argument(s), found 3.\n\
+             Arguments Error: Expected 2 argument(s), found 1.\n\
+             Error: Failed to rename flag\n\
+             Flag `c` does not exist.\n\
+             Error: Failed to find flag\n\
+             No flag at or before 0x0.\n\
+             Arguments Error: Expected between 0 and 1 arguments, found 2.\n\
+             Error: Failed to remove flagspace\n\
+             Cannot remove the current flagspace.\n\
+             Error: Failed to remove flagspace\n\
+             Flagspace `x` does not exist.\n\
+             Arguments Error: Expected between 0 and 2 arguments, found 3.\n\
+             Arguments Error: Expected between 0 and 1 arguments, found 2.\n"
+        );
+    }
+}
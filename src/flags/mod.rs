@@ -0,0 +1,31 @@
+/*
+ * flags: Naming addresses.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+mod flag;
+mod store;
+
+use self::flag::*;
+pub use self::store::*;
+use crate::core::Core;
+use std::sync::Arc;
+
+pub fn register_flags(core: &mut Core) {
+    core.add_command("flag", "f", Arc::new(AddFlag::new()));
+    core.add_command("flags", "", Arc::new(ListFlags::new()));
+    core.add_command("flagRename", "fr", Arc::new(RenameFlag::new()));
+    core.add_command("flagSpace", "fs", Arc::new(FlagSpace::new()));
+    core.add_command("flagClosest", "fd", Arc::new(ClosestFlag::new()));
+}
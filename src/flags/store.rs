@@ -0,0 +1,281 @@
+/*
+ * store.rs: Data structure for holding flags.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::helper::AddrMode;
+use rtrees::rbtree::{Augment, RBTree};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Name given to an address.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Flag {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    /// Address space *addr* belongs to.
+    pub mode: AddrMode,
+    /// Flagspace the flag belongs to.
+    pub space: String,
+}
+
+#[derive(Copy, Clone)]
+struct NoAug;
+
+impl Augment<NoAug> for RBTree<u64, NoAug, Vec<String>> {}
+
+// Maps address to names of all flags at that address.
+type AddrTree = RBTree<u64, NoAug, Vec<String>>;
+
+/// Flags indexed by both name and address, grouped into flagspaces.
+pub struct Flags {
+    names: BTreeMap<String, Flag>,
+    phy: AddrTree,
+    vir: AddrTree,
+    spaces: BTreeSet<String>,
+    space: String,
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags::new()
+    }
+}
+
+/// Returns true if *name* can be used inside expressions.
+pub fn valid_flag_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with(|c: char| c.is_ascii_digit()) && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+}
+
+impl Flags {
+    pub fn new() -> Self {
+        let mut spaces = BTreeSet::new();
+        spaces.insert("default".to_string());
+        Flags {
+            names: BTreeMap::new(),
+            phy: RBTree::new(),
+            vir: RBTree::new(),
+            spaces,
+            space: "default".to_string(),
+        }
+    }
+    fn tree_mut(&mut self, mode: AddrMode) -> &mut AddrTree {
+        match mode {
+            AddrMode::Phy => &mut self.phy,
+            AddrMode::Vir => &mut self.vir,
+        }
+    }
+    /// Add new flag named *name* at *addr* in address space *mode* to the current flagspace.
+    pub fn add(&mut self, name: &str, addr: u64, size: u64, mode: AddrMode) -> Result<(), String> {
+        if !valid_flag_name(name) {
+            return Err(format!("Invalid flag name `{}`", name));
+        }
+        if self.names.contains_key(name) {
+            return Err(format!("Flag `{}` already exists", name));
+        }
+        let flag = Flag {
+            name: name.to_string(),
+            addr,
+            size,
+            mode,
+            space: self.space.clone(),
+        };
+        self.insert(flag);
+        Ok(())
+    }
+    fn insert(&mut self, flag: Flag) {
+        let tree = self.tree_mut(flag.mode);
+        match tree.search_mut(flag.addr) {
+            Some(names) => names.push(flag.name.clone()),
+            None => tree.insert(flag.addr, NoAug, vec![flag.name.clone()]),
+        }
+        self.spaces.insert(flag.space.clone());
+        self.names.insert(flag.name.clone(), flag);
+    }
+    /// Remove flag named *name*, returns the removed flag if it exists.
+    pub fn remove(&mut self, name: &str) -> Option<Flag> {
+        let flag = self.names.remove(name)?;
+        let tree = self.tree_mut(flag.mode);
+        let names = tree.search_mut(flag.addr).unwrap();
+        names.retain(|n| n != name);
+        if names.is_empty() {
+            tree.delete(flag.addr);
+        }
+        Some(flag)
+    }
+    /// Rename flag *old* to *new*.
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<(), String> {
+        if !valid_flag_name(new) {
+            return Err(format!("Invalid flag name `{}`", new));
+        }
+        if self.names.contains_key(new) {
+            return Err(format!("Flag `{}` already exists", new));
+        }
+        let mut flag = match self.remove(old) {
+            Some(flag) => flag,
+            None => return Err(format!("Flag `{}` does not exist", old)),
+        };
+        flag.name = new.to_string();
+        self.insert(flag);
+        Ok(())
+    }
+    pub fn get(&self, name: &str) -> Option<&Flag> {
+        self.names.get(name)
+    }
+    /// Iterate over all flags sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Flag> {
+        self.names.values()
+    }
+    /// Returns the flag with the highest address that is less than or equal to *addr* in the address
+    /// space *mode*. If many flags share that address the one with the smallest name is returned.
+    pub fn closest(&self, addr: u64, mode: AddrMode) -> Option<&Flag> {
+        let mut subtree = match mode {
+            AddrMode::Phy => &self.phy,
+            AddrMode::Vir => &self.vir,
+        };
+        let mut best = None;
+        while subtree.is_node() {
+            if subtree.key() <= addr {
+                best = Some(subtree.data_ref());
+                subtree = subtree.right_ref();
+            } else {
+                subtree = subtree.left_ref();
+            }
+        }
+        best.and_then(|names| names.iter().min()).map(|name| &self.names[name])
+    }
+    /// Name of the current flagspace, new flags are added to it.
+    pub fn space(&self) -> &str {
+        &self.space
+    }
+    /// Switch to flagspace *name*, the flagspace is created if it doesn't exist.
+    pub fn set_space(&mut self, name: &str) {
+        self.spaces.insert(name.to_string());
+        self.space = name.to_string();
+    }
+    /// List of all flagspaces sorted by name.
+    pub fn spaces(&self) -> impl Iterator<Item = &String> {
+        self.spaces.iter()
+    }
+    /// Remove flagspace *name* along with all of its flags.
+    pub fn remove_space(&mut self, name: &str) -> Result<(), String> {
+        if name == self.space {
+            return Err("Cannot remove the current flagspace".to_string());
+        }
+        if !self.spaces.remove(name) {
+            return Err(format!("Flagspace `{}` does not exist", name));
+        }
+        let names: Vec<String> = self.names.values().filter(|flag| flag.space == name).map(|flag| flag.name.clone()).collect();
+        for flag in names {
+            self.remove(&flag);
+        }
+        Ok(())
+    }
+}
+
+// Only the flags are stored, address trees are rebuilt when loading.
+#[derive(Serialize, Deserialize)]
+struct FlagsData {
+    flags: Vec<Flag>,
+    spaces: BTreeSet<String>,
+    space: String,
+}
+
+impl Serialize for Flags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = FlagsData {
+            flags: self.names.values().cloned().collect(),
+            spaces: self.spaces.clone(),
+            space: self.space.clone(),
+        };
+        data.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Flags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = FlagsData::deserialize(deserializer)?;
+        let mut flags = Flags::new();
+        for flag in data.flags {
+            flags.insert(flag);
+        }
+        flags.spaces.extend(data.spaces);
+        flags.set_space(&data.space);
+        Ok(flags)
+    }
+}
+
+#[cfg(test)]
+mod test_flags_store {
+    use super::*;
+
+    #[test]
+    fn test_flags() {
+        let mut flags = Flags::new();
+        flags.add("main", 0x1000, 0x20, AddrMode::Vir).unwrap();
+        flags.add("entry", 0x1000, 1, AddrMode::Vir).unwrap();
+        flags.add("header", 0x0, 0x40, AddrMode::Phy).unwrap();
+        flags.add("data", 0x2000, 0x100, AddrMode::Vir).unwrap();
+        assert_eq!(flags.add("main", 0x0, 1, AddrMode::Phy).err().unwrap(), "Flag `main` already exists");
+        assert_eq!(flags.add("1st", 0x0, 1, AddrMode::Phy).err().unwrap(), "Invalid flag name `1st`");
+        assert_eq!(flags.add("a b", 0x0, 1, AddrMode::Phy).err().unwrap(), "Invalid flag name `a b`");
+        assert_eq!(flags.closest(0x1fff, AddrMode::Vir).unwrap().name, "entry");
+        assert_eq!(flags.closest(0x2000, AddrMode::Vir).unwrap().name, "data");
+        assert_eq!(flags.closest(0x1fff, AddrMode::Phy).unwrap().name, "header");
+        assert_eq!(flags.closest(0xfff, AddrMode::Vir), None);
+        assert_eq!(flags.remove("entry").unwrap().size, 1);
+        assert_eq!(flags.remove("entry"), None);
+        assert_eq!(flags.closest(0x1fff, AddrMode::Vir).unwrap().name, "main");
+        flags.rename("main", "start").unwrap();
+        assert_eq!(flags.rename("main", "start2").err().unwrap(), "Flag `main` does not exist");
+        assert_eq!(flags.rename("start", "data").err().unwrap(), "Flag `data` already exists");
+        assert_eq!(flags.closest(0x1fff, AddrMode::Vir).unwrap().name, "start");
+        assert_eq!(flags.get("start").unwrap().addr, 0x1000);
+        let names: Vec<&str> = flags.iter().map(|flag| flag.name.as_str()).collect();
+        assert_eq!(names, vec!["data", "header", "start"]);
+    }
+
+    #[test]
+    fn test_flagspaces() {
+        let mut flags = Flags::new();
+        flags.add("a", 0x10, 1, AddrMode::Phy).unwrap();
+        flags.set_space("strings");
+        flags.add("str.hello", 0x20, 6, AddrMode::Phy).unwrap();
+        assert_eq!(flags.space(), "strings");
+        assert_eq!(flags.get("str.hello").unwrap().space, "strings");
+        let spaces: Vec<&String> = flags.spaces().collect();
+        assert_eq!(spaces, vec!["default", "strings"]);
+        assert_eq!(flags.remove_space("strings").err().unwrap(), "Cannot remove the current flagspace");
+        flags.set_space("default");
+        flags.remove_space("strings").unwrap();
+        assert_eq!(flags.remove_space("strings").err().unwrap(), "Flagspace `strings` does not exist");
+        assert_eq!(flags.get("str.hello"), None);
+        assert_eq!(flags.closest(0x30, AddrMode::Phy).unwrap().name, "a");
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut flags = Flags::new();
+        flags.add("a", 0x10, 1, AddrMode::Phy).unwrap();
+        flags.set_space("funcs");
+        flags.add("b", 0x20, 2, AddrMode::Vir).unwrap();
+        let data = serde_cbor::to_vec(&flags).unwrap();
+        let flags: Flags = serde_cbor::from_slice(&data).unwrap();
+        assert_eq!(flags.space(), "funcs");
+        assert_eq!(flags.closest(0x15, AddrMode::Phy).unwrap().name, "a");
+        assert_eq!(flags.closest(0x25, AddrMode::Vir).unwrap().name, "b");
+        assert_eq!(flags.get("b").unwrap().space, "funcs");
+    }
+}
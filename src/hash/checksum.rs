@@ -0,0 +1,443 @@
+/*
+ * checksum.rs: commands for computing and fixing non cryptographic checksums.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::read_chunks;
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use rair_io::IoError;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Parameters of a CRC algorithm as described by the Rocksoft model.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Crc {
+    /// Width of the CRC in bits, must be one of 8, 16, 32 or 64.
+    pub width: u8,
+    /// Generator polynomial without the top bit.
+    pub poly: u64,
+    /// Initial value of the CRC register.
+    pub init: u64,
+    /// Reflect both input bytes and the final CRC value.
+    pub reflect: bool,
+    /// Value xored with the final CRC value.
+    pub xorout: u64,
+}
+
+impl Crc {
+    pub fn new(width: u8, poly: u64, init: u64, reflect: bool, xorout: u64) -> Self {
+        Crc { width, poly, init, reflect, xorout }
+    }
+    fn mask(&self) -> u64 {
+        if self.width == 64 {
+            !0
+        } else {
+            (1 << self.width) - 1
+        }
+    }
+    fn update(&self, mut crc: u64, data: &[u8]) -> u64 {
+        let top = 1 << (self.width - 1);
+        for byte in data {
+            let byte = if self.reflect { byte.reverse_bits() } else { *byte };
+            crc ^= u64::from(byte) << (self.width - 8);
+            for _ in 0..8 {
+                crc = if crc & top != 0 { (crc << 1) ^ self.poly } else { crc << 1 };
+            }
+            crc &= self.mask();
+        }
+        crc
+    }
+    fn finalize(&self, crc: u64) -> u64 {
+        let crc = if self.reflect { crc.reverse_bits() >> (64 - self.width) } else { crc };
+        (crc ^ self.xorout) & self.mask()
+    }
+}
+
+/// Checksum algorithms supported by [checksum] and [checksum_fix].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ChecksumAlgorithm {
+    Crc(Crc),
+    Adler32,
+    Fletcher16,
+    /// Fletcher-32 over little endian 16-bit words, odd data is padded with zero.
+    Fletcher32,
+    /// Sum of all bytes truncated to 8 bits.
+    Sum8,
+    /// Sum of all bytes truncated to 16 bits.
+    Sum16,
+    /// Sum of all bytes truncated to 32 bits.
+    Sum32,
+}
+
+impl ChecksumAlgorithm {
+    /// Size of the checksum value in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc(crc) => crc.width as usize / 8,
+            ChecksumAlgorithm::Adler32 | ChecksumAlgorithm::Fletcher32 | ChecksumAlgorithm::Sum32 => 4,
+            ChecksumAlgorithm::Fletcher16 | ChecksumAlgorithm::Sum16 => 2,
+            ChecksumAlgorithm::Sum8 => 1,
+        }
+    }
+}
+
+fn parse_bool(s: &str) -> Result<bool, String> {
+    match s {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Expected `true` or `false`, found `{}`.", s)),
+    }
+}
+
+fn parse_num(s: &str) -> Result<u64, String> {
+    str_to_num(s).map_err(|e| format!("Failed to parse `{}`: {}.", s, e))
+}
+
+// Parses crc[width]:[poly]:[init]:[reflect]:[xorout].
+fn parse_crc(s: &str) -> Result<Crc, String> {
+    let params: Vec<&str> = s.split(':').collect();
+    if params.len() != 5 {
+        return Err("Custom CRC must be in the form crc[width]:[poly]:[init]:[reflect]:[xorout].".to_string());
+    }
+    let width = match &params[0][3..] {
+        "8" => 8,
+        "16" => 16,
+        "32" => 32,
+        "64" => 64,
+        w => return Err(format!("Unsupported CRC width `{}`.", w)),
+    };
+    let crc = Crc::new(width, parse_num(params[1])?, parse_num(params[2])?, parse_bool(params[3])?, parse_num(params[4])?);
+    let mask = crc.mask();
+    if crc.poly & !mask != 0 || crc.init & !mask != 0 || crc.xorout & !mask != 0 {
+        return Err(format!("CRC parameters don't fit in {} bits.", width));
+    }
+    Ok(crc)
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match &*s {
+            "crc8" => Ok(ChecksumAlgorithm::Crc(Crc::new(8, 0x07, 0, false, 0))),
+            "crc16" => Ok(ChecksumAlgorithm::Crc(Crc::new(16, 0x8005, 0, true, 0))),
+            "crc16-ccitt" => Ok(ChecksumAlgorithm::Crc(Crc::new(16, 0x1021, 0xffff, false, 0))),
+            "crc32" => Ok(ChecksumAlgorithm::Crc(Crc::new(32, 0x04c1_1db7, 0xffff_ffff, true, 0xffff_ffff))),
+            "crc64" => Ok(ChecksumAlgorithm::Crc(Crc::new(64, 0x42f0_e1eb_a9ea_3693, !0, true, !0))),
+            "adler32" => Ok(ChecksumAlgorithm::Adler32),
+            "fletcher16" => Ok(ChecksumAlgorithm::Fletcher16),
+            "fletcher32" => Ok(ChecksumAlgorithm::Fletcher32),
+            "sum8" => Ok(ChecksumAlgorithm::Sum8),
+            "sum16" => Ok(ChecksumAlgorithm::Sum16),
+            "sum32" => Ok(ChecksumAlgorithm::Sum32),
+            _ if s.starts_with("crc") && s.contains(':') => Ok(ChecksumAlgorithm::Crc(parse_crc(&s)?)),
+            _ => Err(format!("Unknown checksum algorithm `{}`.", s)),
+        }
+    }
+}
+
+/// Byte order used when writing checksum back using [checksum_fix].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl FromStr for Endian {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "le" => Ok(Endian::Little),
+            "be" => Ok(Endian::Big),
+            _ => Err(format!("Expected `le` or `be`, found `{}`.", s)),
+        }
+    }
+}
+
+/// Compute checksum of *size* bytes starting at *addr* in the address space selected by [Core::mode].
+pub fn checksum(core: &mut Core, algorithm: ChecksumAlgorithm, addr: u64, size: u64) -> Result<u64, IoError> {
+    let mut a: u64 = match algorithm {
+        ChecksumAlgorithm::Crc(crc) => crc.init,
+        ChecksumAlgorithm::Adler32 => 1,
+        _ => 0,
+    };
+    let mut b: u64 = 0;
+    // Fletcher-32 consumes 16-bit words which might be split between two chunks.
+    let mut pending: Option<u8> = None;
+    read_chunks(core, addr, size, |data| match algorithm {
+        ChecksumAlgorithm::Crc(crc) => a = crc.update(a, data),
+        ChecksumAlgorithm::Adler32 => {
+            for byte in data {
+                a = (a + u64::from(*byte)) % 65521;
+                b = (b + a) % 65521;
+            }
+        }
+        ChecksumAlgorithm::Fletcher16 => {
+            for byte in data {
+                a = (a + u64::from(*byte)) % 255;
+                b = (b + a) % 255;
+            }
+        }
+        ChecksumAlgorithm::Fletcher32 => {
+            for byte in data {
+                if let Some(low) = pending.take() {
+                    a = (a + (u64::from(*byte) << 8 | u64::from(low))) % 65535;
+                    b = (b + a) % 65535;
+                } else {
+                    pending = Some(*byte);
+                }
+            }
+        }
+        ChecksumAlgorithm::Sum8 | ChecksumAlgorithm::Sum16 | ChecksumAlgorithm::Sum32 => {
+            a = data.iter().fold(a, |sum, byte| sum.wrapping_add(u64::from(*byte)));
+        }
+    })?;
+    Ok(match algorithm {
+        ChecksumAlgorithm::Crc(crc) => crc.finalize(a),
+        ChecksumAlgorithm::Adler32 => b << 16 | a,
+        ChecksumAlgorithm::Fletcher16 => b << 8 | a,
+        ChecksumAlgorithm::Fletcher32 => {
+            if let Some(low) = pending {
+                a = (a + u64::from(low)) % 65535;
+                b = (b + a) % 65535;
+            }
+            b << 16 | a
+        }
+        ChecksumAlgorithm::Sum8 => a & 0xff,
+        ChecksumAlgorithm::Sum16 => a & 0xffff,
+        ChecksumAlgorithm::Sum32 => a & 0xffff_ffff,
+    })
+}
+
+/// Compute checksum of *size* bytes starting at *addr* and write it at *target* using *endian*
+/// byte order. The computed checksum is returned.
+pub fn checksum_fix(core: &mut Core, algorithm: ChecksumAlgorithm, addr: u64, size: u64, target: u64, endian: Endian) -> Result<u64, IoError> {
+    let value = checksum(core, algorithm, addr, size)?;
+    let width = algorithm.size();
+    let data = match endian {
+        Endian::Little => value.to_le_bytes()[..width].to_vec(),
+        Endian::Big => value.to_be_bytes()[8 - width..].to_vec(),
+    };
+    match core.mode {
+        AddrMode::Phy => core.io.pwrite(target, &data)?,
+        AddrMode::Vir => core.io.vwrite(target, &data)?,
+    }
+    Ok(value)
+}
+
+#[derive(Default)]
+pub struct Checksum {}
+
+impl Checksum {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    fn fix(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 5 {
+            expect(core, args.len() as u64, 5);
+            return;
+        }
+        let algorithm = match ChecksumAlgorithm::from_str(&args[1]) {
+            Ok(algorithm) => algorithm,
+            Err(e) => return error_msg(core, "Failed to parse algorithm", &e),
+        };
+        let size = match eval(core, &args[2]) {
+            Ok(size) => size,
+            Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+        };
+        let target = match eval(core, &args[3]) {
+            Ok(target) => target,
+            Err(e) => return error_msg(core, "Failed to parse address", &e.to_string()),
+        };
+        let endian = match Endian::from_str(&args[4]) {
+            Ok(endian) => endian,
+            Err(e) => return error_msg(core, "Failed to parse endianness", &e),
+        };
+        let loc = core.get_loc();
+        if let Err(e) = checksum_fix(core, algorithm, loc, size, target, endian) {
+            error_msg(core, "Checksum fix failed", &e.to_string());
+        }
+    }
+}
+
+impl Cmd for Checksum {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if !args.is_empty() && args[0] == "fix" {
+            return self.fix(core, args);
+        }
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        let algorithm = match ChecksumAlgorithm::from_str(&args[0]) {
+            Ok(algorithm) => algorithm,
+            Err(e) => return error_msg(core, "Failed to parse algorithm", &e),
+        };
+        let size = match eval(core, &args[1]) {
+            Ok(size) => size,
+            Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+        };
+        let loc = core.get_loc();
+        match checksum(core, algorithm, loc, size) {
+            Ok(value) => writeln!(core.stdout, "0x{:0width$x}", value, width = algorithm.size() * 2).unwrap(),
+            Err(e) => error_msg(core, "Read Failed", &e.to_string()),
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"checksum",
+            &"",
+            vec![
+                (
+                    "[algorithm] [size]",
+                    "Compute checksum of [size] bytes at current location, [algorithm] is one of crc8, crc16, crc16-ccitt, crc32, crc64, \
+                     adler32, fletcher16, fletcher32, sum8, sum16, sum32 or crc[width]:[poly]:[init]:[reflect]:[xorout].",
+                ),
+                (
+                    "fix [algorithm] [size] [addr] [le|be]",
+                    "Compute checksum of [size] bytes at current location and write it at [addr] using given endianness.",
+                ),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_checksum {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("checksum");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [checksum]\n\n\
+             Usage:\n\
+             checksum [algorithm] [size]\tCompute checksum of [size] bytes at current location, [algorithm] is one of crc8, crc16, crc16-ccitt, crc32, crc64, \
+             adler32, fletcher16, fletcher32, sum8, sum16, sum32 or crc[width]:[poly]:[init]:[reflect]:[xorout].\n\
+             checksum fix [algorithm] [size] [addr] [le|be]\tCompute checksum of [size] bytes at current location and write it at [addr] using given endianness.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x5", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x5", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x0, b"123456789").unwrap();
+        let algorithms = [
+            "crc8",
+            "crc16",
+            "crc16-ccitt",
+            "crc32",
+            "crc64",
+            "adler32",
+            "fletcher16",
+            "fletcher32",
+            "sum8",
+            "sum16",
+            "sum32",
+            "crc32:0x1edc6f41:0xffffffff:true:0xffffffff",
+        ];
+        for algorithm in &algorithms {
+            core.run("checksum", &[algorithm.to_string(), "9".to_string()]);
+        }
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0xf4\n\
+             0xbb3d\n\
+             0x29b1\n\
+             0xcbf43926\n\
+             0x995dc9bbdf1939fa\n\
+             0x091e01de\n\
+             0x1ede\n\
+             0xdf09d509\n\
+             0xdd\n\
+             0x01dd\n\
+             0x000001dd\n\
+             0xe3069283\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_checksum_fix() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x0, b"123456789").unwrap();
+        core.io.map(0x0, 0x1000, 0x20).unwrap();
+        core.mode = AddrMode::Vir;
+        core.set_loc(0x1000);
+        core.run("checksum", &["fix".to_string(), "crc32".to_string(), "9".to_string(), "0x1010".to_string(), "le".to_string()]);
+        core.run("checksum", &["fix".to_string(), "crc16".to_string(), "9".to_string(), "0x1014".to_string(), "be".to_string()]);
+        let mut data = [0; 6];
+        core.io.pread(0x10, &mut data).unwrap();
+        assert_eq!(data, [0x26, 0x39, 0xf4, 0xcb, 0xbb, 0x3d]);
+        let value = checksum_fix(&mut core, ChecksumAlgorithm::Sum8, 0x1000, 9, 0x1016, Endian::Big).unwrap();
+        assert_eq!(value, 0xdd);
+        assert_eq!(checksum(&mut core, ChecksumAlgorithm::Sum8, 0x1016, 1).unwrap(), 0xdd);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_checksum_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run("checksum", &["crc32".to_string()]);
+        core.run("checksum", &["crc33".to_string(), "1".to_string()]);
+        core.run("checksum", &["crc12:0x80f:0:false:0".to_string(), "1".to_string()]);
+        core.run("checksum", &["crc8:0x107:0:false:0".to_string(), "1".to_string()]);
+        core.run("checksum", &["crc8:0x7:0:maybe:0".to_string(), "1".to_string()]);
+        core.run("checksum", &["crc32".to_string(), "0x20".to_string()]);
+        core.run("checksum", &["fix".to_string(), "crc32".to_string(), "0x10".to_string()]);
+        core.run("checksum", &["fix".to_string(), "crc32".to_string(), "0x10".to_string(), "0x0".to_string(), "me".to_string()]);
+        core.run("checksum", &["fix".to_string(), "crc32".to_string(), "0x10".to_string(), "0x20".to_string(), "le".to_string()]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected 2 argument(s), found 1.\n\
+             Error: Failed to parse algorithm\n\
+             Unknown checksum algorithm `crc33`.\n\
+             Error: Failed to parse algorithm\n\
+             Unsupported CRC width `12`.\n\
+             Error: Failed to parse algorithm\n\
+             CRC parameters don't fit in 8 bits.\n\
+             Error: Failed to parse algorithm\n\
+             Expected `true` or `false`, found `maybe`.\n\
+             Error: Read Failed\n\
+             Cannot resolve address.\n\
+             Arguments Error: Expected 5 argument(s), found 3.\n\
+             Error: Failed to parse endianness\n\
+             Expected `le` or `be`, found `me`.\n\
+             Error: Checksum fix failed\n\
+             Cannot resolve address.\n"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+/*
+ * digests.rs: commands for computing cryptographic digests of address ranges.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::read_chunks;
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use blake2::{Blake2b, Blake2s};
+use digest::{Digest, DynDigest};
+use md5::Md5;
+use rair_io::IoError;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::cmp;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Cryptographic hash functions supported by [hash] and [hash_blocks].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+    Blake2b,
+    Blake2s,
+}
+
+impl HashAlgorithm {
+    fn hasher(self) -> Box<dyn DynDigest> {
+        match self {
+            HashAlgorithm::Md5 => Box::new(Md5::new()),
+            HashAlgorithm::Sha1 => Box::new(Sha1::new()),
+            HashAlgorithm::Sha256 => Box::new(Sha256::new()),
+            HashAlgorithm::Sha512 => Box::new(Sha512::new()),
+            HashAlgorithm::Blake2b => Box::new(Blake2b::new()),
+            HashAlgorithm::Blake2s => Box::new(Blake2s::new()),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "md5" => Ok(HashAlgorithm::Md5),
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "blake2b" => Ok(HashAlgorithm::Blake2b),
+            "blake2s" => Ok(HashAlgorithm::Blake2s),
+            _ => Err(format!("Unknown hash algorithm `{}`.", s)),
+        }
+    }
+}
+
+/// Compute digest of *size* bytes starting at *addr* in the address space selected by [Core::mode].
+pub fn hash(core: &mut Core, algorithm: HashAlgorithm, addr: u64, size: u64) -> Result<Vec<u8>, IoError> {
+    let mut hasher = algorithm.hasher();
+    read_chunks(core, addr, size, |data| hasher.update(data))?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Split *size* bytes starting at *addr* into blocks of *block* bytes each and compute digest of every
+/// block. Returns list of (block address, digest), the last block might be smaller than *block*.
+pub fn hash_blocks(core: &mut Core, algorithm: HashAlgorithm, addr: u64, size: u64, block: u64) -> Result<Vec<(u64, Vec<u8>)>, IoError> {
+    let mut digests = Vec::new();
+    let mut start = 0;
+    while start < size {
+        let len = cmp::min(block, size - start);
+        digests.push((addr + start, hash(core, algorithm, addr + start, len)?));
+        start += len;
+    }
+    Ok(digests)
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    let mut s = String::with_capacity(digest.len() * 2);
+    for b in digest {
+        write!(s, "{:02x}", b).unwrap();
+    }
+    s
+}
+
+#[derive(Default)]
+pub struct Hash {}
+
+impl Hash {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Hash {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 2 && args.len() != 3 {
+            expect_range(core, args.len() as u64, 2, 3);
+            return;
+        }
+        let algorithm = match HashAlgorithm::from_str(&args[0]) {
+            Ok(algorithm) => algorithm,
+            Err(e) => return error_msg(core, "Failed to parse algorithm", &e),
+        };
+        let size = match eval(core, &args[1]) {
+            Ok(size) => size,
+            Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+        };
+        let loc = core.get_loc();
+        if args.len() == 2 {
+            match hash(core, algorithm, loc, size) {
+                Ok(digest) => writeln!(core.stdout, "{}", to_hex(&digest)).unwrap(),
+                Err(e) => error_msg(core, "Read Failed", &e.to_string()),
+            }
+            return;
+        }
+        let block = match eval(core, &args[2]) {
+            Ok(0) => return error_msg(core, "Failed to parse block size", "Block size can't be zero."),
+            Ok(block) => block,
+            Err(e) => return error_msg(core, "Failed to parse block size", &e.to_string()),
+        };
+        match hash_blocks(core, algorithm, loc, size, block) {
+            Ok(digests) => {
+                for (addr, digest) in digests {
+                    writeln!(core.stdout, "0x{:08x} {}", addr, to_hex(&digest)).unwrap();
+                }
+            }
+            Err(e) => error_msg(core, "Read Failed", &e.to_string()),
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"hash",
+            &"",
+            vec![
+                (
+                    "[algorithm] [size]",
+                    "Compute digest of [size] bytes at current location, [algorithm] is one of md5, sha1, sha256, sha512, blake2b or blake2s.",
+                ),
+                ("[algorithm] [size] [block]", "Compute one digest for each [block] bytes."),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_hash {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("hash");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [hash]\n\n\
+             Usage:\n\
+             hash [algorithm] [size]\tCompute digest of [size] bytes at current location, [algorithm] is one of md5, sha1, sha256, sha512, blake2b or blake2s.\n\
+             hash [algorithm] [size] [block]\tCompute one digest for each [block] bytes.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_hash() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0xf, b"abc").unwrap();
+        core.set_loc(0xf);
+        for algorithm in &["md5", "sha1", "sha256", "sha512", "blake2b", "blake2s"] {
+            core.run("hash", &[algorithm.to_string(), "3".to_string()]);
+        }
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "900150983cd24fb0d6963f7d28e17f72\n\
+             a9993e364706816aba3e25717850c26c9cd0d89d\n\
+             ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n\
+             ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f\n\
+             ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923\n\
+             508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_hash_blocks() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x0, b"abcabcab").unwrap();
+        core.io.map(0x0, 0x1000, 0x10).unwrap();
+        core.mode = AddrMode::Vir;
+        core.set_loc(0x1000);
+        let digests = hash_blocks(&mut core, HashAlgorithm::Sha1, 0x1000, 0x6, 0x3).unwrap();
+        assert_eq!(digests.len(), 2);
+        assert_eq!(digests[0], (0x1000, hash(&mut core, HashAlgorithm::Sha1, 0x1003, 3).unwrap()));
+        core.run("hash", &["MD5".to_string(), "8".to_string(), "3".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x00001000 900150983cd24fb0d6963f7d28e17f72\n\
+             0x00001003 900150983cd24fb0d6963f7d28e17f72\n\
+             0x00001006 187ef4436122d1cc2f40dc2b92f0eba0\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_hash_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run("hash", &["md5".to_string()]);
+        core.run("hash", &["md4".to_string(), "3".to_string()]);
+        core.run("hash", &["md5".to_string(), "0x20".to_string()]);
+        core.run("hash", &["md5".to_string(), "0x10".to_string(), "0".to_string()]);
+        core.run("hash", &["md5".to_string(), "0x1x".to_string()]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected between 2 and 3 arguments, found 1.\n\
+             Error: Failed to parse algorithm\n\
+             Unknown hash algorithm `md4`.\n\
+             Error: Read Failed\n\
+             Cannot resolve address.\n\
+             Error: Failed to parse block size\n\
+             Block size can't be zero.\n\
+             Error: Failed to parse size\n\
+             invalid digit found in string\n"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+/*
+ * hash: commands for hashing and checksumming address ranges.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+mod checksum;
+mod digests;
+
+use self::checksum::*;
+pub use self::checksum::{checksum, checksum_fix, ChecksumAlgorithm, Crc, Endian};
+use self::digests::*;
+pub use self::digests::{hash, hash_blocks, HashAlgorithm};
+use crate::core::*;
+use crate::helper::AddrMode;
+use rair_io::IoError;
+use std::cmp;
+use std::sync::Arc;
+
+// Maximum number of bytes read from RIO at a time while hashing.
+const CHUNK_SIZE: u64 = 0x10000;
+
+// Reads [addr, addr + size) in chunks calling f on each of them.
+fn read_chunks<F: FnMut(&[u8])>(core: &mut Core, addr: u64, size: u64, mut f: F) -> Result<(), IoError> {
+    let mut data = vec![0; cmp::min(size, CHUNK_SIZE) as usize];
+    let mut start = 0;
+    while start < size {
+        let len = cmp::min(size - start, CHUNK_SIZE) as usize;
+        match core.mode {
+            AddrMode::Phy => core.io.pread(addr + start, &mut data[..len])?,
+            AddrMode::Vir => core.io.vread(addr + start, &mut data[..len])?,
+        };
+        f(&data[..len]);
+        start += len as u64;
+    }
+    Ok(())
+}
+
+pub fn register_hash(core: &mut Core) {
+    core.add_command("hash", "", Arc::new(Hash::new()));
+    core.add_command("checksum", "", Arc::new(Checksum::new()));
+}
@@ -44,6 +44,7 @@ pub fn str_to_num(n: &str) -> Result<u64, num::ParseIntError> {
 }
 
 pub fn expect(core: &mut Core, args_len: u64, expect: u64) {
+    core.errors += 1;
     let (r, g, b) = core.env.read().get_color("color.4").unwrap();
     let error = Paint::rgb(r, g, b, "Arguments Error").bold();
     let expected = Paint::rgb(r, g, b, format!("{}", expect));
@@ -52,6 +53,7 @@ pub fn expect(core: &mut Core, args_len: u64, expect: u64) {
 }
 
 pub fn expect_range(core: &mut Core, args_len: u64, min: u64, max: u64) {
+    core.errors += 1;
     assert!(min < max);
     let (r, g, b) = core.env.read().get_color("color.4").unwrap();
     let error = Paint::rgb(r, g, b, "Arguments Error").bold();
@@ -62,6 +64,7 @@ pub fn expect_range(core: &mut Core, args_len: u64, min: u64, max: u64) {
 }
 
 pub fn error_msg(core: &mut Core, title: &str, msg: &str) {
+    core.errors += 1;
     let (r, g, b) = core.env.read().get_color("color.4").unwrap();
     writeln!(core.stderr, "{}: {}", Paint::rgb(r, g, b, "Error").bold(), Paint::rgb(r, g, b, title)).unwrap();
     writeln!(core.stderr, "{}", msg).unwrap();
@@ -103,7 +106,7 @@ pub struct CmdFunctions {
 }
 
 pub trait Cmd {
-    fn run(&mut self, _: &mut Core, _: &[String]);
+    fn run(&self, _: &mut Core, _: &[String]);
     fn help(&self, _: &mut Core);
 }
 #[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
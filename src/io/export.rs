@@ -0,0 +1,301 @@
+/*
+ * export.rs: commands for exporting address space into files.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use rair_env::Environment;
+use rair_io::{write_ihex, write_srec, IoError};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+const PT_LOAD: u32 = 1;
+const EHDR_SIZE: u64 = 64;
+const PHDR_SIZE: u64 = 56;
+const FILL_CHUNK: u64 = 0x10000;
+
+fn byte(_: &str, value: u64, _: &Environment<Core>, _: &mut Core) -> bool {
+    value <= 0xff
+}
+
+// Splits sparce data into runs of contiguous bytes sorted by address.
+fn runs(data: &BTreeMap<u64, u8>) -> Vec<(u64, Vec<u8>)> {
+    let mut runs: Vec<(u64, Vec<u8>)> = Vec::new();
+    for (addr, byte) in data {
+        match runs.last_mut() {
+            Some((start, run)) if *start + run.len() as u64 == *addr => run.push(*byte),
+            _ => runs.push((*addr, vec![*byte])),
+        }
+    }
+    runs
+}
+
+// Little endian ELF64 executable with no sections and one RWX PT_LOAD segment per run.
+fn write_elf(file: &mut dyn Write, data: &BTreeMap<u64, u8>) -> Result<(), IoError> {
+    let runs = runs(data);
+    if runs.len() > 0xffff {
+        return Err(IoError::Custom("ELF file cannot have more than 0xffff segments".to_string()));
+    }
+    let mut out = Vec::new();
+    out.extend_from_slice(b"\x7fELF\x02\x01\x01");
+    out.resize(16, 0);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type: ET_EXEC
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_machine: EM_NONE
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    out.extend_from_slice(&EHDR_SIZE.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    out.extend_from_slice(&(runs.len() as u16).to_le_bytes());
+    out.extend_from_slice(&0x40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&[0; 4]); // e_shnum and e_shstrndx
+    let mut offset = EHDR_SIZE + PHDR_SIZE * runs.len() as u64;
+    for (addr, run) in &runs {
+        out.extend_from_slice(&PT_LOAD.to_le_bytes());
+        out.extend_from_slice(&7u32.to_le_bytes()); // p_flags: RWX
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&addr.to_le_bytes()); // p_vaddr
+        out.extend_from_slice(&addr.to_le_bytes()); // p_paddr
+        out.extend_from_slice(&(run.len() as u64).to_le_bytes()); // p_filesz
+        out.extend_from_slice(&(run.len() as u64).to_le_bytes()); // p_memsz
+        out.extend_from_slice(&1u64.to_le_bytes()); // p_align
+        offset += run.len() as u64;
+    }
+    for (_, run) in runs {
+        out.extend_from_slice(&run);
+    }
+    file.write_all(&out)?;
+    Ok(())
+}
+
+fn write_fill(file: &mut dyn Write, gap: &[u8], mut size: u64) -> Result<(), IoError> {
+    while size > 0 {
+        let n = cmp::min(size, gap.len() as u64);
+        file.write_all(&gap[..n as usize])?;
+        size -= n;
+    }
+    Ok(())
+}
+
+// Data from *loc* to *loc* + *size* where gaps are replaced by *fill*. Gaps are written in chunks
+// so that the output never has to fit in memory.
+fn write_raw(file: &mut dyn Write, data: &BTreeMap<u64, u8>, loc: u64, size: u64, fill: u8) -> Result<(), IoError> {
+    let gap = vec![fill; cmp::min(size, FILL_CHUNK) as usize];
+    let mut written = 0;
+    for (addr, run) in runs(data) {
+        write_fill(file, &gap, addr - loc - written)?;
+        file.write_all(&run)?;
+        written = addr - loc + run.len() as u64;
+    }
+    write_fill(file, &gap, size - written)
+}
+
+#[derive(Default)]
+pub struct Export {}
+
+impl Export {
+    pub fn new(core: &mut Core) -> Self {
+        let env = core.env.clone();
+        env.write()
+            .add_u64_with_cb("export.gapFill", 0, "Byte used to fill gaps when exporting raw binary using `export` command", core, byte)
+            .unwrap();
+        Default::default()
+    }
+}
+
+impl Cmd for Export {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 3 {
+            expect(core, args.len() as u64, 3);
+            return;
+        }
+        let format = args[0].as_str();
+        if !["ihex", "srec", "raw", "elf"].contains(&format) {
+            let msg = format!("Unknown format `{}`, expected ihex, srec, raw or elf.", format);
+            return error_msg(core, "Failed to export data", &msg);
+        }
+        let size = match eval(core, &args[1]) {
+            Ok(size) => size,
+            Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+        };
+        let loc = core.get_loc();
+        let data = match core.mode {
+            AddrMode::Phy => core.io.pread_sparce(loc, size),
+            AddrMode::Vir => core.io.vread_sparce(loc, size),
+        };
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => return error_msg(core, "Failed to read data", &e.to_string()),
+        };
+        // everything but raw output is generated first so that no file is created if it fails.
+        let mut out = Vec::new();
+        let result = match format {
+            "ihex" => write_ihex(&mut out, &data, None, None),
+            "srec" => write_srec(&mut out, &data, b"", 0),
+            "elf" => write_elf(&mut out, &data),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            return error_msg(core, "Failed to export data", &e.to_string());
+        }
+        let mut file = match File::create(&args[2]) {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => return error_msg(core, "Failed to open file", &format!("{}.", e)),
+        };
+        let fill = core.env.read().get_u64("export.gapFill").unwrap() as u8;
+        let result = match format {
+            "raw" => write_raw(&mut file, &data, loc, size, fill),
+            _ => file.write_all(&out).map_err(IoError::from),
+        };
+        if let Err(e) = result.and_then(|_| file.flush().map_err(IoError::from)) {
+            error_msg(core, "Failed to export data", &e.to_string());
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"export",
+            &"",
+            vec![
+                ("ihex [size] [path]", "Write [size] bytes at current location to [path] as Intel HEX, skipping gaps."),
+                ("srec [size] [path]", "Write [size] bytes at current location to [path] as Motorola S-records, skipping gaps."),
+                ("raw [size] [path]", "Write [size] bytes at current location to [path], filling gaps with `export.gapFill`."),
+                ("elf [size] [path]", "Write [size] bytes at current location to [path] as ELF with one segment per contiguous run."),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_export {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+    use std::fs;
+    use std::path::Path;
+    use test_file::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("export");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [export]\n\n\
+             Usage:\n\
+             export ihex [size] [path]\tWrite [size] bytes at current location to [path] as Intel HEX, skipping gaps.\n\
+             export srec [size] [path]\tWrite [size] bytes at current location to [path] as Motorola S-records, skipping gaps.\n\
+             export raw [size] [path]\tWrite [size] bytes at current location to [path], filling gaps with `export.gapFill`.\n\
+             export elf [size] [path]\tWrite [size] bytes at current location to [path] as ELF with one segment per contiguous run.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    fn test_export_cb(paths: &[&Path]) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("wx 0102030405060708 @ 0x4; map 0x0 0x1000 0x8; map 0x8 0x1010 0x8; m vir; s 0x1000; e export.gapFill = 0xff");
+        let (ihex, srec, raw, elf) = (paths[0].to_string_lossy(), paths[1].to_string_lossy(), paths[2].to_string_lossy(), paths[3].to_string_lossy());
+        core.run_line(&format!("export ihex 0x18 {}; export srec 0x18 {}; export raw 0x18 {}; export elf 0x18 {}", ihex, srec, raw, elf));
+        assert_eq!(fs::read_to_string(paths[0]).unwrap(), ":081000000000000001020304de\n:081010000506070800000000be\n:00000001ff\n");
+        assert_eq!(
+            fs::read_to_string(paths[1]).unwrap(),
+            "S0030000fc\nS10b10000000000001020304da\nS10b10100506070800000000ba\nS9030000fc\n"
+        );
+        let mut expected = vec![0, 0, 0, 0, 1, 2, 3, 4];
+        expected.extend_from_slice(&[0xff; 8]);
+        expected.extend_from_slice(&[5, 6, 7, 8, 0, 0, 0, 0]);
+        assert_eq!(fs::read(paths[2]).unwrap(), expected);
+        core.run_line("m phy; close 0");
+        core.run_line(&format!("o ihex://{}; o srec://{} 0x2000; o elf://{}; m vir; px 0x18 @ 0x1000", ihex, srec, elf));
+        core.run_line("m phy; px 0x18 @ 0x2000");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "- offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00001000 0000 0000 0102 0304 #### #### #### ####  ........########\n\
+             0x00001010 0506 0708 0000 0000                      ........\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00002000 0000 0000 0102 0304 0000 0000 0000 0000  ................\n\
+             0x00002010 0506 0708 0000 0000                      ........\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_export() {
+        operate_on_files(&test_export_cb, &[&[], &[], &[], &[]]);
+    }
+
+    fn test_export_raw_cb(path: &Path) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("wx 0102 @ 0xe; map 0x0 0x11000 0x10; m vir; s 0; e export.gapFill = 0xff");
+        core.run_line(&format!("export raw 0x20000 {}", path.to_string_lossy()));
+        let mut expected = vec![0xff; 0x20000];
+        expected[0x11000..0x11010].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2]);
+        assert!(fs::read(path).unwrap() == expected);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_export_raw() {
+        operate_on_file(&test_export_raw_cb, &[]);
+    }
+
+    #[test]
+    fn test_export_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("export ihex 0x10; export hex 0x10 out; export raw x out; export raw 0x10 /; e export.gapFill = 0x100");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        let err = core.stderr.utf8_string().unwrap();
+        assert!(err.starts_with(
+            "Arguments Error: Expected 3 argument(s), found 2.\n\
+             Error: Failed to export data\n\
+             Unknown format `hex`, expected ihex, srec, raw or elf.\n\
+             Error: Failed to parse size\n\
+             invalid digit found in string\n\
+             Error: Failed to open file\n"
+        ));
+        // what in between is different between Windows and *Nix
+        assert!(err.ends_with("Error: Failed to set variable.\nCall back failed.\n"));
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("map 0x0 0x100000000 0x10; m vir; s 0x100000000; export ihex 0x10 rair_export_never_created");
+        assert!(!Path::new("rair_export_never_created").exists());
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to export data\n\
+             Intel HEX addresses cannot exceed 0xffffffff.\n"
+        );
+    }
+}
@@ -16,7 +16,9 @@
  */
 
 use crate::core::*;
+use crate::expr::*;
 use crate::helper::*;
+use crate::loader::{load_uri, unload_binary};
 use rair_io::*;
 use std::io::Write;
 use yansi::Paint;
@@ -35,7 +37,7 @@ impl ListFiles {
 }
 
 impl Cmd for ListFiles {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if !args.is_empty() {
             expect(core, args.len() as u64, 0);
             return;
@@ -80,7 +82,7 @@ fn parse_perm(p: &str) -> Result<IoMode, String> {
     Ok(perm)
 }
 impl Cmd for OpenFile {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() > 3 || args.is_empty() {
             expect_range(core, args.len() as u64, 1, 2);
             return;
@@ -94,7 +96,7 @@ impl Cmd for OpenFile {
                 Ok(perm) => perm,
                 Err(e) => return error_msg(core, "Failed to parse permission", &e),
             };
-            addr = match str_to_num(&args[2]) {
+            addr = match eval(core, &args[2]) {
                 Ok(addr) => Some(addr),
                 Err(e) => {
                     let err_str = format!("{}", e);
@@ -103,7 +105,7 @@ impl Cmd for OpenFile {
                 }
             }
         } else if args.len() == 2 {
-            if let Ok(a) = str_to_num(&args[1]) {
+            if let Ok(a) = eval(core, &args[1]) {
                 addr = Some(a);
                 uri = &args[0];
             } else {
@@ -117,9 +119,12 @@ impl Cmd for OpenFile {
             uri = &args[0];
         }
 
-        let result = match addr {
-            Some(addr) => core.io.open_at(uri, perm, addr),
-            None => core.io.open(uri, perm),
+        let result = match load_uri(core, uri, perm, addr) {
+            Some(result) => result,
+            None => match addr {
+                Some(addr) => core.io.open_at(uri, perm, addr),
+                None => core.io.open(uri, perm),
+            },
         };
         if let Err(e) = result {
             let err_str = format!("{}", e);
@@ -131,7 +136,15 @@ impl Cmd for OpenFile {
             core,
             &"open",
             &"o",
-            vec![("<Perm> [URI] <Addr>", "Open given URI using given optional permission (default to readonly) at given optional address.")],
+            vec![
+                ("<Perm> [URI] <Addr>", "Open given URI using given optional permission (default to readonly) at given optional address."),
+                ("<Perm> elf://[path] <Addr>", "Open ELF file and map its loadable segments into the virtual address space."),
+                ("<Perm> pe://[path] <Addr>", "Open PE file and map its headers and sections at its image base."),
+                (
+                    "<Perm> ihex://[path]?lenient <Addr>",
+                    "Open Intel HEX or S-record (srec://) file tolerating bad checksums and byte counts, see `warnings`.",
+                ),
+            ],
         );
     }
 }
@@ -146,12 +159,14 @@ impl CloseFile {
 }
 
 impl Cmd for CloseFile {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        let keep = !args.is_empty() && args[0] == "keep";
+        let args = if keep { &args[1..] } else { args };
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
         }
-        let hndl = match str_to_num(&args[0]) {
+        let hndl = match eval(core, &args[0]) {
             Ok(hndl) => hndl,
             Err(e) => {
                 let err_str = format!("{}", e);
@@ -159,13 +174,208 @@ impl Cmd for CloseFile {
                 return;
             }
         };
-        if let Err(e) = core.io.close(hndl) {
-            let err_str = format!("{}", e);
-            error_msg(core, "Failed to close file", &err_str);
+        let result = if keep { core.io.close_keep_maps(hndl) } else { core.io.close(hndl) };
+        match result {
+            Ok(_) => unload_binary(core, hndl, keep),
+            Err(e) => {
+                let err_str = format!("{}", e);
+                error_msg(core, "Failed to close file", &err_str);
+            }
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"close",
+            &"",
+            vec![
+                ("[hndl]", "Close file with given hndl and unmap all memory backed by it."),
+                ("keep [hndl]", "Close file with given hndl keeping memory maps backed by it as dangling maps."),
+            ],
+        );
+    }
+}
+
+// Parses [hndl] argument, reporting errors the same way `close` does.
+fn parse_hndl(core: &mut Core, arg: &str) -> Option<u64> {
+    match eval(core, arg) {
+        Ok(hndl) => Some(hndl),
+        Err(e) => {
+            error_msg(core, "Invalid hndl", &e.to_string());
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CommitFile {}
+
+impl CommitFile {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for CommitFile {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() || args.len() > 2 {
+            expect_range(core, args.len() as u64, 1, 2);
+            return;
+        }
+        let hndl = match parse_hndl(core, &args[0]) {
+            Some(hndl) => hndl,
+            None => return,
+        };
+        let result = match args.get(1) {
+            Some(path) => core.io.commit_to(hndl, path),
+            None => core.io.commit(hndl),
+        };
+        if let Err(e) = result {
+            error_msg(core, "Failed to commit changes", &e.to_string());
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"commit",
+            &"",
+            vec![
+                ("[hndl]", "Write changes done to copy-on-write file with given hndl back to the file it was opened from."),
+                ("[hndl] [path]", "Write content of copy-on-write file with given hndl including its changes to [path]."),
+            ],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct DiscardFile {}
+
+impl DiscardFile {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for DiscardFile {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 1 {
+            expect(core, args.len() as u64, 1);
+            return;
+        }
+        let hndl = match parse_hndl(core, &args[0]) {
+            Some(hndl) => hndl,
+            None => return,
+        };
+        if let Err(e) = core.io.discard(hndl) {
+            error_msg(core, "Failed to discard changes", &e.to_string());
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"discard",
+            &"",
+            vec![("[hndl]", "Drop changes done to copy-on-write file with given hndl, reverting it to the content on disk.")],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct ListModified {}
+
+impl ListModified {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+fn modified_rows(file: &RIODesc) -> Vec<String> {
+    file.dirty()
+        .iter()
+        .map(|(lo, hi)| format!("{}\t0x{:x}\t0x{:x}\t{}", file.hndl(), lo, hi - lo + 1, file.name()))
+        .collect()
+}
+
+impl Cmd for ListModified {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() > 1 {
+            expect_range(core, args.len() as u64, 0, 1);
+            return;
+        }
+        let rows = if args.is_empty() {
+            core.io.uri_iter().flat_map(modified_rows).collect()
+        } else {
+            let hndl = match parse_hndl(core, &args[0]) {
+                Some(hndl) => hndl,
+                None => return,
+            };
+            match core.io.hndl_to_desc(hndl) {
+                Some(file) => modified_rows(file),
+                None => return error_msg(core, "Failed to list modified ranges", &IoError::HndlNotFoundError.to_string()),
+            }
+        };
+        for row in rows {
+            writeln!(core.stdout, "{}", row).unwrap();
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"modified",
+            &"",
+            vec![
+                ("", "List physical ranges modified since each file was opened as handle, physical address, size and URI."),
+                ("[hndl]", "List modified physical ranges of file with given hndl."),
+            ],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct ListWarnings {}
+
+impl ListWarnings {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+fn warning_rows(file: &RIODesc) -> Vec<String> {
+    file.warnings().iter().map(|warning| format!("{}\t{}", file.hndl(), warning)).collect()
+}
+
+impl Cmd for ListWarnings {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() > 1 {
+            expect_range(core, args.len() as u64, 0, 1);
+            return;
+        }
+        let rows = if args.is_empty() {
+            core.io.uri_iter().flat_map(warning_rows).collect()
+        } else {
+            let hndl = match parse_hndl(core, &args[0]) {
+                Some(hndl) => hndl,
+                None => return,
+            };
+            match core.io.hndl_to_desc(hndl) {
+                Some(file) => warning_rows(file),
+                None => return error_msg(core, "Failed to list warnings", &IoError::HndlNotFoundError.to_string()),
+            }
+        };
+        for row in rows {
+            writeln!(core.stdout, "{}", row).unwrap();
         }
     }
     fn help(&self, core: &mut Core) {
-        help(core, &"close", &"", vec![("[hndl]", "Close file with given hndl.")]);
+        help(
+            core,
+            &"warnings",
+            &"",
+            vec![
+                ("", "List problems tolerated while loading files opened in lenient mode as handle and warning."),
+                ("[hndl]", "List warnings of file with given hndl."),
+            ],
+        );
     }
 }
 
@@ -173,6 +383,8 @@ impl Cmd for CloseFile {
 mod test_files {
     use super::*;
     use crate::writer::Writer;
+    use std::path::Path;
+    use test_file::*;
     #[test]
     fn test_docs() {
         let mut core = Core::new_no_colors();
@@ -191,9 +403,13 @@ mod test_files {
              Commands: [open | o]\n\n\
              Usage:\n\
              o <Perm> [URI] <Addr>\tOpen given URI using given optional permission (default to readonly) at given optional address.\n\
+             o <Perm> elf://[path] <Addr>\tOpen ELF file and map its loadable segments into the virtual address space.\n\
+             o <Perm> pe://[path] <Addr>\tOpen PE file and map its headers and sections at its image base.\n\
+             o <Perm> ihex://[path]?lenient <Addr>\tOpen Intel HEX or S-record (srec://) file tolerating bad checksums and byte counts, see `warnings`.\n\
              Command: [close]\n\n\
              Usage:\n\
-             close [hndl]\tClose file with given hndl.\n"
+             close [hndl]\tClose file with given hndl and unmap all memory backed by it.\n\
+             close keep [hndl]\tClose file with given hndl keeping memory maps backed by it as dangling maps.\n"
         );
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
     }
@@ -203,8 +419,8 @@ mod test_files {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut open = OpenFile::new();
-        let mut close = CloseFile::new();
+        let open = OpenFile::new();
+        let close = CloseFile::new();
         open.run(&mut core, &["b64://../testing_binaries/rio/base64/no_padding.b64".to_string()]);
         open.run(&mut core, &["rw".to_string(), "malloc://0x50".to_string()]);
         open.run(&mut core, &["c".to_string(), "../testing_binaries/rio/base64/one_pad.b64".to_string(), "0x5000".to_string()]);
@@ -239,7 +455,7 @@ mod test_files {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut open = OpenFile::new();
+        let open = OpenFile::new();
         open.run(&mut core, &["z".to_string(), "malloc://0x50".to_string()]);
         open.run(&mut core, &["z".to_string(), "malloc://0x50".to_string(), "0x500".to_string()]);
         open.run(&mut core, &["rw".to_string(), "malloc://0x50".to_string(), "0b500".to_string()]);
@@ -261,8 +477,8 @@ mod test_files {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut open = OpenFile::new();
-        let mut close = CloseFile::new();
+        let open = OpenFile::new();
+        let close = CloseFile::new();
         open.run(&mut core, &[]);
         core.run("files", &["test".to_string()]);
         close.run(&mut core, &[]);
@@ -280,8 +496,8 @@ mod test_files {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut open = OpenFile::new();
-        let mut close = CloseFile::new();
+        let open = OpenFile::new();
+        let close = CloseFile::new();
         open.run(&mut core, &["file_that_doesnt_exist".to_string()]);
         close.run(&mut core, &["5".to_string()]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
@@ -290,4 +506,140 @@ mod test_files {
         // what in between is different between Windows and *Nix
         assert!(err.ends_with("Error: Failed to close file\nHandle Does not exist.\n"));
     }
+
+    fn test_commit_discard_cb(paths: &[&Path]) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open(&paths[0].to_string_lossy(), IoMode::COW).unwrap();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.help("commit");
+        core.help("discard");
+        let out = paths[1].to_string_lossy();
+        core.run_line(&format!("wx deadbeef; commit 0 {}; commit 0; wx 0000 @ 4; discard 0; px 8; modified 0", out));
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [commit]\n\n\
+             Usage:\n\
+             commit [hndl]\tWrite changes done to copy-on-write file with given hndl back to the file it was opened from.\n\
+             commit [hndl] [path]\tWrite content of copy-on-write file with given hndl including its changes to [path].\n\
+             Command: [discard]\n\n\
+             Usage:\n\
+             discard [hndl]\tDrop changes done to copy-on-write file with given hndl, reverting it to the content on disk.\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000000 dead beef 0305 080d                      ........\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        for path in paths {
+            let mut data = [0; 8];
+            let mut io = RIO::new();
+            io.open(&path.to_string_lossy(), IoMode::READ).unwrap();
+            io.pread(0, &mut data).unwrap();
+            assert_eq!(data, [0xde, 0xad, 0xbe, 0xef, 0x03, 0x05, 0x08, 0x0d]);
+        }
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("commit; commit 0 a b; commit x; commit 1; discard; discard x; discard 2");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected between 1 and 2 arguments, found 0.\n\
+             Arguments Error: Expected between 1 and 2 arguments, found 3.\n\
+             Error: Invalid hndl\n\
+             invalid digit found in string\n\
+             Error: Failed to commit changes\n\
+             File is not opened as Copy-On-Write.\n\
+             Arguments Error: Expected 1 argument(s), found 0.\n\
+             Error: Invalid hndl\n\
+             invalid digit found in string\n\
+             Error: Failed to discard changes\n\
+             Handle Does not exist.\n"
+        );
+    }
+
+    #[test]
+    fn test_commit_discard() {
+        operate_on_files(&test_commit_discard_cb, &[DATA, &[]]);
+    }
+
+    #[test]
+    fn test_modified() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.help("modified");
+        core.run_line("modified; map 0x1c 0x1000 8; wx 0102 @ 2; wx 03 @ 4; m vir; wx 04050607 @ 0x1002; m phy");
+        core.run_line("modified; modified 1; px 0x10 @ 0x18");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [modified]\n\n\
+             Usage:\n\
+             modified\tList physical ranges modified since each file was opened as handle, physical address, size and URI.\n\
+             modified [hndl]\tList modified physical ranges of file with given hndl.\n\
+             0\t0x2\t0x3\tmalloc://0x20\n\
+             0\t0x1e\t0x2\tmalloc://0x20\n\
+             1\t0x20\t0x2\tmalloc://0x20\n\
+             1\t0x20\t0x2\tmalloc://0x20\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000018 0000 0000 0000 0405 0607 0000 0000 0000  ................\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("modified 1 2; modified x; modified 5");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected between 0 and 1 arguments, found 2.\n\
+             Error: Invalid hndl\n\
+             invalid digit found in string\n\
+             Error: Failed to list modified ranges\n\
+             Handle Does not exist.\n"
+        );
+    }
+
+    fn test_warnings_cb(paths: &[&Path]) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        let (ihex, srec) = (paths[0].to_string_lossy(), paths[1].to_string_lossy());
+        core.help("warnings");
+        core.run_line(&format!("o ihex://{}; o ihex://{}?lenient; o srec://{}?lenient 0x100", ihex, ihex, srec));
+        core.run_line("warnings; warnings 0; px 3 @ 0x30");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [warnings]\n\n\
+             Usage:\n\
+             warnings\tList problems tolerated while loading files opened in lenient mode as handle and warning.\n\
+             warnings [hndl]\tList warnings of file with given hndl.\n\
+             0\tInvalid checksum at line 1: expected 0x1e, found 0x1f\n\
+             1\tInvalid byte count at line 1: expected 0x04, found 0x05\n\
+             0\tInvalid checksum at line 1: expected 0x1e, found 0x1f\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000030 0233 7a                                  .3z\n"
+        );
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to open file\n\
+             Invalid checksum at line 1: expected 0x1e, found 0x1f.\n"
+        );
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("warnings 1 2; warnings x; warnings 5");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected between 0 and 1 arguments, found 2.\n\
+             Error: Invalid hndl\n\
+             invalid digit found in string\n\
+             Error: Failed to list warnings\n\
+             Handle Does not exist.\n"
+        );
+    }
+
+    #[test]
+    fn test_warnings() {
+        operate_on_files(&test_warnings_cb, &[b":0300300002337A1F\n:00000001FF\n", b"S1050000AABB\nS1051000CCDD41\nS9030000FC\n"]);
+    }
 }
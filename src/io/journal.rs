@@ -0,0 +1,222 @@
+/*
+ * journal.rs: commands for undoing and redoing writes.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use rair_io::{IoError, RIO};
+use std::io::Write;
+
+// Runs *step* [count] times (1 if no count is given), stopping at the first step with nothing to do.
+fn repeat(core: &mut Core, args: &[String], title: &str, nothing: &str, step: fn(&mut RIO) -> Result<bool, IoError>) {
+    if args.len() > 1 {
+        expect_range(core, args.len() as u64, 0, 1);
+        return;
+    }
+    let count = match args.first().map(|count| eval(core, count)) {
+        Some(Ok(count)) => count,
+        Some(Err(e)) => return error_msg(core, "Failed to parse count", &e.to_string()),
+        None => 1,
+    };
+    for _ in 0..count {
+        match step(&mut core.io) {
+            Ok(true) => (),
+            Ok(false) => return error_msg(core, title, nothing),
+            Err(e) => return error_msg(core, title, &e.to_string()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Undo {}
+
+impl Undo {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Undo {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        repeat(core, args, "Failed to undo write", "Nothing to undo.", RIO::undo);
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"undo",
+            &"",
+            vec![("", "Restore data overwritten by the last write."), ("[count]", "Undo the last [count] writes.")],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct Redo {}
+
+impl Redo {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Redo {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        repeat(core, args, "Failed to redo write", "Nothing to redo.", RIO::redo);
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"redo",
+            &"",
+            vec![("", "Write again the last undone write."), ("[count]", "Redo the last [count] undone writes.")],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct ListJournal {}
+
+impl ListJournal {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for ListJournal {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if !args.is_empty() {
+            expect(core, args.len() as u64, 0);
+            return;
+        }
+        let journal = core.io.journal();
+        let done = journal.done().iter().map(|entry| (entry, ""));
+        let undone = journal.undone().iter().rev().map(|entry| (entry, "\t(undone)"));
+        let mut rows = Vec::new();
+        for (i, (entry, state)) in done.chain(undone).enumerate() {
+            for chunk in &entry.chunks {
+                rows.push(format!("{}\t0x{:x}\t0x{:x}{}", i, chunk.paddr, chunk.new.len(), state));
+            }
+        }
+        for row in rows {
+            writeln!(core.stdout, "{}", row).unwrap();
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"journal",
+            &"",
+            vec![("", "List writes oldest first as write number, physical address and size, followed by undone writes.")],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_journal {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("undo");
+        core.help("redo");
+        core.help("journal");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [undo]\n\n\
+             Usage:\n\
+             undo\tRestore data overwritten by the last write.\n\
+             undo [count]\tUndo the last [count] writes.\n\
+             Command: [redo]\n\n\
+             Usage:\n\
+             redo\tWrite again the last undone write.\n\
+             redo [count]\tRedo the last [count] undone writes.\n\
+             Command: [journal]\n\n\
+             Usage:\n\
+             journal\tList writes oldest first as write number, physical address and size, followed by undone writes.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("map 0x1e 0x1000 2; map 0x20 0x1002 2; wx 0102 @ 0x10; wx 0304 @ 0x12; m vir; wx aabbcc @ 0x1001; m phy");
+        core.run_line("journal; undo 2; journal; px 4 @ 0x10; redo; px 4 @ 0x10");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0\t0x10\t0x2\n\
+             1\t0x12\t0x2\n\
+             2\t0x1f\t0x1\n\
+             2\t0x20\t0x2\n\
+             0\t0x10\t0x2\n\
+             1\t0x12\t0x2\t(undone)\n\
+             2\t0x1f\t0x1\t(undone)\n\
+             2\t0x20\t0x2\t(undone)\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000010 0102 0000                                ....\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000010 0102 0304                                ....\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        let mut data = [0; 3];
+        core.io.pread(0x1f, &mut data).unwrap();
+        assert_eq!(data, [0; 3]);
+        core.run_line("redo; wx ff @ 0; redo");
+        core.io.pread(0x1f, &mut data).unwrap();
+        assert_eq!(data, [0xaa, 0xbb, 0xcc]);
+        core.run_line("undo 5");
+        core.io.pread(0x0, &mut data).unwrap();
+        assert_eq!(data, [0; 3]);
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to redo write\n\
+             Nothing to redo.\n\
+             Error: Failed to undo write\n\
+             Nothing to undo.\n"
+        );
+    }
+
+    #[test]
+    fn test_undo_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("undo 1 2; redo x; journal 1; wx 01; close 0; undo");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected between 0 and 1 arguments, found 2.\n\
+             Error: Failed to parse count\n\
+             invalid digit found in string\n\
+             Arguments Error: Expected 0 argument(s), found 1.\n\
+             Error: Failed to undo write\n\
+             Nothing to undo.\n"
+        );
+    }
+}
@@ -16,7 +16,9 @@
  */
 
 use crate::core::*;
+use crate::expr::*;
 use crate::helper::*;
+use rair_io::{MapPerm, RIOMap};
 use std::io::Write;
 use yansi::Paint;
 
@@ -39,33 +41,88 @@ impl Map {
     }
 }
 
+fn parse_map_perm(p: &str) -> Result<MapPerm, String> {
+    let mut perm = MapPerm::empty();
+    for c in p.to_lowercase().chars() {
+        match c {
+            'r' => perm |= MapPerm::READ,
+            'w' => perm |= MapPerm::WRITE,
+            'x' => perm |= MapPerm::EXEC,
+            '-' => (),
+            _ => return Err(format!("Unknown Permission: `{}`", c)),
+        }
+    }
+    Ok(perm)
+}
+
 impl Cmd for Map {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
-        if args.len() != 3 {
+    fn run(&self, core: &mut Core, mut args: &[String]) {
+        let mut priority = 0;
+        if !args.is_empty() && args[0] == "priority" {
+            if args.len() < 5 {
+                expect(core, args.len() as u64, 5);
+                return;
+            }
+            priority = match eval(core, &args[1]) {
+                Ok(p) => p,
+                Err(e) => return map_error(core, "priority", &e.to_string()),
+            };
+            args = &args[2..];
+        }
+        if args.len() < 3 {
             expect(core, args.len() as u64, 3);
             return;
         }
-        let phy = match str_to_num(&args[0]) {
+        let phy = match eval(core, &args[0]) {
             Ok(p) => p,
             Err(e) => return map_error(core, "phy", &e.to_string()),
         };
-        let vir = match str_to_num(&args[1]) {
+        let vir = match eval(core, &args[1]) {
             Ok(v) => v,
             Err(e) => return map_error(core, "vir", &e.to_string()),
         };
-        let size = match str_to_num(&args[2]) {
+        let size = match eval(core, &args[2]) {
             Ok(s) => s,
             Err(e) => return map_error(core, "size", &e.to_string()),
         };
+        let perm = match args.get(3).map(|p| parse_map_perm(p)) {
+            Some(Ok(p)) => p,
+            Some(Err(e)) => return map_error(core, "perm", &e),
+            None => MapPerm::all(),
+        };
         if size == 0 {
             return;
         }
-        if let Err(e) = core.io.map(phy, vir, size) {
+        let map = RIOMap {
+            paddr: phy,
+            vaddr: vir,
+            size,
+            perm,
+            name: args.get(4).cloned().unwrap_or_default(),
+            tags: args.iter().skip(5).cloned().collect(),
+            priority,
+        };
+        if let Err(e) = core.io.map_region(map) {
             error_msg(core, "Failed to map memory", &e.to_string());
         }
     }
     fn help(&self, core: &mut Core) {
-        help(core, &"map", &"", vec![("[phy] [vir] [size]", "Map region from physical address space to virtual address space.")]);
+        help(
+            core,
+            &"map",
+            &"",
+            vec![
+                ("[phy] [vir] [size]", "Map region from physical address space to virtual address space."),
+                (
+                    "[phy] [vir] [size] [perm] <name> <tags...>",
+                    "Map region with permissions (combination of r, w, x and -), optional name and tags.",
+                ),
+                (
+                    "priority [n] [phy] [vir] [size] <perm> <name> <tags...>",
+                    "Map region that can overlap maps of different priority, map with highest priority is used.",
+                ),
+            ],
+        );
     }
 }
 
@@ -79,47 +136,68 @@ impl UnMap {
 }
 
 impl Cmd for UnMap {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 2 {
             expect(core, args.len() as u64, 2);
             return;
         }
-        let vir = match str_to_num(&args[0]) {
+        let vir = match eval(core, &args[0]) {
             Ok(v) => v,
             Err(e) => return unmap_error(core, "vir", &e.to_string()),
         };
 
-        let size = match str_to_num(&args[1]) {
+        let size = match eval(core, &args[1]) {
             Ok(s) => s,
             Err(e) => return unmap_error(core, "size", &e.to_string()),
         };
         if size == 0 {
             return;
         }
+        let dangling = core.io.remove_dangling(vir, size);
         if let Err(e) = core.io.unmap(vir, size) {
-            error_msg(core, "Failed to unmap memory", &e.to_string());
+            if dangling.is_empty() {
+                error_msg(core, "Failed to unmap memory", &e.to_string());
+            }
         }
     }
     fn help(&self, core: &mut Core) {
-        help(core, &"unmap", &"um", vec![("[vir] [size]", "Unmap a previosly mapped memory region.")]);
+        help(
+            core,
+            &"unmap",
+            &"um",
+            vec![("[vir] [size]", "Unmap a previosly mapped memory region (or dangling maps overlapping it).")],
+        );
     }
 }
 
 #[derive(Default)]
 pub struct ListMap {}
 
+fn map_row(map: &RIOMap) -> String {
+    let (vaddr, paddr, size) = (format!("0x{:x}", map.vaddr), format!("0x{:x}", map.paddr), format!("0x{:x}", map.size));
+    let perm = map.perm.to_string();
+    let mut row = format!("{: <20}{: <20}{: <20}{: <8}{}", vaddr, paddr, size, perm, map.name);
+    if !map.tags.is_empty() {
+        row.push_str(&format!(" [{}]", map.tags.join(", ")));
+    }
+    row.trim_end().to_string()
+}
+
 impl ListMap {
     pub fn new(core: &mut Core) -> Self {
         let env = core.env.clone();
         env.write()
             .add_str_with_cb("maps.headerColor", "color.6", "Color used in the header of `maps` command", core, is_color)
             .unwrap();
+        env.write()
+            .add_str_with_cb("maps.danglingColor", "color.4", "Color used for maps whose backing file is closed in `maps` command", core, is_color)
+            .unwrap();
         Default::default()
     }
 }
 
 impl Cmd for ListMap {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if !args.is_empty() {
             expect(core, args.len() as u64, 0);
             return;
@@ -127,20 +205,80 @@ impl Cmd for ListMap {
         let env = core.env.read();
         let color = env.get_str("maps.headerColor").unwrap();
         let (r, g, b) = env.get_color(color).unwrap();
+        let dangling_color = env.get_color(env.get_str("maps.danglingColor").unwrap()).unwrap();
         writeln!(
             core.stdout,
-            "{: <20}{: <20}{}",
+            "{: <20}{: <20}{: <20}{: <8}{}",
             Paint::rgb(r, g, b, "Virtual Address"),
             Paint::rgb(r, g, b, "Physical Address"),
-            Paint::rgb(r, g, b, "Size")
+            Paint::rgb(r, g, b, "Size"),
+            Paint::rgb(r, g, b, "Perm"),
+            Paint::rgb(r, g, b, "Name")
         )
         .unwrap();
-        for map in core.io.map_iter() {
-            writeln!(core.stdout, "{: <20}{: <20}{}", format!("0x{:x}", map.vaddr), format!("0x{:x}", map.paddr), format!("0x{:x}", map.size)).unwrap();
+        for map in core.io.effective_maps() {
+            writeln!(core.stdout, "{}", map_row(&map)).unwrap();
+        }
+        let (r, g, b) = dangling_color;
+        for map in core.io.dangling_iter() {
+            let row = format!("{} (dangling)", map_row(map));
+            writeln!(core.stdout, "{}", Paint::rgb(r, g, b, row)).unwrap();
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"maps",
+            &"",
+            vec![(
+                "",
+                "List memory maps of the active bank as used for address translation, followed by dangling maps whose backing file is closed.",
+            )],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct Bank {}
+
+impl Bank {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Bank {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() {
+            let active = core.io.bank().to_string();
+            let banks: Vec<String> = core.io.banks().iter().map(|bank| bank.to_string()).collect();
+            for bank in banks {
+                let marker = if bank == active { '*' } else { ' ' };
+                writeln!(core.stdout, "{} {}", marker, bank).unwrap();
+            }
+        } else if args.len() == 1 {
+            core.io.switch_bank(&args[0]);
+        } else if args.len() == 2 && args[0] == "remove" {
+            if let Err(e) = core.io.remove_bank(&args[1]) {
+                error_msg(core, "Failed to remove bank", &e.to_string());
+            }
+        } else if args.len() == 2 {
+            error_msg(core, "Invalid subcommand", &format!("Expected `remove`, found `{}`.", args[0]));
+        } else {
+            expect_range(core, args.len() as u64, 0, 2);
         }
     }
     fn help(&self, core: &mut Core) {
-        help(core, &"maps", &"", vec![("", "List all memory maps.")]);
+        help(
+            core,
+            &"bank",
+            &"",
+            vec![
+                ("", "List map banks, the active bank is marked with `*`."),
+                ("[name]", "Switch virtual address space to map bank [name], the bank is created if it doesn't exist."),
+                ("remove [name]", "Remove inactive map bank [name] with all of its maps."),
+            ],
+        );
     }
 }
 #[cfg(test)]
@@ -159,7 +297,11 @@ mod test_mapping {
         map.help(&mut core);
         assert_eq!(
             core.stdout.utf8_string().unwrap(),
-            "Command: [map]\n\nUsage:\nmap [phy] [vir] [size]\tMap region from physical address space to virtual address space.\n"
+            "Command: [map]\n\n\
+             Usage:\n\
+             map [phy] [vir] [size]\tMap region from physical address space to virtual address space.\n\
+             map [phy] [vir] [size] [perm] <name> <tags...>\tMap region with permissions (combination of r, w, x and -), optional name and tags.\n\
+             map priority [n] [phy] [vir] [size] <perm> <name> <tags...>\tMap region that can overlap maps of different priority, map with highest priority is used.\n"
         );
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
     }
@@ -172,7 +314,7 @@ mod test_mapping {
         unmap.help(&mut core);
         assert_eq!(
             core.stdout.utf8_string().unwrap(),
-            "Commands: [unmap | um]\n\nUsage:\num [vir] [size]\tUnmap a previosly mapped memory region.\n"
+            "Commands: [unmap | um]\n\nUsage:\num [vir] [size]\tUnmap a previosly mapped memory region (or dangling maps overlapping it).\n"
         );
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
     }
@@ -182,15 +324,20 @@ mod test_mapping {
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.help("maps");
-        assert_eq!(core.stdout.utf8_string().unwrap(), "Command: [maps]\n\nUsage:\nmaps\tList all memory maps.\n");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [maps]\n\n\
+             Usage:\n\
+             maps\tList memory maps of the active bank as used for address translation, followed by dangling maps whose backing file is closed.\n"
+        );
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
     }
     fn test_map_cb(path: &Path) {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut map = Map::new();
-        let mut unmap = UnMap::new();
+        let map = Map::new();
+        let unmap = UnMap::new();
         core.io.open(&path.to_string_lossy(), IoMode::READ).unwrap();
         map.run(&mut core, &["0x0".to_string(), "0x500".to_string(), "0x20".to_string()]);
         map.run(&mut core, &["0x10".to_string(), "0x520".to_string(), "0x20".to_string()]);
@@ -203,10 +350,10 @@ mod test_mapping {
         core.run("maps", &[]);
         assert_eq!(
             core.stdout.utf8_string().unwrap(),
-            "Virtual Address     Physical Address    Size\n\
-             0x500               0x0                 0x20\n\
-             0x520               0x10                0x20\n\
-             0x540               0x20                0x20\n"
+            "Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x500               0x0                 0x20                rwx\n\
+             0x520               0x10                0x20                rwx\n\
+             0x540               0x20                0x20                rwx\n"
         );
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
         core.stderr = Writer::new_buf();
@@ -221,10 +368,10 @@ mod test_mapping {
         core.run("maps", &[]);
         assert_eq!(
             core.stdout.utf8_string().unwrap(),
-            "Virtual Address     Physical Address    Size\n\
-             0x500               0x0                 0x10\n\
-             0x515               0x15                0xb\n\
-             0x540               0x20                0x20\n"
+            "Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x500               0x0                 0x10                rwx\n\
+             0x515               0x15                0xb                 rwx\n\
+             0x540               0x20                0x20                rwx\n"
         );
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
     }
@@ -233,12 +380,162 @@ mod test_mapping {
         operate_on_file(&test_map_cb, DATA);
     }
     #[test]
+    fn test_map_perm() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run(
+            "map",
+            &[
+                "0x0".to_string(),
+                "0x500".to_string(),
+                "0x20".to_string(),
+                "r-x".to_string(),
+                "text".to_string(),
+                "code".to_string(),
+                "entry".to_string(),
+            ],
+        );
+        core.run("map", &["0x20".to_string(), "0x520".to_string(), "0x20".to_string(), "rw".to_string(), "data".to_string()]);
+        core.run("map", &["0x40".to_string(), "0x540".to_string(), "0x20".to_string(), "rq".to_string()]);
+        core.run("maps", &[]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x500               0x0                 0x20                r-x     text [code, entry]\n\
+             0x520               0x20                0x20                rw-     data\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Failed to map memory\nFailed to parse perm, Unknown Permission: `q`.\n");
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.mode = AddrMode::Vir;
+        core.set_loc(0x500);
+        core.run("wx", &["ff".to_string()]);
+        assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Read Failed\nMap Not Writable\n");
+    }
+    #[test]
+    fn test_bank_docs() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("bank");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [bank]\n\n\
+             Usage:\n\
+             bank\tList map banks, the active bank is marked with `*`.\n\
+             bank [name]\tSwitch virtual address space to map bank [name], the bank is created if it doesn't exist.\n\
+             bank remove [name]\tRemove inactive map bank [name] with all of its maps.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+    #[test]
+    fn test_map_priority() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x80, &[0xff; 0x10]).unwrap();
+        core.run("map", &["0x0".to_string(), "0x500".to_string(), "0x40".to_string(), "rwx".to_string(), "flash".to_string()]);
+        core.run(
+            "map",
+            &[
+                "priority".to_string(),
+                "1".to_string(),
+                "0x80".to_string(),
+                "0x510".to_string(),
+                "0x10".to_string(),
+                "r--".to_string(),
+                "patch".to_string(),
+            ],
+        );
+        core.run("map", &["0x40".to_string(), "0x530".to_string(), "0x10".to_string()]);
+        core.run("maps", &[]);
+        core.mode = AddrMode::Vir;
+        core.set_loc(0x50e);
+        core.run("px", &["4".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x500               0x0                 0x10                rwx     flash\n\
+             0x510               0x80                0x10                r--     patch\n\
+             0x520               0x20                0x20                rwx     flash\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x0000050e 0000 ffff                                ....\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Failed to map memory\nPhyiscal addresses overlap.\n");
+    }
+    #[test]
+    fn test_banks() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.map(0x0, 0x500, 0x20).unwrap();
+        core.run("bank", &["overlay".to_string()]);
+        core.io.map(0x80, 0x500, 0x10).unwrap();
+        core.run("bank", &[]);
+        core.run("maps", &[]);
+        core.run("bank", &["default".to_string()]);
+        core.run("maps", &[]);
+        core.run("bank", &["remove".to_string(), "default".to_string()]);
+        core.run("bank", &["remove".to_string(), "rom".to_string()]);
+        core.run("bank", &["remove".to_string(), "overlay".to_string()]);
+        core.run("bank", &[]);
+        core.run("bank", &["a".to_string(), "b".to_string()]);
+        core.run("bank", &["remove".to_string(), "a".to_string(), "b".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "  default\n\
+             * overlay\n\
+             Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x500               0x80                0x10                rwx\n\
+             Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x500               0x0                 0x20                rwx\n\
+             * default\n"
+        );
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to remove bank\nCannot remove the active bank.\n\
+             Error: Failed to remove bank\nBank `rom` does not exist.\n\
+             Error: Invalid subcommand\nExpected `remove`, found `a`.\n\
+             Arguments Error: Expected between 0 and 2 arguments, found 3.\n"
+        );
+    }
+    #[test]
+    fn test_dangling_maps() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.map(0x0, 0x500, 0x20).unwrap();
+        core.io.map(0x100, 0x600, 0x20).unwrap();
+        core.io.map(0x180, 0x700, 0x20).unwrap();
+        core.run("close", &["keep".to_string(), "1".to_string()]);
+        core.run("maps", &[]);
+        core.run("unmap", &["0x610".to_string(), "0x1".to_string()]);
+        core.run("close", &["0".to_string()]);
+        core.run("maps", &[]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x500               0x0                 0x20                rwx\n\
+             0x600               0x100               0x20                rwx (dangling)\n\
+             0x700               0x180               0x20                rwx (dangling)\n\
+             Virtual Address     Physical Address    Size                Perm    Name\n\
+             0x700               0x180               0x20                rwx (dangling)\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+    #[test]
     fn test_map_error() {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut map = Map::new();
-        let mut unmap = UnMap::new();
+        let map = Map::new();
+        let unmap = UnMap::new();
         map.run(&mut core, &[]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
         assert_eq!(core.stderr.utf8_string().unwrap(), "Arguments Error: Expected 3 argument(s), found 0.\n");
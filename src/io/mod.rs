@@ -14,33 +14,46 @@
  * You should have received a copy of the GNU Lesser General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+mod export;
 mod files;
+mod journal;
 mod map;
 mod print;
 mod write;
 
+use self::export::*;
 use self::files::*;
+use self::journal::*;
 use self::map::*;
 use self::print::*;
 use self::write::*;
 use crate::core::Core;
-use parking_lot::Mutex;
 use std::sync::Arc;
 pub fn register_io(core: &mut Core) {
-    let maps = Arc::new(Mutex::new(ListMap::new(core)));
-    let files = Arc::new(Mutex::new(ListFiles::new(core)));
-    let px = Arc::new(Mutex::new(PrintHex::new(core)));
+    let maps = Arc::new(ListMap::new(core));
+    let files = Arc::new(ListFiles::new(core));
+    let px = Arc::new(PrintHex::new(core));
 
-    core.add_command("map", "", Arc::new(Mutex::new(Map::new())));
+    core.add_command("map", "", Arc::new(Map::new()));
     core.add_command("maps", "", maps);
     core.add_command("printHex", "px", px);
-    core.add_command("printBase", "pb", Arc::new(Mutex::new(PrintBase::new())));
-    core.add_command("printCSV", "pcsv", Arc::new(Mutex::new(PrintCSV::new())));
-    core.add_command("printSignedCSV", "pscsv", Arc::new(Mutex::new(PrintSignedCSV::new())));
-    core.add_command("unmap", "um", Arc::new(Mutex::new(UnMap::new())));
+    core.add_command("printBase", "pb", Arc::new(PrintBase::new()));
+    core.add_command("printCSV", "pcsv", Arc::new(PrintCSV::new()));
+    core.add_command("printSignedCSV", "pscsv", Arc::new(PrintSignedCSV::new()));
+    core.add_command("unmap", "um", Arc::new(UnMap::new()));
     core.add_command("files", "", files);
-    core.add_command("open", "o", Arc::new(Mutex::new(OpenFile::new())));
-    core.add_command("close", "", Arc::new(Mutex::new(CloseFile::new())));
-    core.add_command("writeHex", "wx", Arc::new(Mutex::new(WriteHex::new())));
-    core.add_command("writeToFile", "wtf", Arc::new(Mutex::new(WriteToFile::new())));
+    core.add_command("open", "o", Arc::new(OpenFile::new()));
+    core.add_command("close", "", Arc::new(CloseFile::new()));
+    core.add_command("commit", "", Arc::new(CommitFile::new()));
+    core.add_command("discard", "", Arc::new(DiscardFile::new()));
+    core.add_command("modified", "", Arc::new(ListModified::new()));
+    core.add_command("warnings", "", Arc::new(ListWarnings::new()));
+    core.add_command("writeHex", "wx", Arc::new(WriteHex::new()));
+    core.add_command("writeToFile", "wtf", Arc::new(WriteToFile::new()));
+    let export = Arc::new(Export::new(core));
+    core.add_command("export", "", export);
+    core.add_command("bank", "", Arc::new(Bank::new()));
+    core.add_command("undo", "", Arc::new(Undo::new()));
+    core.add_command("redo", "", Arc::new(Redo::new()));
+    core.add_command("journal", "", Arc::new(ListJournal::new()));
 }
@@ -16,6 +16,7 @@
  */
 
 use crate::core::*;
+use crate::expr::*;
 use crate::helper::*;
 use crate::writer::*;
 use rair_env::Environment;
@@ -57,20 +58,32 @@ impl PrintHex {
         env.write()
             .add_str_with_cb("printHex.gapReplace", "#", "Text used to replace gaps when using the `printHex` command", core, one_byte)
             .unwrap();
+        env.write()
+            .add_str_with_cb("printHex.commentColor", "color.8", "Color used for comments when using the `printHex` command", core, is_color)
+            .unwrap();
+        env.write()
+            .add_str_with_cb(
+                "printHex.modifiedColor",
+                "color.3",
+                "Color used for bytes modified during the session when using the `printHex` command",
+                core,
+                is_color,
+            )
+            .unwrap();
 
         Default::default()
     }
 }
 
 impl Cmd for PrintHex {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         // we can always optimize by try and using pread an vread.
         // If they fail, only then we might want to attempt the sparce version.
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
         }
-        let size = match str_to_num(&args[0]) {
+        let size = match eval(core, &args[0]) {
             Ok(s) => s,
             Err(e) => {
                 return error_msg(
@@ -99,6 +112,10 @@ impl Cmd for PrintHex {
         let na = core.env.read().get_color(color).unwrap();
         let gap = env.get_str("printHex.gapReplace").unwrap();
         let no_print = env.get_str("printHex.nonPrintReplace").unwrap();
+        let color = env.get_str("printHex.commentColor").unwrap();
+        let comment_color = env.get_color(color).unwrap();
+        let color = env.get_str("printHex.modifiedColor").unwrap();
+        let modified = env.get_color(color).unwrap();
 
         writeln!(
             core.stdout,
@@ -112,15 +129,25 @@ impl Cmd for PrintHex {
             let mut hex = Writer::new_buf();
             for j in i..cmp::min(i + 16, size) {
                 if let Some(c) = data.get(&(j + loc)) {
-                    if j % 2 == 0 {
-                        write!(hex, "{:02x}", c).unwrap();
+                    let dirty = match core.mode {
+                        AddrMode::Phy => core.io.pdirty(j + loc),
+                        AddrMode::Vir => core.io.vdirty(j + loc),
+                    };
+                    let byte = format!("{:02x}", c);
+                    let printable = *c >= 0x21 && *c <= 0x7E;
+                    let text = if printable { (*c as char).to_string() } else { no_print.to_string() };
+                    if dirty {
+                        write!(hex, "{}", Paint::rgb(modified.0, modified.1, modified.2, byte)).unwrap();
+                        write!(ascii, "{}", Paint::rgb(modified.0, modified.1, modified.2, text)).unwrap();
+                    } else if printable {
+                        write!(hex, "{}", byte).unwrap();
+                        write!(ascii, "{}", text).unwrap();
                     } else {
-                        write!(hex, "{:02x} ", c).unwrap();
+                        write!(hex, "{}", byte).unwrap();
+                        write!(ascii, "{}", Paint::rgb(na.0, na.1, na.2, text)).unwrap();
                     }
-                    if *c >= 0x21 && *c <= 0x7E {
-                        write!(ascii, "{}", *c as char).unwrap()
-                    } else {
-                        write!(ascii, "{}", Paint::rgb(na.0, na.1, na.2, no_print)).unwrap();
+                    if j % 2 == 1 {
+                        write!(hex, " ").unwrap();
                     }
                 } else {
                     if j % 2 == 0 {
@@ -131,7 +158,21 @@ impl Cmd for PrintHex {
                     write!(ascii, "{}", Paint::rgb(na.0, na.1, na.2, gap)).unwrap();
                 }
             }
-            writeln!(core.stdout, "{: <40} {}", hex.utf8_string().unwrap(), ascii.utf8_string().unwrap()).unwrap();
+            // hex column may contain color codes, so it is padded based on the number of printed bytes.
+            let (lo, hi) = (loc + i, loc + cmp::min(i + 16, size) - 1);
+            let pad = (40 - (hi - lo + 1) * 5 / 2) as usize;
+            write!(core.stdout, "{}{: <pad$} {}", hex.utf8_string().unwrap(), "", ascii.utf8_string().unwrap(), pad = pad).unwrap();
+            // comments are shown on the first printed row they cover.
+            let comments = core.comments.overlap(lo, hi, core.mode);
+            let mut comments = comments.iter().filter(|c| c.addr >= lo || i == 0).peekable();
+            if comments.peek().is_some() {
+                write!(core.stdout, "{: <1$} ", "", (15 - (hi - lo)) as usize).unwrap();
+                for comment in comments {
+                    let text = format!(" ; {}", comment.text);
+                    write!(core.stdout, "{}", Paint::rgb(comment_color.0, comment_color.1, comment_color.2, text)).unwrap();
+                }
+            }
+            writeln!(core.stdout).unwrap();
         }
     }
     fn help(&self, core: &mut Core) {
@@ -162,12 +203,12 @@ fn encode_hex(data: &[u8]) -> String {
     out
 }
 impl Cmd for PrintBase {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 2 {
             expect(core, args.len() as u64, 2);
             return;
         }
-        let size = match str_to_num(&args[1]) {
+        let size = match eval(core, &args[1]) {
             Ok(size) => size as usize,
             Err(e) => {
                 let err_str = format!("{}", e);
@@ -347,12 +388,12 @@ fn csv512(data: &[u8]) -> String {
 }
 
 impl Cmd for PrintCSV {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 2 {
             expect(core, args.len() as u64, 2);
             return;
         }
-        let count = match str_to_num(&args[1]) {
+        let count = match eval(core, &args[1]) {
             Ok(count) => count as usize,
             Err(e) => {
                 let err_str = format!("{}", e);
@@ -360,7 +401,7 @@ impl Cmd for PrintCSV {
                 return;
             }
         };
-        let bsize = match str_to_num(&args[0]) {
+        let bsize = match eval(core, &args[0]) {
             Ok(size) => size as usize,
             Err(e) => {
                 let err_str = format!("{}", e);
@@ -516,12 +557,12 @@ fn scsv128(data: &[u8]) -> String {
 }
 
 impl Cmd for PrintSignedCSV {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 2 {
             expect(core, args.len() as u64, 2);
             return;
         }
-        let count = match str_to_num(&args[1]) {
+        let count = match eval(core, &args[1]) {
             Ok(count) => count as usize,
             Err(e) => {
                 let err_str = format!("{}", e);
@@ -529,7 +570,7 @@ impl Cmd for PrintSignedCSV {
                 return;
             }
         };
-        let bsize = match str_to_num(&args[0]) {
+        let bsize = match eval(core, &args[0]) {
             Ok(size) => size as usize,
             Err(e) => {
                 let err_str = format!("{}", e);
@@ -1061,7 +1102,7 @@ mod test_print_hex {
     #[test]
     fn test_pb_2() {
         let mut core = Core::new_no_colors();
-        let mut pb = PrintBase::new();
+        let pb = PrintBase::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1092,7 +1133,7 @@ mod test_print_hex {
     #[test]
     fn test_pb_16() {
         let mut core = Core::new_no_colors();
-        let mut pb = PrintBase::new();
+        let pb = PrintBase::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1112,7 +1153,7 @@ mod test_print_hex {
     #[test]
     fn test_pb_error() {
         let mut core = Core::new_no_colors();
-        let mut pb = PrintBase::new();
+        let pb = PrintBase::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1141,7 +1182,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_8() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1170,7 +1211,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_16() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1200,7 +1241,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_32() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1228,7 +1269,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_64() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
@@ -1259,7 +1300,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_128() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
@@ -1290,7 +1331,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_256() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
@@ -1320,7 +1361,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_512() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
@@ -1350,7 +1391,7 @@ mod test_print_hex {
     #[test]
     fn test_pcsv_errors() {
         let mut core = Core::new_no_colors();
-        let mut pcsv = PrintCSV::new();
+        let pcsv = PrintCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
@@ -1386,7 +1427,7 @@ mod test_print_hex {
     #[test]
     fn test_pscsv_errors() {
         let mut core = Core::new_no_colors();
-        let mut pscsv = PrintSignedCSV::new();
+        let pscsv = PrintSignedCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
@@ -1422,7 +1463,7 @@ mod test_print_hex {
     #[test]
     fn test_pscsv_8() {
         let mut core = Core::new_no_colors();
-        let mut pscsv = PrintSignedCSV::new();
+        let pscsv = PrintSignedCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1451,7 +1492,7 @@ mod test_print_hex {
     #[test]
     fn test_pscsv_16() {
         let mut core = Core::new_no_colors();
-        let mut pscsv = PrintSignedCSV::new();
+        let pscsv = PrintSignedCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1481,7 +1522,7 @@ mod test_print_hex {
     #[test]
     fn test_pscsv_32() {
         let mut core = Core::new_no_colors();
-        let mut pscsv = PrintSignedCSV::new();
+        let pscsv = PrintSignedCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/base64/no_padding.b64", IoMode::READ).unwrap();
@@ -1509,7 +1550,7 @@ mod test_print_hex {
     #[test]
     fn test_pscsv_64() {
         let mut core = Core::new_no_colors();
-        let mut pscsv = PrintSignedCSV::new();
+        let pscsv = PrintSignedCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
@@ -1540,7 +1581,7 @@ mod test_print_hex {
     #[test]
     fn test_pscsv_128() {
         let mut core = Core::new_no_colors();
-        let mut pscsv = PrintSignedCSV::new();
+        let pscsv = PrintSignedCSV::new();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
         core.io.open("../testing_binaries/rio/srec/record_0_1_9.srec", IoMode::READ).unwrap();
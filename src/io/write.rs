@@ -16,6 +16,7 @@
  */
 
 use crate::core::*;
+use crate::expr::*;
 use crate::helper::*;
 use std::fs::File;
 use std::io::prelude::*;
@@ -31,7 +32,7 @@ impl WriteHex {
 }
 
 impl Cmd for WriteHex {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
@@ -78,12 +79,12 @@ impl WriteToFile {
 }
 
 impl Cmd for WriteToFile {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 2 {
             expect(core, args.len() as u64, 2);
             return;
         }
-        let size = match str_to_num(&args[0]) {
+        let size = match eval(core, &args[0]) {
             Ok(size) => size as usize,
             Err(e) => {
                 let err_str = format!("{}.", e);
@@ -158,7 +159,7 @@ mod test_write {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut wx = WriteHex::new();
+        let wx = WriteHex::new();
         core.io.open("malloc://0x5000", IoMode::READ | IoMode::WRITE).unwrap();
         core.io.map(0x0, 0x500, 0x500).unwrap();
         wx.run(&mut core, &["123456789abcde".to_string()]);
@@ -177,7 +178,7 @@ mod test_write {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut wtf = WriteToFile::new();
+        let wtf = WriteToFile::new();
         core.io.open("malloc://0x50", IoMode::READ | IoMode::WRITE).unwrap();
         core.io.map(0x0, 0x500, 0x50).unwrap();
 
@@ -205,7 +206,7 @@ mod test_write {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut wx = WriteHex::new();
+        let wx = WriteHex::new();
         core.io.open("malloc://0x50", IoMode::READ | IoMode::WRITE).unwrap();
         wx.run(&mut core, &[]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
@@ -236,7 +237,7 @@ mod test_write {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut wtf = WriteToFile::new();
+        let wtf = WriteToFile::new();
         core.io.open("malloc://0x50", IoMode::READ | IoMode::WRITE).unwrap();
         wtf.run(&mut core, &[]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
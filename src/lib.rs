@@ -26,25 +26,47 @@
  * You should have received a copy of the GNU Lesser General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+extern crate blake2;
+extern crate digest;
 extern crate flate2;
+extern crate md5;
 extern crate parking_lot;
 extern crate rair_env;
 extern crate rair_io;
+extern crate regex;
 extern crate rtrees;
 extern crate serde;
+extern crate sha1;
+extern crate sha2;
 #[cfg(test)]
 extern crate test_file;
 extern crate yansi;
+mod analysis;
 mod commands;
+mod comments;
 mod core;
+mod expr;
+mod flags;
+mod hash;
 mod helper;
 mod io;
+mod loader;
 mod loc;
+mod parser;
+mod search;
 mod utils;
 mod writer;
 
+pub use self::analysis::*;
 pub use self::commands::*;
+pub use self::comments::*;
 pub use self::core::*;
+pub use self::expr::*;
+pub use self::flags::*;
+pub use self::hash::*;
 pub use self::helper::*;
 pub use self::io::*;
+pub use self::loader::*;
+pub use self::parser::*;
+pub use self::search::*;
 pub use self::writer::*;
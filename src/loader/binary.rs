@@ -0,0 +1,112 @@
+/*
+ * binary.rs: information recorded about loaded executables.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use serde::{Deserialize, Serialize};
+
+/// Section of a loaded executable.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Section {
+    pub name: String,
+    /// Virtual address of the section, 0 if the section is not loaded in memory.
+    pub vaddr: u64,
+    /// Physical address of the section contents, [None] if the section has no contents in file.
+    pub paddr: Option<u64>,
+    pub size: u64,
+}
+
+/// Symbol defined in a loaded executable.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub vaddr: u64,
+    pub size: u64,
+}
+
+/// Function imported by a loaded executable.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Import {
+    /// Name of the library the function is imported from.
+    pub library: String,
+    /// Name of the function, [None] if it is imported by ordinal.
+    pub name: Option<String>,
+    /// Ordinal of the function, [None] if it is imported by name.
+    pub ordinal: Option<u64>,
+    /// Virtual address of the slot where the address of the function is stored at runtime.
+    pub vaddr: u64,
+}
+
+/// Function or variable exported by a loaded executable.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Export {
+    /// Name of the export, [None] if it is only exported by ordinal.
+    pub name: Option<String>,
+    pub ordinal: u64,
+    pub vaddr: u64,
+    /// `library.function` that the export is forwarded to, vaddr then points to this string.
+    pub forwarder: Option<String>,
+}
+
+/// Data directory of a loaded executable.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DataDirectory {
+    pub name: String,
+    /// Virtual address of the directory (physical address for the PE certificate table).
+    pub vaddr: u64,
+    pub size: u64,
+}
+
+/// Information about executable loaded using one of the loaders.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Binary {
+    /// Handle of the file containing the executable.
+    pub hndl: u64,
+    /// Human readable description of the executable format.
+    pub format: String,
+    /// Virtual address of the entry point.
+    pub entry: u64,
+    pub sections: Vec<Section>,
+    /// Symbols sorted by their virtual address.
+    pub symbols: Vec<Symbol>,
+    #[serde(default)]
+    pub imports: Vec<Import>,
+    #[serde(default)]
+    pub exports: Vec<Export>,
+    /// Non empty data directories.
+    #[serde(default)]
+    pub directories: Vec<DataDirectory>,
+    /// Handles of the memory backing zero filled parts of segments, closed along with the executable.
+    #[serde(default)]
+    pub zero_fills: Vec<u64>,
+}
+
+impl Binary {
+    /// Returns section with the given name.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+    /// Returns symbol with the given name.
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+    /// Returns data directory with the given name.
+    pub fn directory(&self, name: &str) -> Option<&DataDirectory> {
+        self.directories.iter().find(|d| d.name == name)
+    }
+    /// Returns symbol whose range contains *vaddr*.
+    pub fn symbol_at(&self, vaddr: u64) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.vaddr == vaddr || (s.vaddr < vaddr && vaddr - s.vaddr < s.size))
+    }
+}
@@ -0,0 +1,540 @@
+/*
+ * elf.rs: ELF loader.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::binary::*;
+use super::helper::*;
+use crate::core::*;
+use rair_io::{IoError, IoMode, MapPerm};
+use std::convert::TryInto;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHT_DYNSYM: u32 = 11;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+fn elf_error(msg: &str) -> IoError {
+    IoError::Custom(msg.to_string())
+}
+
+// Decodes integers from ELF structures according to the file class and endianness.
+struct Reader {
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl Reader {
+    fn bytes<'a>(&self, data: &'a [u8], off: usize, size: usize) -> Result<&'a [u8], IoError> {
+        match data.get(off..off + size) {
+            Some(bytes) => Ok(bytes),
+            None => Err(elf_error("Truncated ELF structure")),
+        }
+    }
+    fn u8(&self, data: &[u8], off: usize) -> Result<u8, IoError> {
+        Ok(self.bytes(data, off, 1)?[0])
+    }
+    fn u16(&self, data: &[u8], off: usize) -> Result<u16, IoError> {
+        let bytes = self.bytes(data, off, 2)?.try_into().unwrap();
+        Ok(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+    fn u32(&self, data: &[u8], off: usize) -> Result<u32, IoError> {
+        let bytes = self.bytes(data, off, 4)?.try_into().unwrap();
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+    fn u64(&self, data: &[u8], off: usize) -> Result<u64, IoError> {
+        let bytes = self.bytes(data, off, 8)?.try_into().unwrap();
+        Ok(if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) })
+    }
+    // Reads address or offset sized field, its offset differs between ELF32 and ELF64.
+    fn word(&self, data: &[u8], off32: usize, off64: usize) -> Result<u64, IoError> {
+        if self.is_64 {
+            self.u64(data, off64)
+        } else {
+            Ok(u64::from(self.u32(data, off32)?))
+        }
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    entsize: u64,
+}
+
+// Contents of the opened file, reads are bound checked against the file size.
+struct ElfFile {
+    base: u64,
+    size: u64,
+}
+
+impl ElfFile {
+    fn read(&self, core: &mut Core, offset: u64, size: u64) -> Result<Vec<u8>, IoError> {
+        match offset.checked_add(size) {
+            Some(end) if end <= self.size => (),
+            _ => return Err(elf_error("ELF structure is out of file bounds")),
+        }
+        let mut data = vec![0; size as usize];
+        core.io.pread(self.base + offset, &mut data)?;
+        Ok(data)
+    }
+}
+
+fn parse_segments(core: &mut Core, file: &ElfFile, r: &Reader, hdr: &[u8]) -> Result<Vec<Segment>, IoError> {
+    let phoff = r.word(hdr, 28, 32)?;
+    let phentsize = u64::from(r.u16(hdr, 42 + 12 * r.is_64 as usize)?);
+    let phnum = u64::from(r.u16(hdr, 44 + 12 * r.is_64 as usize)?);
+    if phnum == 0 {
+        return Ok(Vec::new());
+    }
+    let table = file.read(core, phoff, phentsize * phnum)?;
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let ph = r.bytes(&table, (i * phentsize) as usize, phentsize as usize)?;
+        if r.u32(ph, 0)? != PT_LOAD {
+            continue;
+        }
+        let offset = r.word(ph, 4, 8)?;
+        let filesz = r.word(ph, 16, 32)?;
+        match offset.checked_add(filesz) {
+            Some(end) if end <= file.size => (),
+            _ => return Err(elf_error("Loadable segment is out of file bounds")),
+        }
+        let vaddr = r.word(ph, 8, 16)?;
+        let memsz = r.word(ph, 20, 40)?;
+        if vaddr.checked_add(memsz).is_none() {
+            return Err(elf_error("Loadable segment is out of address space"));
+        }
+        // p_flags bits (PF_X, PF_W, PF_R) match those of MapPerm.
+        let flags = if r.is_64 { r.u32(ph, 4)? } else { r.u32(ph, 24)? };
+        segments.push(Segment {
+            paddr: file.base + offset,
+            vaddr,
+            filesz,
+            memsz,
+            perm: MapPerm::from_bits_truncate(u64::from(flags)),
+            name: format!("LOAD{}", segments.len()),
+        });
+    }
+    Ok(segments)
+}
+
+fn parse_section_headers(core: &mut Core, file: &ElfFile, r: &Reader, hdr: &[u8]) -> Result<(Vec<SectionHeader>, u16), IoError> {
+    let shoff = r.word(hdr, 32, 40)?;
+    let shentsize = u64::from(r.u16(hdr, 46 + 12 * r.is_64 as usize)?);
+    let shnum = u64::from(r.u16(hdr, 48 + 12 * r.is_64 as usize)?);
+    let shstrndx = r.u16(hdr, 50 + 12 * r.is_64 as usize)?;
+    if shnum == 0 {
+        return Ok((Vec::new(), 0));
+    }
+    let table = file.read(core, shoff, shentsize * shnum)?;
+    let mut headers = Vec::new();
+    for i in 0..shnum {
+        let sh = r.bytes(&table, (i * shentsize) as usize, shentsize as usize)?;
+        headers.push(SectionHeader {
+            name: r.u32(sh, 0)?,
+            kind: r.u32(sh, 4)?,
+            addr: r.word(sh, 12, 16)?,
+            offset: r.word(sh, 16, 24)?,
+            size: r.word(sh, 20, 32)?,
+            link: r.u32(sh, 24 + 16 * r.is_64 as usize)?,
+            entsize: r.word(sh, 36, 56)?,
+        });
+    }
+    Ok((headers, shstrndx))
+}
+
+fn section_data(core: &mut Core, file: &ElfFile, sh: &SectionHeader) -> Result<Vec<u8>, IoError> {
+    if sh.kind == SHT_NOBITS {
+        return Ok(Vec::new());
+    }
+    file.read(core, sh.offset, sh.size)
+}
+
+fn parse_sections(core: &mut Core, file: &ElfFile, headers: &[SectionHeader], shstrndx: u16) -> Result<Vec<Section>, IoError> {
+    let names = match headers.get(shstrndx as usize) {
+        Some(sh) => section_data(core, file, sh)?,
+        None => Vec::new(),
+    };
+    let mut sections = Vec::new();
+    // First section header is always reserved.
+    for sh in headers.iter().skip(1) {
+        sections.push(Section {
+            name: c_str(&names, sh.name),
+            vaddr: sh.addr,
+            paddr: if sh.kind == SHT_NOBITS { None } else { Some(file.base + sh.offset) },
+            size: sh.size,
+        });
+    }
+    Ok(sections)
+}
+
+fn parse_symbols(core: &mut Core, file: &ElfFile, r: &Reader, headers: &[SectionHeader]) -> Result<Vec<Symbol>, IoError> {
+    let mut symbols: Vec<Symbol> = Vec::new();
+    for sh in headers.iter().filter(|sh| sh.kind == SHT_SYMTAB || sh.kind == SHT_DYNSYM) {
+        let entsize = if sh.entsize != 0 {
+            sh.entsize
+        } else if r.is_64 {
+            24
+        } else {
+            16
+        };
+        let table = section_data(core, file, sh)?;
+        let names = match headers.get(sh.link as usize) {
+            Some(strtab) => section_data(core, file, strtab)?,
+            None => Vec::new(),
+        };
+        for i in 1..sh.size / entsize {
+            let sym = r.bytes(&table, (i * entsize) as usize, entsize as usize)?;
+            let info = if r.is_64 { r.u8(sym, 4)? } else { r.u8(sym, 12)? };
+            if info & 0xf == STT_SECTION || info & 0xf == STT_FILE {
+                continue;
+            }
+            let name = c_str(&names, r.u32(sym, 0)?);
+            if name.is_empty() {
+                continue;
+            }
+            let symbol = Symbol {
+                name,
+                vaddr: r.word(sym, 4, 8)?,
+                size: r.word(sym, 8, 16)?,
+            };
+            if !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+    }
+    symbols.sort_by(|a, b| a.vaddr.cmp(&b.vaddr).then_with(|| a.name.cmp(&b.name)));
+    Ok(symbols)
+}
+
+fn parse_elf(core: &mut Core, hndl: u64) -> Result<Binary, IoError> {
+    let desc = core.io.hndl_to_desc(hndl).unwrap();
+    let file = ElfFile {
+        base: desc.paddr_base(),
+        size: desc.size(),
+    };
+    let ident = file.read(core, 0, 16).map_err(|_| elf_error("Not an ELF file"))?;
+    if &ident[0..4] != b"\x7fELF" {
+        return Err(elf_error("Not an ELF file"));
+    }
+    let r = Reader {
+        is_64: match ident[4] {
+            1 => false,
+            2 => true,
+            _ => return Err(elf_error("Unknown ELF class")),
+        },
+        big_endian: match ident[5] {
+            1 => false,
+            2 => true,
+            _ => return Err(elf_error("Unknown ELF data encoding")),
+        },
+    };
+    let hdr = file.read(core, 0, if r.is_64 { 64 } else { 52 })?;
+    let segments = parse_segments(core, &file, &r, &hdr)?;
+    let (headers, shstrndx) = parse_section_headers(core, &file, &r, &hdr)?;
+    let sections = parse_sections(core, &file, &headers, shstrndx)?;
+    let symbols = parse_symbols(core, &file, &r, &headers)?;
+    let zero_fills = map_segments(core, &segments)?;
+    Ok(Binary {
+        hndl,
+        format: format!("ELF{} ({} endian)", if r.is_64 { 64 } else { 32 }, if r.big_endian { "big" } else { "little" }),
+        entry: r.word(&hdr, 24, 24)?,
+        sections,
+        symbols,
+        imports: Vec::new(),
+        exports: Vec::new(),
+        directories: Vec::new(),
+        zero_fills,
+    })
+}
+
+/// Open ELF file at *path* (at physical address *addr* if given), map all of its loadable
+/// segments into the virtual address space and record its entry point, sections and symbols
+/// in [Core::binaries]. Returns handle of the opened file.
+pub fn load_elf(core: &mut Core, path: &str, perm: IoMode, addr: Option<u64>) -> Result<u64, IoError> {
+    load_binary(core, path, perm, addr, parse_elf)
+}
+
+#[cfg(test)]
+mod test_elf {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::RIOMap;
+    use std::path::Path;
+    use test_file::*;
+
+    fn put(data: &mut [u8], off: usize, value: u64, size: usize, big_endian: bool) {
+        for i in 0..size {
+            let byte = (value >> (8 * i)) as u8;
+            if big_endian {
+                data[off + size - 1 - i] = byte;
+            } else {
+                data[off + i] = byte;
+            }
+        }
+    }
+
+    // Builds ELF file with text segment, data segment with .bss and symbol table.
+    fn build_elf(is_64: bool, big_endian: bool) -> Vec<u8> {
+        let mut elf = vec![0; 0x400];
+        let w = if is_64 { 8 } else { 4 };
+        let mut p = |off: usize, value: u64, size: usize| put(&mut elf, off, value, size, big_endian);
+        // ELF header
+        let (phoff, shoff, phentsize, shentsize, symentsize) = if is_64 { (0x40, 0x200, 56, 64, 24) } else { (0x40, 0x200, 32, 40, 16) };
+        p(16, 2, 2);
+        p(24, 0x40_0100, w);
+        p(if is_64 { 32 } else { 28 }, phoff, w);
+        p(if is_64 { 40 } else { 32 }, shoff, w);
+        let x = if is_64 { 12 } else { 0 };
+        p(42 + x, phentsize, 2);
+        p(44 + x, 3, 2);
+        p(46 + x, shentsize, 2);
+        p(48 + x, 7, 2);
+        p(50 + x, 6, 2);
+        // program headers: PT_LOAD, PT_NOTE, PT_LOAD with zero filled memory
+        let phdrs: [(u64, u64, u64, u64, u64, u64); 3] = [(1, 0x100, 0x40_0100, 0x10, 0x10, 5), (4, 0x110, 0, 8, 8, 4), (1, 0x110, 0x60_0110, 8, 0x20, 6)];
+        for (i, (kind, offset, vaddr, filesz, memsz, flags)) in phdrs.iter().enumerate() {
+            let ph = phoff as usize + i * phentsize as usize;
+            p(ph, *kind, 4);
+            if is_64 {
+                p(ph + 4, *flags, 4);
+                p(ph + 8, *offset, 8);
+                p(ph + 16, *vaddr, 8);
+                p(ph + 32, *filesz, 8);
+                p(ph + 40, *memsz, 8);
+            } else {
+                p(ph + 4, *offset, 4);
+                p(ph + 8, *vaddr, 4);
+                p(ph + 16, *filesz, 4);
+                p(ph + 20, *memsz, 4);
+                p(ph + 24, *flags, 4);
+            }
+        }
+        // symbols: null, main (FUNC), a.c (FILE)
+        for (i, (name, info, value, size)) in [(1u64, 0x12u64, 0x40_0100u64, 0x10u64), (6, 0x04, 0, 0)].iter().enumerate() {
+            let sym = 0x120 + (i + 1) * symentsize as usize;
+            p(sym, *name, 4);
+            if is_64 {
+                p(sym + 4, *info, 1);
+                p(sym + 8, *value, 8);
+                p(sym + 16, *size, 8);
+            } else {
+                p(sym + 4, *value, 4);
+                p(sym + 8, *size, 4);
+                p(sym + 12, *info, 1);
+            }
+        }
+        // section headers: null, .text, .data, .bss, .symtab, .strtab, .shstrtab
+        let shdrs: [(u64, u64, u64, u64, u64, u64, u64); 7] = [
+            (0, 0, 0, 0, 0, 0, 0),
+            (1, 1, 0x40_0100, 0x100, 0x10, 0, 0),
+            (7, 1, 0x60_0110, 0x110, 8, 0, 0),
+            (13, 8, 0x60_0118, 0x118, 0x18, 0, 0),
+            (18, 2, 0, 0x120, 3 * symentsize, 5, symentsize),
+            (26, 3, 0, 0x1a0, 10, 0, 0),
+            (34, 3, 0, 0x1c0, 44, 0, 0),
+        ];
+        for (i, (name, kind, addr, offset, size, link, entsize)) in shdrs.iter().enumerate() {
+            let sh = shoff as usize + i * shentsize as usize;
+            p(sh, *name, 4);
+            p(sh + 4, *kind, 4);
+            if is_64 {
+                p(sh + 16, *addr, 8);
+                p(sh + 24, *offset, 8);
+                p(sh + 32, *size, 8);
+                p(sh + 40, *link, 4);
+                p(sh + 56, *entsize, 8);
+            } else {
+                p(sh + 12, *addr, 4);
+                p(sh + 16, *offset, 4);
+                p(sh + 20, *size, 4);
+                p(sh + 24, *link, 4);
+                p(sh + 36, *entsize, 4);
+            }
+        }
+        elf[0..4].copy_from_slice(b"\x7fELF");
+        elf[4] = if is_64 { 2 } else { 1 };
+        elf[5] = if big_endian { 2 } else { 1 };
+        elf[0x100..0x110].copy_from_slice(b"0123456789abcdef");
+        elf[0x110..0x118].copy_from_slice(b"datadata");
+        elf[0x1a0..0x1aa].copy_from_slice(b"\0main\0a.c\0");
+        elf[0x1c0..0x1ec].copy_from_slice(b"\0.text\0.data\0.bss\0.symtab\0.strtab\0.shstrtab\0");
+        elf
+    }
+
+    fn check_binary(core: &mut Core, format: &str) {
+        assert_eq!(core.binaries.len(), 1);
+        let bin = &core.binaries[0];
+        assert_eq!(bin.format, format);
+        assert_eq!(bin.entry, 0x40_0100);
+        let names: Vec<&str> = bin.sections.iter().map(|s| &*s.name).collect();
+        assert_eq!(names, vec![".text", ".data", ".bss", ".symtab", ".strtab", ".shstrtab"]);
+        assert_eq!(bin.section(".bss").unwrap().paddr, None);
+        assert_eq!(bin.section(".data").unwrap().paddr, Some(0x110));
+        assert_eq!(
+            bin.symbols,
+            vec![Symbol {
+                name: "main".to_string(),
+                vaddr: 0x40_0100,
+                size: 0x10
+            }]
+        );
+        assert_eq!(bin.symbol_at(0x40_010f).unwrap().name, "main");
+        assert!(bin.symbol_at(0x40_0110).is_none());
+        let mut data = [0xff; 0x10];
+        core.io.vread(0x40_0100, &mut data).unwrap();
+        assert_eq!(&data, b"0123456789abcdef");
+        let mut data = [0xff; 0x20];
+        core.io.vread(0x60_0110, &mut data).unwrap();
+        assert_eq!(&data[..8], b"datadata");
+        assert_eq!(&data[8..], &[0; 0x18][..]);
+        let maps: Vec<(MapPerm, String)> = core.io.map_iter().map(|m| (m.perm, m.name.clone())).collect();
+        assert_eq!(
+            maps,
+            vec![
+                (MapPerm::READ | MapPerm::EXEC, "LOAD0".to_string()),
+                (MapPerm::READ | MapPerm::WRITE, "LOAD1".to_string()),
+                (MapPerm::READ | MapPerm::WRITE, "LOAD1".to_string())
+            ]
+        );
+    }
+
+    fn test_elf64_cb(path: &Path) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("open", &[format!("elf://{}", path.to_string_lossy())]);
+        check_binary(&mut core, "ELF64 (little endian)");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_elf64() {
+        operate_on_file(&test_elf64_cb, &build_elf(true, false));
+    }
+
+    fn test_elf32_cb(path: &Path) {
+        let mut core = Core::new_no_colors();
+        load_elf(&mut core, &path.to_string_lossy(), IoMode::READ, Some(0)).unwrap();
+        check_binary(&mut core, "ELF32 (big endian)");
+    }
+
+    #[test]
+    fn test_elf32() {
+        operate_on_file(&test_elf32_cb, &build_elf(false, true));
+    }
+
+    fn test_errors_cb(paths: &[&Path]) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        for path in &paths[..5] {
+            core.run("open", &[format!("elf://{}", path.to_string_lossy())]);
+        }
+        // failed loads don't leave any files or maps behind.
+        assert_eq!(core.io.uri_iter().count(), 0);
+        assert_eq!(core.io.map_iter().count(), 0);
+        assert!(core.binaries.is_empty());
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.map(0, 0x60_0120, 0x10).unwrap();
+        // user maps of other priorities that overlap already mapped segments stay untouched.
+        core.io
+            .map_region(RIOMap {
+                paddr: 0,
+                vaddr: 0x60_0110,
+                size: 0x8,
+                priority: 1,
+                ..Default::default()
+            })
+            .unwrap();
+        core.run("open", &[format!("elf://{}", paths[5].to_string_lossy())]);
+        assert_eq!(core.io.uri_iter().count(), 1);
+        let maps: Vec<(u64, u64)> = core.io.map_iter().map(|m| (m.vaddr, m.priority)).collect();
+        assert_eq!(maps, vec![(0x60_0110, 1), (0x60_0120, 0)]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to open file\n\
+             Not an ELF file.\n\
+             Error: Failed to open file\n\
+             Unknown ELF class.\n\
+             Error: Failed to open file\n\
+             Loadable segment is out of file bounds.\n\
+             Error: Failed to open file\n\
+             Zero filled part of segment is too large.\n\
+             Error: Failed to open file\n\
+             Loadable segment is out of address space.\n\
+             Error: Failed to open file\n\
+             Phyiscal addresses overlap.\n"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bad_class = build_elf(true, false);
+        bad_class[4] = 3;
+        let mut bad_segment = build_elf(true, false);
+        put(&mut bad_segment, 0x40 + 56 * 2 + 32, 0x1000, 8, false);
+        let mut huge_memsz = build_elf(true, false);
+        put(&mut huge_memsz, 0x40 + 56 * 2 + 40, 0xffff_ffff_ffff, 8, false);
+        let mut bad_vaddr = build_elf(true, false);
+        put(&mut bad_vaddr, 0x40 + 56 * 2 + 16, 0xffff_ffff_ffff_fff0, 8, false);
+        let good = build_elf(true, false);
+        operate_on_files(&test_errors_cb, &[DATA, &bad_class, &bad_segment, &huge_memsz, &bad_vaddr, &good]);
+    }
+
+    fn test_close_cb(path: &Path) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("open", &[format!("elf://{}", path.to_string_lossy())]);
+        let zero_fills = core.binaries[0].zero_fills.clone();
+        assert_eq!(zero_fills.len(), 1);
+        assert_eq!(core.io.hndl_to_desc(zero_fills[0]).unwrap().name(), "malloc://0x18");
+        core.run("close", &["0".to_string()]);
+        assert!(core.binaries.is_empty());
+        assert_eq!(core.io.uri_iter().count(), 0);
+        assert_eq!(core.io.map_iter().count(), 0);
+        core.run("open", &[format!("elf://{}", path.to_string_lossy())]);
+        let hndl = core.binaries[0].hndl;
+        core.run("close", &["keep".to_string(), hndl.to_string()]);
+        assert!(core.binaries.is_empty());
+        assert_eq!(core.io.uri_iter().count(), 0);
+        assert_eq!(core.io.map_iter().count(), 0);
+        assert_eq!(core.io.dangling_iter().count(), 3);
+        // zero filled memory closed by hand is no longer closed along with the executable.
+        core.run("open", &[format!("elf://{}", path.to_string_lossy())]);
+        let (hndl, zero_fill) = (core.binaries[0].hndl, core.binaries[0].zero_fills[0]);
+        core.run("close", &[zero_fill.to_string()]);
+        assert!(core.binaries[0].zero_fills.is_empty());
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run("close", &[hndl.to_string()]);
+        assert_eq!(core.io.uri_iter().count(), 1);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_close() {
+        operate_on_file(&test_close_cb, &build_elf(true, false));
+    }
+}
@@ -0,0 +1,111 @@
+/*
+ * helper.rs: routines shared between the different loaders.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::binary::Binary;
+use crate::core::*;
+use rair_io::{IoError, IoMode, MapPerm, RIOMap};
+
+// Region of the virtual address space that a loader maps, memory beyond filesz is zero filled.
+pub(super) struct Segment {
+    pub paddr: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub perm: MapPerm,
+    pub name: String,
+}
+
+impl Segment {
+    fn map(&self, paddr: u64, vaddr: u64, size: u64) -> RIOMap {
+        RIOMap {
+            paddr,
+            vaddr,
+            size,
+            perm: self.perm,
+            name: self.name.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// Reads NUL terminated string starting at off inside table.
+pub(super) fn c_str(table: &[u8], off: u32) -> String {
+    let start = off as usize;
+    if start >= table.len() {
+        return String::new();
+    }
+    let end = table[start..].iter().position(|c| *c == 0).map_or(table.len(), |p| start + p);
+    String::from_utf8_lossy(&table[start..end]).to_string()
+}
+
+// Largest zero filled part of a single segment, anything bigger comes from a malformed header.
+const MAX_ZERO_FILL: u64 = 0x1000_0000;
+
+// Maps all segments and returns handles of the memory allocated for their zero filled parts,
+// in case of failure that memory is closed again. Maps backed by the file itself are removed
+// once the caller closes the file.
+pub(super) fn map_segments(core: &mut Core, segments: &[Segment]) -> Result<Vec<u64>, IoError> {
+    let mut zero_fills = Vec::new();
+    if let Err(e) = map_segments_inner(core, segments, &mut zero_fills) {
+        for hndl in zero_fills {
+            core.io.close(hndl).unwrap();
+        }
+        return Err(e);
+    }
+    Ok(zero_fills)
+}
+
+fn map_segments_inner(core: &mut Core, segments: &[Segment], zero_fills: &mut Vec<u64>) -> Result<(), IoError> {
+    for segment in segments {
+        let filesz = segment.filesz.min(segment.memsz);
+        if segment.vaddr.checked_add(segment.memsz).is_none() || segment.paddr.checked_add(filesz).is_none() {
+            return Err(IoError::Custom("Segment is out of address space".to_string()));
+        }
+        if filesz != 0 {
+            core.io.map_region(segment.map(segment.paddr, segment.vaddr, filesz))?;
+        }
+        if segment.memsz > filesz {
+            // memory that is not backed by the file must be zero filled.
+            let zeros = segment.memsz - filesz;
+            if zeros > MAX_ZERO_FILL {
+                return Err(IoError::Custom("Zero filled part of segment is too large".to_string()));
+            }
+            let hndl = core.io.open(&format!("malloc://0x{:x}", zeros), IoMode::READ | IoMode::WRITE)?;
+            zero_fills.push(hndl);
+            let paddr = core.io.hndl_to_desc(hndl).unwrap().paddr_base();
+            core.io.map_region(segment.map(paddr, segment.vaddr + filesz, zeros))?;
+        }
+    }
+    Ok(())
+}
+
+// Opens file at path and parses it using parse, the file is closed again if parsing fails.
+pub(super) fn load_binary(core: &mut Core, path: &str, perm: IoMode, addr: Option<u64>, parse: fn(&mut Core, u64) -> Result<Binary, IoError>) -> Result<u64, IoError> {
+    let hndl = match addr {
+        Some(addr) => core.io.open_at(path, perm, addr)?,
+        None => core.io.open(path, perm)?,
+    };
+    match parse(core, hndl) {
+        Ok(binary) => {
+            core.binaries.push(binary);
+            Ok(hndl)
+        }
+        Err(e) => {
+            core.io.close(hndl).unwrap();
+            Err(e)
+        }
+    }
+}
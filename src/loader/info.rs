@@ -0,0 +1,246 @@
+/*
+ * info.rs: commands for showing information about loaded executables.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::binary::*;
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use std::io::Write;
+
+fn list_binaries(core: &mut Core) {
+    for i in 0..core.binaries.len() {
+        let bin = &core.binaries[i];
+        let name = match core.io.hndl_to_desc(bin.hndl) {
+            Some(desc) => desc.name().to_string(),
+            None => "-".to_string(),
+        };
+        writeln!(core.stdout, "{}\t{}\t0x{:08x}\t{}", bin.hndl, bin.format, bin.entry, name).unwrap();
+    }
+}
+
+fn list_table(core: &mut Core, bin: &Binary, table: &str) {
+    let rows: Vec<String> = match table {
+        "sections" => bin
+            .sections
+            .iter()
+            .map(|s| {
+                let paddr = s.paddr.map_or("-".to_string(), |p| format!("0x{:08x}", p));
+                format!("0x{:08x}\t{}\t0x{:x}\t{}", s.vaddr, paddr, s.size, s.name)
+            })
+            .collect(),
+        "symbols" => bin.symbols.iter().map(|s| format!("0x{:08x}\t0x{:x}\t{}", s.vaddr, s.size, s.name)).collect(),
+        "imports" => bin
+            .imports
+            .iter()
+            .map(|i| {
+                let name = i.name.clone().unwrap_or_else(|| format!("#{}", i.ordinal.unwrap()));
+                format!("0x{:08x}\t{}\t{}", i.vaddr, i.library, name)
+            })
+            .collect(),
+        "exports" => bin
+            .exports
+            .iter()
+            .map(|e| {
+                let mut row = format!("0x{:08x}\t#{}\t{}", e.vaddr, e.ordinal, e.name.as_ref().map_or("-", |n| n));
+                if let Some(forwarder) = &e.forwarder {
+                    row.push_str(&format!(" -> {}", forwarder));
+                }
+                row
+            })
+            .collect(),
+        "directories" => bin.directories.iter().map(|d| format!("0x{:08x}\t0x{:x}\t{}", d.vaddr, d.size, d.name)).collect(),
+        _ => return error_msg(core, "Failed to show binary information", &format!("Unknown table `{}`.", table)),
+    };
+    for row in rows {
+        writeln!(core.stdout, "{}", row).unwrap();
+    }
+}
+
+#[derive(Default)]
+pub struct Info {}
+
+impl Info {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Info {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() {
+            return list_binaries(core);
+        }
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        let hndl = match eval(core, &args[0]) {
+            Ok(hndl) => hndl,
+            Err(e) => return error_msg(core, "Failed to parse handle", &e.to_string()),
+        };
+        let bin = match core.binaries.iter().find(|b| b.hndl == hndl) {
+            Some(bin) => bin.clone(),
+            None => return error_msg(core, "Failed to show binary information", &format!("No executable is loaded from handle {}.", hndl)),
+        };
+        list_table(core, &bin, &args[1]);
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"info",
+            &"",
+            vec![
+                ("", "List loaded executables with their handle, format and entry point."),
+                ("[hndl] [table]", "List sections, symbols, imports, exports or directories of executable loaded from [hndl]."),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_info {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    fn binary(hndl: u64) -> Binary {
+        Binary {
+            hndl,
+            format: "PE32 (i386)".to_string(),
+            entry: 0x40_1000,
+            sections: vec![
+                Section {
+                    name: ".text".to_string(),
+                    vaddr: 0x40_1000,
+                    paddr: Some(0x200),
+                    size: 0x10,
+                },
+                Section {
+                    name: ".bss".to_string(),
+                    vaddr: 0x40_2000,
+                    paddr: None,
+                    size: 0x80,
+                },
+            ],
+            symbols: vec![Symbol {
+                name: "run".to_string(),
+                vaddr: 0x40_1000,
+                size: 0,
+            }],
+            imports: vec![
+                Import {
+                    library: "KERNEL32.dll".to_string(),
+                    name: Some("Sleep".to_string()),
+                    ordinal: None,
+                    vaddr: 0x40_3000,
+                },
+                Import {
+                    library: "KERNEL32.dll".to_string(),
+                    name: None,
+                    ordinal: Some(5),
+                    vaddr: 0x40_3004,
+                },
+            ],
+            exports: vec![
+                Export {
+                    name: Some("run".to_string()),
+                    ordinal: 1,
+                    vaddr: 0x40_1000,
+                    forwarder: None,
+                },
+                Export {
+                    name: None,
+                    ordinal: 2,
+                    vaddr: 0x40_3010,
+                    forwarder: Some("KERNEL32.ExitProcess".to_string()),
+                },
+            ],
+            directories: vec![DataDirectory {
+                name: "IAT".to_string(),
+                vaddr: 0x40_3000,
+                size: 0xc,
+            }],
+            zero_fills: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_info_docs() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("info");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [info]\n\n\
+             Usage:\n\
+             info\tList loaded executables with their handle, format and entry point.\n\
+             info [hndl] [table]\tList sections, symbols, imports, exports or directories of executable loaded from [hndl].\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_info() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        let hndl = core.io.open("malloc://0x400", IoMode::READ | IoMode::WRITE).unwrap();
+        core.binaries.push(binary(hndl));
+        core.binaries.push(binary(hndl + 1));
+        core.run("info", &[]);
+        for table in &["sections", "symbols", "imports", "exports", "directories"] {
+            core.run("info", &[hndl.to_string(), table.to_string()]);
+        }
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0\tPE32 (i386)\t0x00401000\tmalloc://0x400\n\
+             1\tPE32 (i386)\t0x00401000\t-\n\
+             0x00401000\t0x00000200\t0x10\t.text\n\
+             0x00402000\t-\t0x80\t.bss\n\
+             0x00401000\t0x0\trun\n\
+             0x00403000\tKERNEL32.dll\tSleep\n\
+             0x00403004\tKERNEL32.dll\t#5\n\
+             0x00401000\t#1\trun\n\
+             0x00403010\t#2\t- -> KERNEL32.ExitProcess\n\
+             0x00403000\t0xc\tIAT\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_info_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.binaries.push(binary(0));
+        core.run("info", &["0".to_string()]);
+        core.run("info", &["x".to_string(), "sections".to_string()]);
+        core.run("info", &["1".to_string(), "sections".to_string()]);
+        core.run("info", &["0".to_string(), "relocations".to_string()]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected 2 argument(s), found 1.\n\
+             Error: Failed to parse handle\n\
+             invalid digit found in string\n\
+             Error: Failed to show binary information\n\
+             No executable is loaded from handle 1.\n\
+             Error: Failed to show binary information\n\
+             Unknown table `relocations`.\n"
+        );
+    }
+}
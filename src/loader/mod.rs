@@ -0,0 +1,67 @@
+/*
+ * loader: loading executable formats into the virtual address space.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+mod binary;
+mod elf;
+mod helper;
+mod info;
+mod pe;
+
+pub use self::binary::*;
+pub use self::elf::load_elf;
+use self::info::*;
+pub use self::pe::load_pe;
+use crate::core::Core;
+use rair_io::{IoError, IoMode};
+use std::sync::Arc;
+
+pub fn register_loader(core: &mut Core) {
+    core.add_command("info", "", Arc::new(Info::new()));
+}
+
+/// Open *uri* using the loader selected by its scheme (`elf://` or `pe://`). Returns [None]
+/// if *uri* doesn't use the scheme of any loader, otherwise the handle of the opened file or
+/// the error that made loading fail is returned.
+pub fn load_uri(core: &mut Core, uri: &str, perm: IoMode, addr: Option<u64>) -> Option<Result<u64, IoError>> {
+    if uri.starts_with("elf://") {
+        return Some(load_elf(core, uri.trim_start_matches("elf://"), perm, addr));
+    }
+    if uri.starts_with("pe://") {
+        return Some(load_pe(core, uri.trim_start_matches("pe://"), perm, addr));
+    }
+    None
+}
+
+/// Update loaded executables after the file with handle *hndl* got closed. If *hndl* belongs to
+/// a loaded executable, it is forgotten and the memory backing its zero filled segments is closed
+/// as well, keeping their maps as dangling maps if *keep* is set.
+pub fn unload_binary(core: &mut Core, hndl: u64, keep: bool) {
+    // closed handles get reused, so they must not stay recorded as zero filled memory.
+    for bin in &mut core.binaries {
+        bin.zero_fills.retain(|h| *h != hndl);
+    }
+    let i = match core.binaries.iter().position(|bin| bin.hndl == hndl) {
+        Some(i) => i,
+        None => return,
+    };
+    for hndl in core.binaries.remove(i).zero_fills {
+        if keep {
+            core.io.close_keep_maps(hndl).unwrap();
+        } else {
+            core.io.close(hndl).unwrap();
+        }
+    }
+}
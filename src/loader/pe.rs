@@ -0,0 +1,593 @@
+/*
+ * pe.rs: PE/COFF loader.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::binary::*;
+use super::helper::*;
+use crate::core::*;
+use rair_io::{IoError, IoMode, MapPerm};
+use std::convert::TryInto;
+
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const DIRECTORY_NAMES: [&str; 16] = [
+    "Export",
+    "Import",
+    "Resource",
+    "Exception",
+    "Certificate",
+    "BaseRelocation",
+    "Debug",
+    "Architecture",
+    "GlobalPtr",
+    "TLS",
+    "LoadConfig",
+    "BoundImport",
+    "IAT",
+    "DelayImport",
+    "CLRRuntime",
+    "Reserved",
+];
+const EXPORT_DIRECTORY: usize = 0;
+const IMPORT_DIRECTORY: usize = 1;
+const CERTIFICATE_DIRECTORY: usize = 4;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+// Strings referenced by import and export tables are truncated to this length.
+const MAX_NAME: u64 = 0x200;
+
+fn pe_error(msg: &str) -> IoError {
+    IoError::Custom(msg.to_string())
+}
+
+// Adds offset read from the file to base, malformed headers can point beyond the address space.
+fn address(base: u64, offset: u64) -> Result<u64, IoError> {
+    base.checked_add(offset).ok_or_else(|| pe_error("PE address is out of address space"))
+}
+
+// PE is always little endian, all buffers passed here are read with a known size.
+fn u16(data: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(data[off..off + 2].try_into().unwrap())
+}
+fn u32(data: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(data[off..off + 4].try_into().unwrap())
+}
+fn u64(data: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(data[off..off + 8].try_into().unwrap())
+}
+
+fn machine_name(machine: u16) -> String {
+    match machine {
+        0x14c => "i386".to_string(),
+        0x8664 => "x86-64".to_string(),
+        0x1c0 => "arm".to_string(),
+        0x1c4 => "thumb".to_string(),
+        0xaa64 => "arm64".to_string(),
+        _ => format!("machine 0x{:x}", machine),
+    }
+}
+
+struct SectionHeader {
+    name: String,
+    vaddr: u64,
+    vsize: u64,
+    raw_size: u64,
+    raw_ptr: u64,
+    perm: MapPerm,
+}
+
+struct PeFile {
+    base: u64,
+    size: u64,
+    is_64: bool,
+    image_base: u64,
+    headers_size: u64,
+    sections: Vec<SectionHeader>,
+}
+
+impl PeFile {
+    fn read(&self, core: &mut Core, offset: u64, size: u64) -> Result<Vec<u8>, IoError> {
+        match offset.checked_add(size) {
+            Some(end) if end <= self.size => (),
+            _ => return Err(pe_error("PE structure is out of file bounds")),
+        }
+        let mut data = vec![0; size as usize];
+        core.io.pread(self.base + offset, &mut data)?;
+        Ok(data)
+    }
+    // Returns file offset of rva and number of bytes stored in file starting at this offset.
+    fn rva_to_offset(&self, rva: u64) -> Result<(u64, u64), IoError> {
+        if rva < self.headers_size {
+            return Ok((rva, self.headers_size - rva));
+        }
+        for s in &self.sections {
+            if rva >= s.vaddr && rva - s.vaddr < s.raw_size.min(s.vsize) {
+                return Ok((s.raw_ptr + rva - s.vaddr, s.raw_size.min(s.vsize) - (rva - s.vaddr)));
+            }
+        }
+        Err(pe_error("PE structure is not backed by file"))
+    }
+    fn read_rva(&self, core: &mut Core, rva: u64, size: u64) -> Result<Vec<u8>, IoError> {
+        let (offset, available) = self.rva_to_offset(rva)?;
+        if size > available {
+            return Err(pe_error("PE structure is not backed by file"));
+        }
+        self.read(core, offset, size)
+    }
+    fn read_str(&self, core: &mut Core, rva: u64) -> Result<String, IoError> {
+        let (offset, available) = self.rva_to_offset(rva)?;
+        let data = self.read(core, offset, available.min(MAX_NAME))?;
+        Ok(c_str(&data, 0))
+    }
+}
+
+fn parse_directories(pe: &PeFile, opt: &[u8]) -> Vec<(u64, u64)> {
+    let (count_off, dirs_off) = if pe.is_64 { (108, 112) } else { (92, 96) };
+    let count = (u32(opt, count_off) as usize).min((opt.len() - dirs_off) / 8).min(DIRECTORY_NAMES.len());
+    (0..count).map(|i| (u64::from(u32(opt, dirs_off + 8 * i)), u64::from(u32(opt, dirs_off + 8 * i + 4)))).collect()
+}
+
+fn section_perm(characteristics: u32) -> MapPerm {
+    let mut perm = MapPerm::empty();
+    if characteristics & IMAGE_SCN_MEM_EXECUTE != 0 {
+        perm |= MapPerm::EXEC;
+    }
+    if characteristics & IMAGE_SCN_MEM_READ != 0 {
+        perm |= MapPerm::READ;
+    }
+    if characteristics & IMAGE_SCN_MEM_WRITE != 0 {
+        perm |= MapPerm::WRITE;
+    }
+    perm
+}
+
+fn parse_section_headers(core: &mut Core, pe: &PeFile, offset: u64, count: u64) -> Result<Vec<SectionHeader>, IoError> {
+    let table = pe.read(core, offset, 40 * count)?;
+    let mut sections = Vec::new();
+    for sh in table.chunks(40) {
+        let raw_size = u64::from(u32(sh, 16));
+        let vsize = match u32(sh, 8) {
+            0 => raw_size,
+            size => u64::from(size),
+        };
+        sections.push(SectionHeader {
+            name: c_str(&sh[..8], 0),
+            vaddr: u64::from(u32(sh, 12)),
+            vsize,
+            raw_size,
+            raw_ptr: u64::from(u32(sh, 20)),
+            perm: section_perm(u32(sh, 36)),
+        });
+    }
+    Ok(sections)
+}
+
+fn parse_exports(core: &mut Core, pe: &PeFile, (rva, size): (u64, u64)) -> Result<Vec<Export>, IoError> {
+    let dir = pe.read_rva(core, rva, 40)?;
+    let ordinal_base = u64::from(u32(&dir, 16));
+    let functions_count = u64::from(u32(&dir, 20));
+    let names_count = u64::from(u32(&dir, 24));
+    let functions = pe.read_rva(core, u64::from(u32(&dir, 28)), 4 * functions_count)?;
+    let names = pe.read_rva(core, u64::from(u32(&dir, 32)), 4 * names_count)?;
+    let ordinals = pe.read_rva(core, u64::from(u32(&dir, 36)), 2 * names_count)?;
+    let mut exports = Vec::new();
+    for i in 0..functions_count as usize {
+        let function = u64::from(u32(&functions, 4 * i));
+        if function == 0 {
+            continue;
+        }
+        let name = match (0..names_count as usize).find(|j| u16(&ordinals, 2 * j) as usize == i) {
+            Some(j) => Some(pe.read_str(core, u64::from(u32(&names, 4 * j)))?),
+            None => None,
+        };
+        // exports pointing inside the export directory are forwarded to other libraries.
+        let forwarder = if function >= rva && function - rva < size { Some(pe.read_str(core, function)?) } else { None };
+        exports.push(Export {
+            name,
+            ordinal: ordinal_base + i as u64,
+            vaddr: address(pe.image_base, function)?,
+            forwarder,
+        });
+    }
+    Ok(exports)
+}
+
+fn parse_imports(core: &mut Core, pe: &PeFile, rva: u64) -> Result<Vec<Import>, IoError> {
+    let (thunk_size, ordinal_flag) = if pe.is_64 { (8, 1 << 63) } else { (4, 1 << 31) };
+    let mut imports = Vec::new();
+    for i in 0.. {
+        let desc = pe.read_rva(core, rva + 20 * i, 20)?;
+        if desc.iter().all(|b| *b == 0) {
+            break;
+        }
+        let library = pe.read_str(core, u64::from(u32(&desc, 12)))?;
+        let iat = u64::from(u32(&desc, 16));
+        // lookup table is optional, otherwise the address table is used before it is bound.
+        let lookup = match u32(&desc, 0) {
+            0 => iat,
+            lookup => u64::from(lookup),
+        };
+        for j in 0.. {
+            let thunk = pe.read_rva(core, lookup + thunk_size * j, thunk_size)?;
+            let thunk = if pe.is_64 { u64(&thunk, 0) } else { u64::from(u32(&thunk, 0)) };
+            if thunk == 0 {
+                break;
+            }
+            let (name, ordinal) = if thunk & ordinal_flag != 0 {
+                (None, Some(thunk & 0xffff))
+            } else {
+                // name is preceded by 2 bytes hint.
+                (Some(pe.read_str(core, (thunk & 0x7fff_ffff) + 2)?), None)
+            };
+            imports.push(Import {
+                library: library.clone(),
+                name,
+                ordinal,
+                vaddr: address(pe.image_base, address(iat, thunk_size * j)?)?,
+            });
+        }
+    }
+    Ok(imports)
+}
+
+fn parse_pe(core: &mut Core, hndl: u64) -> Result<Binary, IoError> {
+    let desc = core.io.hndl_to_desc(hndl).unwrap();
+    let mut pe = PeFile {
+        base: desc.paddr_base(),
+        size: desc.size(),
+        is_64: false,
+        image_base: 0,
+        headers_size: 0,
+        sections: Vec::new(),
+    };
+    let dos = pe.read(core, 0, 0x40).map_err(|_| pe_error("Not a PE file"))?;
+    if &dos[0..2] != b"MZ" {
+        return Err(pe_error("Not a PE file"));
+    }
+    let pe_offset = u64::from(u32(&dos, 0x3c));
+    let coff = pe.read(core, pe_offset, 24).map_err(|_| pe_error("Not a PE file"))?;
+    if &coff[0..4] != b"PE\0\0" {
+        return Err(pe_error("Not a PE file"));
+    }
+    let machine = u16(&coff, 4);
+    let sections_count = u64::from(u16(&coff, 6));
+    let opt_size = u64::from(u16(&coff, 20));
+    let opt = pe.read(core, pe_offset + 24, opt_size)?;
+    pe.is_64 = match if opt.len() >= 2 { u16(&opt, 0) } else { 0 } {
+        PE32_MAGIC => false,
+        PE32_PLUS_MAGIC => true,
+        _ => return Err(pe_error("Unknown PE optional header magic")),
+    };
+    if opt.len() < if pe.is_64 { 112 } else { 96 } {
+        return Err(pe_error("Truncated PE optional header"));
+    }
+    pe.image_base = if pe.is_64 { u64(&opt, 24) } else { u64::from(u32(&opt, 28)) };
+    pe.headers_size = u64::from(u32(&opt, 60)).min(pe.size);
+    pe.sections = parse_section_headers(core, &pe, pe_offset + 24 + opt_size, sections_count)?;
+    let mut segments = vec![Segment {
+        paddr: pe.base,
+        vaddr: pe.image_base,
+        filesz: pe.headers_size,
+        memsz: pe.headers_size,
+        perm: MapPerm::READ,
+        name: "HEADER".to_string(),
+    }];
+    let mut sections = Vec::new();
+    for s in &pe.sections {
+        // raw data is either padded to the file alignment or shorter than the section in memory.
+        let filesz = s.raw_size.min(s.vsize);
+        match s.raw_ptr.checked_add(filesz) {
+            Some(end) if end <= pe.size => (),
+            _ => return Err(pe_error("Section is out of file bounds")),
+        }
+        sections.push(Section {
+            name: s.name.clone(),
+            vaddr: address(pe.image_base, s.vaddr)?,
+            paddr: if filesz == 0 { None } else { Some(pe.base + s.raw_ptr) },
+            size: s.vsize,
+        });
+        segments.push(Segment {
+            paddr: pe.base + s.raw_ptr,
+            vaddr: address(pe.image_base, s.vaddr)?,
+            filesz,
+            memsz: s.vsize,
+            perm: s.perm,
+            name: s.name.clone(),
+        });
+    }
+    let dirs = parse_directories(&pe, &opt);
+    let exports = match dirs.get(EXPORT_DIRECTORY) {
+        Some(dir) if dir.1 != 0 => parse_exports(core, &pe, *dir)?,
+        _ => Vec::new(),
+    };
+    let imports = match dirs.get(IMPORT_DIRECTORY) {
+        Some(dir) if dir.1 != 0 => parse_imports(core, &pe, dir.0)?,
+        _ => Vec::new(),
+    };
+    let mut directories = Vec::new();
+    for (i, (rva, size)) in dirs.into_iter().enumerate() {
+        if size == 0 {
+            continue;
+        }
+        // certificate table is not loaded in memory, it is referenced by its file offset.
+        let vaddr = address(if i == CERTIFICATE_DIRECTORY { pe.base } else { pe.image_base }, rva)?;
+        directories.push(DataDirectory {
+            name: DIRECTORY_NAMES[i].to_string(),
+            vaddr,
+            size,
+        });
+    }
+    let mut symbols: Vec<Symbol> = exports
+        .iter()
+        .filter(|e| e.forwarder.is_none())
+        .filter_map(|e| {
+            e.name.as_ref().map(|name| Symbol {
+                name: name.clone(),
+                vaddr: e.vaddr,
+                size: 0,
+            })
+        })
+        .collect();
+    symbols.sort_by(|a, b| a.vaddr.cmp(&b.vaddr).then_with(|| a.name.cmp(&b.name)));
+    let zero_fills = map_segments(core, &segments)?;
+    Ok(Binary {
+        hndl,
+        format: format!("{} ({})", if pe.is_64 { "PE32+" } else { "PE32" }, machine_name(machine)),
+        entry: address(pe.image_base, u64::from(u32(&opt, 16)))?,
+        sections,
+        symbols,
+        imports,
+        exports,
+        directories,
+        zero_fills,
+    })
+}
+
+/// Open PE file at *path* (at physical address *addr* if given), map its headers and sections
+/// at their image base and record its entry point, sections, imports, exports and data
+/// directories in [Core::binaries]. Returns handle of the opened file.
+pub fn load_pe(core: &mut Core, path: &str, perm: IoMode, addr: Option<u64>) -> Result<u64, IoError> {
+    load_binary(core, path, perm, addr, parse_pe)
+}
+
+#[cfg(test)]
+mod test_pe {
+    use super::*;
+    use crate::writer::Writer;
+    use std::path::Path;
+    use test_file::*;
+
+    fn put(data: &mut [u8], off: usize, value: u64, size: usize) {
+        data[off..off + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    // Builds PE file with truncated .text, partially zero filled .rdata holding the
+    // export and import tables and .bss that is not backed by the file at all.
+    fn build_pe(is_64: bool) -> Vec<u8> {
+        let mut pe = vec![0; 0x700];
+        let w: usize = if is_64 { 8 } else { 4 };
+        let mut p = |off: usize, value: u64, size: usize| put(&mut pe, off, value, size);
+        p(0x3c, 0x80, 4);
+        p(0x84, if is_64 { 0x8664 } else { 0x14c }, 2);
+        p(0x86, 3, 2);
+        let opt_size = if is_64 { 0xf0 } else { 0xe0 };
+        p(0x94, opt_size, 2);
+        // optional header
+        p(0x98, if is_64 { 0x20b } else { 0x10b }, 2);
+        p(0x98 + 16, 0x1000, 4);
+        if is_64 {
+            p(0x98 + 24, 0x1_4000_0000, 8);
+        } else {
+            p(0x98 + 28, 0x40_0000, 4);
+        }
+        p(0x98 + 60, 0x200, 4);
+        let dirs = 0x98 + if is_64 { 112 } else { 96 };
+        p(dirs - 4, 16, 4);
+        for (i, rva, size) in [(0, 0x2000, 0x100), (1, 0x2180, 0x28), (12, 0x21e0, 3 * w as u64)].iter() {
+            p(dirs + 8 * i, *rva, 4);
+            p(dirs + 8 * i + 4, *size, 4);
+        }
+        // section headers
+        let sections = [
+            (&b".text"[..], 0x10, 0x1000, 0x200, 0x200, 0x6000_0020),
+            (b".rdata", 0x400, 0x2000, 0x300, 0x400, 0x4000_0040),
+            (b".bss", 0x80, 0x3000, 0, 0, 0xc000_0080),
+        ];
+        for (i, (name, vsize, vaddr, raw_size, raw_ptr, characteristics)) in sections.iter().enumerate() {
+            let sh = 0x98 + opt_size as usize + 40 * i;
+            p(sh + 8, *vsize, 4);
+            p(sh + 12, *vaddr, 4);
+            p(sh + 16, *raw_size, 4);
+            p(sh + 20, *raw_ptr, 4);
+            p(sh + 36, *characteristics, 4);
+            for (j, c) in name.iter().enumerate() {
+                p(sh + j, u64::from(*c), 1);
+            }
+        }
+        // export directory: run and quit which is forwarded to KERNEL32.ExitProcess
+        for (off, value) in [(12, 0x2100), (16, 1), (20, 2), (24, 2), (28, 0x2040), (32, 0x2050), (36, 0x2060)].iter() {
+            p(0x400 + off, *value, 4);
+        }
+        p(0x440, 0x1000, 4);
+        p(0x444, 0x2070, 4);
+        p(0x450, 0x2109, 4);
+        p(0x454, 0x210d, 4);
+        p(0x462, 1, 2);
+        // import directory: KERNEL32.dll!Sleep and KERNEL32.dll!#5
+        p(0x580, 0x21c0, 4);
+        p(0x58c, 0x2200, 4);
+        p(0x590, 0x21e0, 4);
+        for table in [0x5c0, 0x5e0].iter() {
+            p(*table, 0x2210, w);
+            p(*table + w, 5 | 1u64 << (8 * w - 1), w);
+        }
+        pe[0..2].copy_from_slice(b"MZ");
+        pe[0x80..0x84].copy_from_slice(b"PE\0\0");
+        pe[0x200..0x214].copy_from_slice(b"0123456789abcdefXXXX");
+        pe[0x470..0x485].copy_from_slice(b"KERNEL32.ExitProcess\0");
+        pe[0x500..0x512].copy_from_slice(b"test.dll\0run\0quit\0");
+        pe[0x600..0x60d].copy_from_slice(b"KERNEL32.dll\0");
+        pe[0x612..0x618].copy_from_slice(b"Sleep\0");
+        pe
+    }
+
+    fn check_binary(core: &mut Core, format: &str, base: u64, paddr: u64) {
+        assert_eq!(core.binaries.len(), 1);
+        let bin = &core.binaries[0];
+        assert_eq!(bin.format, format);
+        assert_eq!(bin.entry, base + 0x1000);
+        let names: Vec<&str> = bin.sections.iter().map(|s| &*s.name).collect();
+        assert_eq!(names, vec![".text", ".rdata", ".bss"]);
+        assert_eq!(bin.section(".rdata").unwrap().paddr, Some(paddr + 0x400));
+        assert_eq!(bin.section(".bss").unwrap().paddr, None);
+        let w = if base > 0xffff_ffff { 8 } else { 4 };
+        assert_eq!(
+            bin.imports,
+            vec![
+                Import {
+                    library: "KERNEL32.dll".to_string(),
+                    name: Some("Sleep".to_string()),
+                    ordinal: None,
+                    vaddr: base + 0x21e0
+                },
+                Import {
+                    library: "KERNEL32.dll".to_string(),
+                    name: None,
+                    ordinal: Some(5),
+                    vaddr: base + 0x21e0 + w
+                }
+            ]
+        );
+        assert_eq!(
+            bin.exports,
+            vec![
+                Export {
+                    name: Some("run".to_string()),
+                    ordinal: 1,
+                    vaddr: base + 0x1000,
+                    forwarder: None
+                },
+                Export {
+                    name: Some("quit".to_string()),
+                    ordinal: 2,
+                    vaddr: base + 0x2070,
+                    forwarder: Some("KERNEL32.ExitProcess".to_string())
+                }
+            ]
+        );
+        assert_eq!(bin.symbols.len(), 1);
+        assert_eq!(bin.symbol_at(base + 0x1000).unwrap().name, "run");
+        assert_eq!(bin.directories.len(), 3);
+        assert_eq!(bin.directory("IAT").unwrap().vaddr, base + 0x21e0);
+        let mut data = [0xff; 0x10];
+        core.io.vread(base + 0x1000, &mut data).unwrap();
+        assert_eq!(&data, b"0123456789abcdef");
+        // raw data beyond virtual size is not mapped.
+        assert!(core.io.vread(base + 0x1000, &mut [0; 0x11]).is_err());
+        let mut data = [0xff; 0x200];
+        core.io.vread(base + 0x2200, &mut data).unwrap();
+        assert_eq!(&data[..0xd], b"KERNEL32.dll\0");
+        assert_eq!(&data[0x100..], &[0; 0x100][..]);
+        let mut data = [0xff; 0x80];
+        core.io.vread(base + 0x3000, &mut data).unwrap();
+        assert_eq!(&data[..], &[0; 0x80][..]);
+        let mut data = [0; 2];
+        core.io.vread(base, &mut data).unwrap();
+        assert_eq!(&data, b"MZ");
+        let maps: Vec<(MapPerm, String)> = core.io.map_iter().map(|m| (m.perm, m.name.clone())).collect();
+        let (r, rw, rx) = (MapPerm::READ, MapPerm::READ | MapPerm::WRITE, MapPerm::READ | MapPerm::EXEC);
+        assert_eq!(
+            maps,
+            vec![
+                (r, "HEADER".to_string()),
+                (rx, ".text".to_string()),
+                (r, ".rdata".to_string()),
+                (r, ".rdata".to_string()),
+                (rw, ".bss".to_string())
+            ]
+        );
+    }
+
+    fn test_pe64_cb(path: &Path) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("open", &[format!("pe://{}", path.to_string_lossy())]);
+        check_binary(&mut core, "PE32+ (x86-64)", 0x1_4000_0000, 0);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_pe64() {
+        operate_on_file(&test_pe64_cb, &build_pe(true));
+    }
+
+    fn test_pe32_cb(path: &Path) {
+        let mut core = Core::new_no_colors();
+        load_pe(&mut core, &path.to_string_lossy(), IoMode::READ, Some(0x1000)).unwrap();
+        check_binary(&mut core, "PE32 (i386)", 0x40_0000, 0x1000);
+    }
+
+    #[test]
+    fn test_pe32() {
+        operate_on_file(&test_pe32_cb, &build_pe(false));
+    }
+
+    fn test_errors_cb(paths: &[&Path]) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        for path in paths {
+            core.run("open", &[format!("pe://{}", path.to_string_lossy())]);
+        }
+        assert_eq!(core.io.uri_iter().count(), 0);
+        assert_eq!(core.io.map_iter().count(), 0);
+        assert!(core.binaries.is_empty());
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to open file\n\
+             Not a PE file.\n\
+             Error: Failed to open file\n\
+             Unknown PE optional header magic.\n\
+             Error: Failed to open file\n\
+             Section is out of file bounds.\n\
+             Error: Failed to open file\n\
+             PE structure is not backed by file.\n\
+             Error: Failed to open file\n\
+             PE address is out of address space.\n\
+             Error: Failed to open file\n\
+             Segment is out of address space.\n"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut bad_magic = build_pe(true);
+        put(&mut bad_magic, 0x98, 0x107, 2);
+        let mut bad_section = build_pe(true);
+        put(&mut bad_section, 0x188 + 20, 0x6f8, 4);
+        let mut bad_imports = build_pe(true);
+        put(&mut bad_imports, 0x5c0, 0x3000, 8);
+        let mut bad_image_base = build_pe(true);
+        put(&mut bad_image_base, 0x98 + 24, 0xffff_ffff_ffff_f000, 8);
+        // only .bss reaches beyond the end of the address space.
+        let mut bad_segment = build_pe(true);
+        put(&mut bad_segment, 0x98 + 24, 0xffff_ffff_ffff_cff0, 8);
+        operate_on_files(&test_errors_cb, &[DATA, &bad_magic, &bad_section, &bad_imports, &bad_image_base, &bad_segment]);
+    }
+}
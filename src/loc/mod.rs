@@ -26,6 +26,6 @@ use std::sync::Arc;
 
 pub fn register_loc(core: &mut Core) {
     let history = Arc::new(Mutex::new(History::new()));
-    core.add_command("mode", "m", Arc::new(Mutex::new(Mode::with_history(history.clone()))));
-    core.add_command("seek", "s", Arc::new(Mutex::new(Seek::with_history(history))));
+    core.add_command("mode", "m", Arc::new(Mode::with_history(history.clone())));
+    core.add_command("seek", "s", Arc::new(Seek::with_history(history)));
 }
@@ -30,7 +30,7 @@ impl Mode {
 }
 
 impl Cmd for Mode {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
@@ -102,7 +102,7 @@ mod test_mode {
     fn test_mode_cb(path: &Path) {
         let mut core = Core::new_no_colors();
         let len = DATA.len() as u64;
-        let mut mode: Mode = Default::default();
+        let mode: Mode = Default::default();
         core.io.open(&path.to_string_lossy(), IoMode::READ).unwrap();
         core.io.map(0x0, 0x5000, len).unwrap();
         assert_eq!(core.get_loc(), 0x0);
@@ -131,7 +131,7 @@ mod test_mode {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut mode: Mode = Default::default();
+        let mode: Mode = Default::default();
         mode.run(&mut core, &[]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
         assert_eq!(core.stderr.utf8_string().unwrap(), "Arguments Error: Expected 1 argument(s), found 0.\n");
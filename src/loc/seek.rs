@@ -17,6 +17,7 @@
 
 use super::history::History;
 use crate::core::*;
+use crate::expr::*;
 use crate::helper::*;
 
 #[derive(Default)]
@@ -28,7 +29,7 @@ impl Seek {
     pub(super) fn with_history(history: MRc<History>) -> Self {
         Seek { history }
     }
-    fn backward(&mut self, core: &mut Core) {
+    fn backward(&self, core: &mut Core) {
         if let Some((mode, addr)) = self.history.lock().backward(core) {
             core.mode = mode;
             core.set_loc(addr);
@@ -36,7 +37,7 @@ impl Seek {
             error_msg(core, "Seek Error", "History is empty.");
         }
     }
-    fn forward(&mut self, core: &mut Core) {
+    fn forward(&self, core: &mut Core) {
         if let Some((mode, addr)) = self.history.lock().forward(core) {
             core.mode = mode;
             core.set_loc(addr);
@@ -44,14 +45,14 @@ impl Seek {
             error_msg(core, "Seek Error", "History is empty.");
         }
     }
-    fn add_loc(&mut self, core: &mut Core, offset: u64) {
+    fn add_loc(&self, core: &mut Core, offset: u64) {
         if let Some(loc) = core.get_loc().checked_add(offset) {
             self.set_loc(core, loc);
         } else {
             error_msg(core, "Seek Error", "Attempt to add with overflow.");
         }
     }
-    fn sub_loc(&mut self, core: &mut Core, offset: u64) {
+    fn sub_loc(&self, core: &mut Core, offset: u64) {
         if let Some(loc) = core.get_loc().checked_sub(offset) {
             self.set_loc(core, loc);
         } else {
@@ -59,14 +60,14 @@ impl Seek {
         }
     }
     #[inline]
-    fn set_loc(&mut self, core: &mut Core, offset: u64) {
+    fn set_loc(&self, core: &mut Core, offset: u64) {
         self.history.lock().add(core);
         core.set_loc(offset);
     }
 }
 
 impl Cmd for Seek {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
@@ -76,17 +77,17 @@ impl Cmd for Seek {
         } else if args[0] == "+" {
             self.forward(core)
         } else if args[0].starts_with('+') {
-            match str_to_num(&args[0][1..]) {
+            match eval(core, &args[0][1..]) {
                 Ok(offset) => self.add_loc(core, offset),
                 Err(e) => error_msg(core, "Seek Error", &e.to_string()),
             }
         } else if args[0].starts_with('-') {
-            match str_to_num(&args[0][1..]) {
+            match eval(core, &args[0][1..]) {
                 Ok(offset) => self.sub_loc(core, offset),
                 Err(e) => error_msg(core, "Seek Error", &e.to_string()),
             }
         } else {
-            match str_to_num(&args[0]) {
+            match eval(core, &args[0]) {
                 Ok(offset) => self.set_loc(core, offset),
                 Err(e) => error_msg(core, "Seek Error", &e.to_string()),
             }
@@ -139,7 +140,7 @@ mod test_seek {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut seek: Seek = Default::default();
+        let seek: Seek = Default::default();
         assert_eq!(core.mode, AddrMode::Phy);
         assert_eq!(core.get_loc(), 0x0);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
@@ -215,7 +216,7 @@ mod test_seek {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut seek: Seek = Default::default();
+        let seek: Seek = Default::default();
         assert_eq!(core.mode, AddrMode::Phy);
         assert_eq!(core.get_loc(), 0x0);
         seek.run(&mut core, &["-0x5".to_string()]);
@@ -241,12 +242,29 @@ mod test_seek {
         assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Seek Error\nAttempt to add with overflow.\n");
     }
 
+    #[test]
+    fn test_seek_expr() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("s", &["0x4000+0x1c*4".to_string()]);
+        assert_eq!(core.get_loc(), 0x4070);
+        core.run("s", &["$$ + 0x10".to_string()]);
+        assert_eq!(core.get_loc(), 0x4080);
+        core.run("s", &["+(2*8)".to_string()]);
+        assert_eq!(core.get_loc(), 0x4090);
+        core.run("s", &["(1+".to_string()]);
+        assert_eq!(core.get_loc(), 0x4090);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Seek Error\nunexpected end of expression\n");
+    }
+
     #[test]
     fn test_seek_invalid_arguments() {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut seek: Seek = Default::default();
+        let seek: Seek = Default::default();
         assert_eq!(core.mode, AddrMode::Phy);
         assert_eq!(core.get_loc(), 0x0);
 
@@ -0,0 +1,261 @@
+/*
+ * parser.rs: Parsing rair command lines.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::io;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Destination of the output of a [ParsedCommand].
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    /// Output goes to [Core::stdout](crate::Core::stdout).
+    Stdout,
+    /// `> file`: Output truncates file and is written to it.
+    Write(String),
+    /// `>> file`: Output is appended to file.
+    Append(String),
+    /// `| shellcmd`: Output is piped to the standard input of shell command.
+    Pipe(String),
+}
+
+/// Single command of a command line as returned by [parse_line].
+#[derive(Debug, PartialEq)]
+pub struct ParsedCommand {
+    pub name: String,
+    pub args: Vec<String>,
+    /// `@addr`: Expression evaluating to temporary location the command runs at.
+    pub at: Option<String>,
+    /// `~pattern`: Only output lines that contain pattern are kept.
+    pub grep: Option<String>,
+    pub output: Output,
+}
+
+struct Scanner {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+    // Reads a word, quotes group characters and backslash escapes the next character.
+    // Unquoted whitespace, `;` and `@` end the word.
+    fn word(&mut self) -> Result<String, String> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                c if c.is_whitespace() || c == ';' || c == '@' => break,
+                '\'' | '"' => {
+                    self.pos += 1;
+                    loop {
+                        match self.peek() {
+                            None => return Err(format!("Missing closing {}", c)),
+                            Some(q) if q == c => break,
+                            Some('\\') if c == '"' && matches!(self.chars.get(self.pos + 1), Some('"') | Some('\\')) => {
+                                word.push(self.chars[self.pos + 1]);
+                                self.pos += 1;
+                            }
+                            Some(q) => word.push(q),
+                        }
+                        self.pos += 1;
+                    }
+                }
+                '\\' => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(e) => word.push(e),
+                        None => return Err("Nothing to escape after `\\`".to_string()),
+                    }
+                }
+                c => word.push(c),
+            }
+            self.pos += 1;
+        }
+        Ok(word)
+    }
+    // Word that must follow special token such as `@` or `>`.
+    fn operand(&mut self, token: &str) -> Result<String, String> {
+        self.skip_spaces();
+        let word = self.word()?;
+        if word.is_empty() {
+            return Err(format!("Expected argument after `{}`", token));
+        }
+        Ok(word)
+    }
+    // Raw text up to the next unquoted `;`.
+    fn rest(&mut self) -> String {
+        let start = self.pos;
+        let mut quote = None;
+        while let Some(c) = self.peek() {
+            match (c, quote) {
+                (';', None) => break,
+                ('\'', None) | ('"', None) => quote = Some(c),
+                (c, Some(q)) if c == q => quote = None,
+                _ => (),
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect::<String>().trim().to_string()
+    }
+}
+
+/// Parse command line into list of commands. Commands are separated by `;`, each command is a
+/// name followed by arguments and optionally `@addr`, `~pattern` and either `> file`,
+/// `>> file` or `| shellcmd`. Arguments can be quoted using `'` or `"`. The characters `~`, `>`
+/// and `|` are only special at the beginning of a word so that `s 1|2` is a seek to an expression
+/// while `px 10 | less` is a pipe.
+pub fn parse_line(line: &str) -> Result<Vec<ParsedCommand>, String> {
+    let mut scanner = Scanner {
+        chars: line.chars().collect(),
+        pos: 0,
+    };
+    let mut commands = Vec::new();
+    loop {
+        let mut words = Vec::new();
+        let mut at = None;
+        let mut grep = None;
+        let mut output = Output::Stdout;
+        loop {
+            scanner.skip_spaces();
+            match scanner.peek() {
+                None | Some(';') => break,
+                Some('@') => {
+                    scanner.pos += 1;
+                    at = Some(scanner.operand("@")?);
+                }
+                Some('~') => {
+                    scanner.pos += 1;
+                    grep = Some(scanner.word()?);
+                }
+                Some('|') => {
+                    scanner.pos += 1;
+                    let shell = scanner.rest();
+                    if shell.is_empty() {
+                        return Err("Expected argument after `|`".to_string());
+                    }
+                    output = Output::Pipe(shell);
+                }
+                Some('>') => {
+                    scanner.pos += 1;
+                    output = if scanner.peek() == Some('>') {
+                        scanner.pos += 1;
+                        Output::Append(scanner.operand(">>")?)
+                    } else {
+                        Output::Write(scanner.operand(">")?)
+                    };
+                }
+                Some(_) => words.push(scanner.word()?),
+            }
+        }
+        if !words.is_empty() {
+            let name = words.remove(0);
+            commands.push(ParsedCommand { name, args: words, at, grep, output });
+        } else if at.is_some() || grep.is_some() || output != Output::Stdout {
+            return Err("Missing command name".to_string());
+        }
+        if scanner.peek().is_none() {
+            return Ok(commands);
+        }
+        scanner.pos += 1;
+    }
+}
+
+// Keeps only lines of data that contain pattern.
+pub(crate) fn grep(data: &[u8], pattern: &str) -> Vec<u8> {
+    let pattern = pattern.as_bytes();
+    data.split_inclusive(|c| *c == b'\n')
+        .filter(|line| pattern.is_empty() || line.windows(pattern.len()).any(|w| w == pattern))
+        .flatten()
+        .copied()
+        .collect()
+}
+
+// Runs shell command with data as its standard input, returns its standard output and standard error.
+pub(crate) fn pipe(shell: &str, data: Vec<u8>) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let (sh, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut child = Command::new(sh).arg(flag).arg(shell).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    // writing from another thread so that the child never blocks on a full output pipe.
+    let writer = thread::spawn(move || stdin.write_all(&data));
+    let output = child.wait_with_output()?;
+    // shell commands are allowed to exit without reading all of their input.
+    let _ = writer.join().unwrap();
+    Ok((output.stdout, output.stderr))
+}
+
+#[cfg(test)]
+mod test_parser {
+    use super::*;
+
+    fn cmd(name: &str, args: &[&str]) -> ParsedCommand {
+        ParsedCommand {
+            name: name.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            at: None,
+            grep: None,
+            output: Output::Stdout,
+        }
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("").unwrap(), vec![]);
+        assert_eq!(parse_line(" ;; ").unwrap(), vec![]);
+        assert_eq!(parse_line("px 0x10; s 1|2").unwrap(), vec![cmd("px", &["0x10"]), cmd("s", &["1|2"])]);
+        assert_eq!(
+            parse_line(r#"/ "hello world" 0x10;/ 'a;b' 5 ; / a\ b\; 1"#).unwrap(),
+            vec![cmd("/", &["hello world", "0x10"]), cmd("/", &["a;b", "5"]), cmd("/", &["a b;", "1"])]
+        );
+        assert_eq!(parse_line(r#"/ "\"x\\" 1"#).unwrap(), vec![cmd("/", &["\"x\\", "1"])]);
+        let mut px = cmd("px", &["16"]);
+        px.at = Some("$$+4".to_string());
+        px.grep = Some("0x".to_string());
+        px.output = Output::Append("out file".to_string());
+        assert_eq!(parse_line("px 16@$$+4 ~0x >> 'out file'").unwrap(), vec![px]);
+        let mut px = cmd("px", &["16"]);
+        px.at = Some("0x10".to_string());
+        px.output = Output::Pipe("grep ';' | wc -l".to_string());
+        let mut s = cmd("s", &["5"]);
+        s.output = Output::Write("x".to_string());
+        assert_eq!(parse_line("px 16 @ 0x10 | grep ';' | wc -l ; s 5 >x").unwrap(), vec![px, s]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_line("/ 'abc").err().unwrap(), "Missing closing '");
+        assert_eq!(parse_line("/ \"abc").err().unwrap(), "Missing closing \"");
+        assert_eq!(parse_line("/ abc\\").err().unwrap(), "Nothing to escape after `\\`");
+        assert_eq!(parse_line("px 10 @").err().unwrap(), "Expected argument after `@`");
+        assert_eq!(parse_line("px 10 > ;s").err().unwrap(), "Expected argument after `>`");
+        assert_eq!(parse_line("px 10 >>").err().unwrap(), "Expected argument after `>>`");
+        assert_eq!(parse_line("px 10 | ").err().unwrap(), "Expected argument after `|`");
+        assert_eq!(parse_line("@ 0x10").err().unwrap(), "Missing command name");
+    }
+
+    #[test]
+    fn test_grep() {
+        assert_eq!(grep(b"abc\nbcd\ncde", "bc"), b"abc\nbcd\n".to_vec());
+        assert_eq!(grep(b"abc\nbcd\ncde", "e"), b"cde".to_vec());
+        assert_eq!(grep(b"abc\n", ""), b"abc\n".to_vec());
+    }
+}
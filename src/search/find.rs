@@ -0,0 +1,449 @@
+/*
+ * find.rs: commands for searching the address space for patterns.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::pattern::Pattern;
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use rair_io::IoError;
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
+use std::mem;
+
+const HITS_SPACE: &str = "search";
+
+/// Location of a single match returned by [search].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Hit {
+    /// Address of the first byte of the match, in the address space the search was done in.
+    pub addr: u64,
+    /// Number of matched bytes.
+    pub size: u64,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:08x}\t0x{:x}", self.addr, self.size)
+    }
+}
+
+// Reads chunk at addr as list of contiguous (address, data) runs.
+pub(super) fn read_runs(core: &mut Core, addr: u64, size: u64) -> Result<Vec<(u64, Vec<u8>)>, IoError> {
+    let mut data = vec![0; size as usize];
+    let result = match core.mode {
+        AddrMode::Phy => core.io.pread(addr, &mut data),
+        AddrMode::Vir => core.io.vread(addr, &mut data),
+    };
+    if result.is_ok() {
+        return Ok(vec![(addr, data)]);
+    }
+    let sparce: BTreeMap<u64, u8> = match core.mode {
+        AddrMode::Phy => core.io.pread_sparce(addr, size)?,
+        AddrMode::Vir => core.io.vread_sparce(addr, size)?,
+    };
+    let mut runs: Vec<(u64, Vec<u8>)> = Vec::new();
+    for (k, v) in sparce {
+        if let Some((start, run)) = runs.last_mut() {
+            if *start + run.len() as u64 == k {
+                run.push(v);
+                continue;
+            }
+        }
+        runs.push((k, vec![v]));
+    }
+    Ok(runs)
+}
+
+/// Search for *pattern* in the range [*addr*, *addr* + *size*) of the address space
+/// selected by [Core::mode]. Data is read in chunks of `search.chunkSize` bytes, unmapped
+/// gaps are skipped and matches never span a gap. Regular expression matches don't overlap,
+/// those longer than 0x100 bytes might be truncated where they cross a chunk boundary.
+pub fn search(core: &mut Core, pattern: &Pattern, addr: u64, size: u64) -> Result<Vec<Hit>, IoError> {
+    let chunk = cmp::max(core.env.read().get_u64("search.chunkSize").unwrap(), 1);
+    let overlap = pattern.overlap();
+    let mut hits: Vec<Hit> = Vec::new();
+    // tail of the previously scanned run and its address.
+    let mut carry_addr = 0;
+    let mut carry: Vec<u8> = Vec::new();
+    let end = addr.saturating_add(size);
+    let mut start = addr;
+    while start < end {
+        let chunk_size = cmp::min(chunk, end - start);
+        for (run_addr, run) in read_runs(core, start, chunk_size)? {
+            // data of the next chunk might continue this run.
+            let open = run_addr + run.len() as u64 == start + chunk_size && start + chunk_size < end;
+            let mut buffer;
+            let base;
+            if !carry.is_empty() && carry_addr + carry.len() as u64 == run_addr {
+                base = carry_addr;
+                buffer = carry;
+                buffer.extend(run);
+            } else {
+                base = run_addr;
+                buffer = run;
+            }
+            let new_data = (run_addr - base) as usize;
+            for (offset, len) in pattern.find(&buffer) {
+                let hit = Hit {
+                    addr: base + offset as u64,
+                    size: len as u64,
+                };
+                if pattern.is_regex() {
+                    if let Some(last) = hits.last() {
+                        if last.addr + last.size > hit.addr {
+                            continue;
+                        }
+                    }
+                    // match reaching the end of the run might go on in the next chunk, so it is
+                    // left in the carried bytes to be matched again.
+                    if open && offset + len == buffer.len() && len <= overlap {
+                        break;
+                    }
+                } else {
+                    // matches that end inside the carried bytes were found in the previous run.
+                    if offset + len <= new_data {
+                        continue;
+                    }
+                    if let Some(last) = hits.last() {
+                        if last.addr >= hit.addr {
+                            continue;
+                        }
+                    }
+                }
+                hits.push(hit);
+            }
+            let mut keep = cmp::min(overlap, buffer.len());
+            if pattern.is_regex() {
+                // bytes of reported hits are never matched again.
+                if let Some(last) = hits.last() {
+                    let hit_end = last.addr + last.size;
+                    if hit_end > base {
+                        keep = cmp::min(keep, buffer.len() - (hit_end - base) as usize);
+                    }
+                }
+            }
+            carry_addr = base + (buffer.len() - keep) as u64;
+            carry = buffer.split_off(buffer.len() - keep);
+        }
+        start += chunk_size;
+    }
+    Ok(hits)
+}
+
+fn search_cmd(core: &mut Core, args: &[String], pattern: Result<Pattern, String>) {
+    let pattern = match pattern {
+        Ok(pattern) => pattern,
+        Err(e) => return error_msg(core, "Failed to parse pattern", &format!("{}.", e)),
+    };
+    let size = match eval(core, &args[1]) {
+        Ok(size) => size,
+        Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+    };
+    if size == 0 {
+        return;
+    }
+    let loc = core.get_loc();
+    let hits = match search(core, &pattern, loc, size) {
+        Ok(hits) => hits,
+        Err(e) => return error_msg(core, "Search failed", &e.to_string()),
+    };
+    for hit in &hits {
+        writeln!(core.stdout, "{}", hit).unwrap();
+    }
+    flag_hits(core, &hits);
+}
+
+// Hits of the last search are kept as flags `hit.0`, `hit.1` ... in the `search` flagspace so that
+// they can be used as addresses, flags added for older searches are dropped.
+fn flag_hits(core: &mut Core, hits: &[Hit]) {
+    for name in mem::take(&mut core.hits) {
+        // the user might have removed the flag and added one with the same name elsewhere.
+        if core.flags.get(&name).map_or(false, |flag| flag.space == HITS_SPACE) {
+            core.flags.remove(&name);
+        }
+    }
+    let space = core.flags.space().to_string();
+    core.flags.set_space(HITS_SPACE);
+    for (i, hit) in hits.iter().enumerate() {
+        let name = format!("hit.{}", i);
+        // user flags that happen to have the same name are left as they are.
+        if core.flags.add(&name, hit.addr, hit.size, core.mode).is_ok() {
+            core.hits.push(name);
+        }
+    }
+    core.flags.set_space(&space);
+}
+
+#[derive(Default)]
+pub struct SearchHex {}
+
+impl SearchHex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for SearchHex {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        search_cmd(core, args, Pattern::from_hex(&args[0]));
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"searchHex",
+            &"/x",
+            vec![(
+                "[hexpairs] [size]",
+                "Search for hexpairs (`?` is wildcard nibble, `[hexpairs]:[mask]` for bit masks) in [size] bytes starting at current location.",
+            )],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct SearchAscii {}
+
+impl SearchAscii {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for SearchAscii {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        search_cmd(core, args, Ok(Pattern::from_ascii(&args[0])));
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"searchAscii",
+            &"/",
+            vec![("[string] [size]", "Search for ascii string in [size] bytes starting at current location.")],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct SearchWide {}
+
+impl SearchWide {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for SearchWide {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        search_cmd(core, args, Ok(Pattern::from_utf16(&args[0])));
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"searchWide",
+            &"/w",
+            vec![("[string] [size]", "Search for UTF-16LE string in [size] bytes starting at current location.")],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct SearchRegex {}
+
+impl SearchRegex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for SearchRegex {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 2 {
+            expect(core, args.len() as u64, 2);
+            return;
+        }
+        search_cmd(core, args, Pattern::from_regex(&args[0]));
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"searchRegex",
+            &"/r",
+            vec![(
+                "[regex] [size]",
+                "Search for regular expression in [size] bytes starting at current location, matches longer than 0x100 bytes might be truncated.",
+            )],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_search {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("/x");
+        core.help("/");
+        core.help("/w");
+        core.help("/r");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Commands: [searchHex | /x]\n\n\
+             Usage:\n\
+             /x [hexpairs] [size]\tSearch for hexpairs (`?` is wildcard nibble, `[hexpairs]:[mask]` for bit masks) in [size] bytes starting at current location.\n\
+             Commands: [searchAscii | /]\n\n\
+             Usage:\n\
+             / [string] [size]\tSearch for ascii string in [size] bytes starting at current location.\n\
+             Commands: [searchWide | /w]\n\n\
+             Usage:\n\
+             /w [string] [size]\tSearch for UTF-16LE string in [size] bytes starting at current location.\n\
+             Commands: [searchRegex | /r]\n\n\
+             Usage:\n\
+             /r [regex] [size]\tSearch for regular expression in [size] bytes starting at current location, matches longer than 0x100 bytes might be truncated.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_search_phy() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        // pattern spans the boundary between both files
+        core.io.pwrite(0x1e, b"rair").unwrap();
+        core.io.pwrite(0x30, b"r\0a\0i\0r\0").unwrap();
+        core.env.write().set_u64("search.chunkSize", 0x7, &mut Core::new_no_colors()).unwrap();
+        let hits = search(&mut core, &Pattern::from_ascii("rair"), 0, 0x40).unwrap();
+        assert_eq!(hits, vec![Hit { addr: 0x1e, size: 4 }]);
+        core.run("/w", &["rair".to_string(), "0x40".to_string()]);
+        core.run("/x", &["72??69:ff00ff".to_string(), "0x40".to_string()]);
+        core.run("/r", &["r.i".to_string(), "0x40".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x00000030\t0x8\n\
+             0x0000001e\t0x3\n\
+             0x0000001e\t0x3\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_search_regex_chunks() {
+        let mut core = Core::new_no_colors();
+        core.io.open("malloc://0x800", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0xc, &[b'a'; 8]).unwrap();
+        core.io.pwrite(0x1f0, &[b'b'; 0x20]).unwrap();
+        core.io.pwrite(0x400, &[b'c'; 0x300]).unwrap();
+        let pattern = Pattern::from_regex("a+|b+|c+").unwrap();
+        // matches going on in the next chunk are not truncated.
+        core.env.write().set_u64("search.chunkSize", 0x10, &mut Core::new_no_colors()).unwrap();
+        assert_eq!(search(&mut core, &pattern, 0, 0x20).unwrap(), vec![Hit { addr: 0xc, size: 8 }]);
+        // hits don't overlap when the carried bytes start inside the last hit.
+        core.env.write().set_u64("search.chunkSize", 0x200, &mut Core::new_no_colors()).unwrap();
+        assert_eq!(search(&mut core, &pattern, 0x100, 0x300).unwrap(), vec![Hit { addr: 0x1f0, size: 0x20 }]);
+        // matches longer than the window are cut at chunk boundaries.
+        core.env.write().set_u64("search.chunkSize", 0x100, &mut Core::new_no_colors()).unwrap();
+        assert_eq!(
+            search(&mut core, &pattern, 0x400, 0x300).unwrap(),
+            vec![Hit { addr: 0x400, size: 0x200 }, Hit { addr: 0x600, size: 0x100 }]
+        );
+    }
+
+    #[test]
+    fn test_search_vir() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x0, b"hello world hello").unwrap();
+        core.io.map(0x0, 0x1000, 0x8).unwrap();
+        core.io.map(0x8, 0x2008, 0x9).unwrap();
+        core.mode = AddrMode::Vir;
+        core.set_loc(0x1000);
+        core.run("/", &["hello".to_string(), "0x1020".to_string()]);
+        core.run("/", &["world".to_string(), "0x1020".to_string()]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "0x00001000\t0x5\n0x0000200c\t0x5\n");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_search_hits() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x100", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x0, b"hello world hello").unwrap();
+        core.io.map(0x0, 0x1000, 0x20).unwrap();
+        core.run_line("/ hello 0x20; s hit.1");
+        assert_eq!(core.get_loc(), 0xc);
+        // only flags added by the previous search are dropped.
+        core.run_line("fs search; f mine 0x10; fs default");
+        core.run_line("m vir; s 0x1000; / world 0x20; s hit.0; fs");
+        assert_eq!(core.get_loc(), 0x1006);
+        assert!(core.flags.get("hit.1").is_none());
+        assert_eq!(core.flags.get("mine").unwrap().space, "search");
+        assert_eq!(core.flags.space(), "default");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x00000000\t0x5\n\
+             0x0000000c\t0x5\n\
+             0x00001006\t0x5\n\
+             * default\n\
+             \x20 search\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_search_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("/x", &["123".to_string(), "0x10".to_string()]);
+        core.run("/r", &["(".to_string()]);
+        core.run("/", &["abc".to_string(), "ff".to_string()]);
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to parse pattern\n\
+             Data can't have odd number of digits.\n\
+             Arguments Error: Expected 2 argument(s), found 1.\n\
+             Error: Failed to parse size\n\
+             invalid digit found in string\n"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+/*
+ * search: commands for searching the address space.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+mod find;
+mod pattern;
+mod strings;
+
+use self::find::*;
+pub use self::find::{search, Hit};
+pub use self::pattern::Pattern;
+use self::strings::*;
+pub use self::strings::{strings, Encoding};
+use crate::core::Core;
+use std::sync::Arc;
+
+pub fn register_search(core: &mut Core) {
+    core.env.write().add_u64("search.chunkSize", 0x10000, "Number of bytes read at a time by search commands").unwrap();
+    core.add_command("searchHex", "/x", Arc::new(SearchHex::new()));
+    core.add_command("searchAscii", "/", Arc::new(SearchAscii::new()));
+    core.add_command("searchWide", "/w", Arc::new(SearchWide::new()));
+    core.add_command("searchRegex", "/r", Arc::new(SearchRegex::new()));
+    let strings = Arc::new(Strings::new(core));
+    core.add_command("strings", "", strings);
+}
@@ -0,0 +1,181 @@
+/*
+ * pattern.rs: Patterns that can be searched for in the address space.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use regex::bytes::Regex;
+
+// Regular expression matches that are longer than this might be truncated
+// or missed if they happen to span two different read chunks.
+const REGEX_WINDOW: usize = 0x100;
+
+/// Byte pattern to be searched for using [search](crate::search).
+pub enum Pattern {
+    /// Exact sequence of bytes.
+    Bytes(Vec<u8>),
+    /// Sequence of bytes where only bits set in the mask (second vector) are compared.
+    Masked(Vec<u8>, Vec<u8>),
+    /// Regular expression matched against raw bytes.
+    Regex(Regex),
+}
+
+fn parse_nibble(c: char) -> Result<(u8, u8), String> {
+    if c == '?' {
+        return Ok((0, 0));
+    }
+    match c.to_digit(16) {
+        Some(d) => Ok((d as u8, 0xf)),
+        None => Err(format!("Invalid hex digit `{}`", c)),
+    }
+}
+
+fn parse_hexpairs(hex: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    if hex.is_empty() {
+        return Err("Empty pattern".to_string());
+    }
+    if hex.len() % 2 != 0 {
+        return Err("Data can't have odd number of digits".to_string());
+    }
+    let chars: Vec<char> = hex.chars().collect();
+    let mut bytes = Vec::with_capacity(chars.len() / 2);
+    let mut mask = Vec::with_capacity(chars.len() / 2);
+    for pair in chars.chunks(2) {
+        let (hi, hi_mask) = parse_nibble(pair[0])?;
+        let (lo, lo_mask) = parse_nibble(pair[1])?;
+        bytes.push(hi << 4 | lo);
+        mask.push(hi_mask << 4 | lo_mask);
+    }
+    Ok((bytes, mask))
+}
+
+impl Pattern {
+    /// Parse hexpairs into [Pattern], `?` can be used as wildcard nibble,
+    /// while `[hexpairs]:[mask]` compares only the bits set in the mask.
+    pub fn from_hex(hex: &str) -> Result<Pattern, String> {
+        let mut split = hex.splitn(2, ':');
+        let (bytes, mut mask) = parse_hexpairs(split.next().unwrap())?;
+        if let Some(explicit) = split.next() {
+            let (explicit, explicit_mask) = parse_hexpairs(explicit)?;
+            if explicit_mask.iter().any(|m| *m != 0xff) {
+                return Err("Mask can't have wildcards".to_string());
+            }
+            if explicit.len() != bytes.len() {
+                return Err("Mask and pattern must be of the same size".to_string());
+            }
+            for (m, e) in mask.iter_mut().zip(explicit) {
+                *m &= e;
+            }
+        }
+        if mask.iter().all(|m| *m == 0xff) {
+            Ok(Pattern::Bytes(bytes))
+        } else {
+            Ok(Pattern::Masked(bytes, mask))
+        }
+    }
+    /// Pattern matching ASCII (or more generally UTF-8) encoded string.
+    pub fn from_ascii(s: &str) -> Pattern {
+        Pattern::Bytes(s.as_bytes().to_vec())
+    }
+    /// Pattern matching UTF-16 little endian encoded string.
+    pub fn from_utf16(s: &str) -> Pattern {
+        Pattern::Bytes(s.encode_utf16().flat_map(|c| c.to_le_bytes().to_vec()).collect())
+    }
+    /// Pattern matching regular expression over raw bytes.
+    pub fn from_regex(re: &str) -> Result<Pattern, String> {
+        match Regex::new(re) {
+            Ok(regex) => Ok(Pattern::Regex(regex)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+    // Number of bytes from the end of one chunk that must be prepended
+    // to the next chunk so that matches spanning both are not missed.
+    pub(super) fn overlap(&self) -> usize {
+        match self {
+            Pattern::Bytes(b) => b.len().saturating_sub(1),
+            Pattern::Masked(b, _) => b.len().saturating_sub(1),
+            Pattern::Regex(_) => REGEX_WINDOW,
+        }
+    }
+    // Matches of regular expressions have variable length and never overlap each other.
+    pub(super) fn is_regex(&self) -> bool {
+        match self {
+            Pattern::Regex(_) => true,
+            _ => false,
+        }
+    }
+    // Returns list of (offset, size) for all matches inside data.
+    pub(super) fn find(&self, data: &[u8]) -> Vec<(usize, usize)> {
+        match self {
+            Pattern::Bytes(b) => {
+                if b.is_empty() || b.len() > data.len() {
+                    return Vec::new();
+                }
+                data.windows(b.len()).enumerate().filter(|(_, w)| w == b).map(|(i, _)| (i, b.len())).collect()
+            }
+            Pattern::Masked(b, m) => {
+                if b.is_empty() || b.len() > data.len() {
+                    return Vec::new();
+                }
+                data.windows(b.len())
+                    .enumerate()
+                    .filter(|(_, w)| w.iter().zip(b).zip(m).all(|((x, y), mask)| x & mask == y & mask))
+                    .map(|(i, _)| (i, b.len()))
+                    .collect()
+            }
+            Pattern::Regex(re) => re.find_iter(data).filter(|m| m.end() != m.start()).map(|m| (m.start(), m.end() - m.start())).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_pattern {
+    use super::*;
+    #[test]
+    fn test_from_hex() {
+        match Pattern::from_hex("0a1B").unwrap() {
+            Pattern::Bytes(b) => assert_eq!(b, vec![0x0a, 0x1b]),
+            _ => panic!("Expected exact bytes"),
+        }
+        match Pattern::from_hex("0a?b??").unwrap() {
+            Pattern::Masked(b, m) => {
+                assert_eq!(b, vec![0x0a, 0x0b, 0x00]);
+                assert_eq!(m, vec![0xff, 0x0f, 0x00]);
+            }
+            _ => panic!("Expected masked bytes"),
+        }
+        match Pattern::from_hex("1234:f0ff").unwrap() {
+            Pattern::Masked(b, m) => {
+                assert_eq!(b, vec![0x12, 0x34]);
+                assert_eq!(m, vec![0xf0, 0xff]);
+            }
+            _ => panic!("Expected masked bytes"),
+        }
+        assert_eq!(Pattern::from_hex("123").err().unwrap(), "Data can't have odd number of digits");
+        assert_eq!(Pattern::from_hex("").err().unwrap(), "Empty pattern");
+        assert_eq!(Pattern::from_hex("12x4").err().unwrap(), "Invalid hex digit `x`");
+        assert_eq!(Pattern::from_hex("1234:ff").err().unwrap(), "Mask and pattern must be of the same size");
+        assert_eq!(Pattern::from_hex("1234:ff?f").err().unwrap(), "Mask can't have wildcards");
+    }
+    #[test]
+    fn test_find() {
+        let data = b"abcaXcabcAbc";
+        assert_eq!(Pattern::from_ascii("abc").find(data), vec![(0, 3), (6, 3)]);
+        assert_eq!(Pattern::from_hex("61??63").unwrap().find(data), vec![(0, 3), (3, 3), (6, 3)]);
+        assert_eq!(Pattern::from_hex("616263:dfffff").unwrap().find(data), vec![(0, 3), (6, 3), (9, 3)]);
+        assert_eq!(Pattern::from_regex("[aA]b+c").unwrap().find(data), vec![(0, 3), (6, 3), (9, 3)]);
+        assert_eq!(Pattern::from_utf16("ab").find(b"\x00a\x00b\x00a\x00"), vec![(1, 4)]);
+        assert_eq!(Pattern::from_ascii("abc").find(b"ab"), vec![]);
+        assert!(Pattern::from_regex("(").is_err());
+    }
+}
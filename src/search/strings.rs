@@ -0,0 +1,362 @@
+/*
+ * strings.rs: commands for extracting strings from the address space.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use super::find::read_runs;
+use crate::core::*;
+use crate::expr::*;
+use crate::helper::*;
+use rair_env::Environment;
+use rair_io::IoError;
+use std::cmp;
+use std::fmt;
+use std::io::Write;
+use std::str;
+
+// Strings longer than this many bytes are split, so that data carried between chunks
+// and scanned again stays bounded.
+const MAX_STRING: usize = 0x1000;
+
+/// Encoding of strings found by [strings].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    Ascii,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Ascii => write!(f, "ascii"),
+            Encoding::Utf8 => write!(f, "utf8"),
+            Encoding::Utf16Le => write!(f, "utf16le"),
+            Encoding::Utf16Be => write!(f, "utf16be"),
+        }
+    }
+}
+
+// String found inside a buffer, start and end are offsets into that buffer.
+struct Found {
+    start: usize,
+    end: usize,
+    encoding: Encoding,
+    text: String,
+}
+
+fn printable(c: char) -> bool {
+    c == '\t' || !c.is_control()
+}
+
+// Decodes UTF-8 character at the beginning of data.
+fn decode_utf8(data: &[u8]) -> Option<(char, usize)> {
+    let width = match data[0] {
+        0x00..=0x7f => 1,
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return None,
+    };
+    if width > data.len() {
+        return None;
+    }
+    let s = str::from_utf8(&data[..width]).ok()?;
+    Some((s.chars().next().unwrap(), width))
+}
+
+fn scan_utf8(data: &[u8], found: &mut Vec<Found>) {
+    let mut i = 0;
+    let mut start = 0;
+    let mut text = String::new();
+    while i <= data.len() {
+        let c = if i < data.len() { decode_utf8(&data[i..]) } else { None };
+        match c {
+            Some((c, width)) if printable(c) => {
+                if text.is_empty() {
+                    start = i;
+                }
+                text.push(c);
+                i += width;
+            }
+            _ => {
+                if !text.is_empty() {
+                    let encoding = if text.is_ascii() { Encoding::Ascii } else { Encoding::Utf8 };
+                    found.push(Found { start, end: i, encoding, text });
+                }
+                text = String::new();
+                i += 1;
+            }
+        }
+    }
+}
+
+// Only characters below U+0800 are accepted so that random byte pairs are not reported
+// as CJK text.
+fn scan_utf16(data: &[u8], first: usize, big_endian: bool, found: &mut Vec<Found>) {
+    let mut start = first;
+    let mut text = String::new();
+    let mut i = first;
+    while i <= data.len() {
+        let unit = if i + 2 <= data.len() {
+            let unit = [data[i], data[i + 1]];
+            Some(if big_endian { u16::from_be_bytes(unit) } else { u16::from_le_bytes(unit) })
+        } else {
+            None
+        };
+        match unit.and_then(|u| std::char::from_u32(u32::from(u))) {
+            Some(c) if c < '\u{800}' && printable(c) => {
+                if text.is_empty() {
+                    start = i;
+                }
+                text.push(c);
+            }
+            _ => {
+                if !text.is_empty() {
+                    let encoding = if big_endian { Encoding::Utf16Be } else { Encoding::Utf16Le };
+                    found.push(Found { start, end: i, encoding, text });
+                }
+                text = String::new();
+            }
+        }
+        i += 2;
+    }
+}
+
+// Scans contiguous data for strings of all encodings. Strings are sorted by address and
+// strings overlapping longer or earlier ones are dropped. Also returns offset of the first
+// string (of any length) that might continue after the end of data.
+fn scan(data: &[u8], min_len: usize) -> (Vec<Found>, usize) {
+    let mut found = Vec::new();
+    let mut le = Vec::new();
+    scan_utf8(data, &mut found);
+    for first in 0..2 {
+        scan_utf16(data, first, false, &mut le);
+        scan_utf16(data, first, true, &mut found);
+    }
+    let tail = found.iter().chain(le.iter()).filter(|s| s.end + 4 > data.len()).map(|s| s.start).min().unwrap_or(data.len());
+    found.retain(|s| s.text.chars().count() >= min_len);
+    le.retain(|s| s.text.chars().count() >= min_len);
+    // `\0a\0b\0c\0` is both utf16be and utf16le shifted by one byte, prefer little endian.
+    found.retain(|s| s.encoding != Encoding::Utf16Be || !le.iter().any(|l| l.start == s.start + 1 && l.text == s.text));
+    found.append(&mut le);
+    found.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    let mut end = 0;
+    found.retain(|s| {
+        if s.start < end {
+            return false;
+        }
+        end = s.end;
+        true
+    });
+    (found, tail)
+}
+
+// Appends strings found in data to result. Unless data is complete, strings that might
+// continue in the next chunk are not reported and the offset of the data that must be
+// rescanned is returned.
+fn report(result: &mut Vec<(u64, Encoding, String)>, base: u64, data: &[u8], min_len: usize, complete: bool) -> usize {
+    let (found, tail) = scan(data, min_len);
+    let keep = if complete { data.len() } else { cmp::min(tail, data.len().saturating_sub(3)) };
+    for s in found {
+        if s.start < keep {
+            result.push((base + s.start as u64, s.encoding, s.text));
+        }
+    }
+    keep
+}
+
+/// Extract strings of at least *min_len* characters from the range [*addr*, *addr* + *size*) in
+/// the address space selected by [Core::mode]. Strings never span unmapped gaps and strings
+/// longer than 0x1000 bytes are split.
+pub fn strings(core: &mut Core, addr: u64, size: u64, min_len: u64) -> Result<Vec<(u64, Encoding, String)>, IoError> {
+    let chunk = cmp::max(core.env.read().get_u64("search.chunkSize").unwrap(), 1);
+    let min_len = cmp::max(min_len, 1) as usize;
+    let mut result = Vec::new();
+    // Data that might contain strings not yet terminated.
+    let mut carry_addr = 0;
+    let mut carry: Vec<u8> = Vec::new();
+    let end = addr.saturating_add(size);
+    let mut start = addr;
+    while start < end {
+        let chunk_size = cmp::min(chunk, end - start);
+        for (run_addr, run) in read_runs(core, start, chunk_size)? {
+            if !carry.is_empty() && carry_addr + carry.len() as u64 != run_addr {
+                report(&mut result, carry_addr, &carry, min_len, true);
+                carry.clear();
+            }
+            if carry.is_empty() {
+                carry_addr = run_addr;
+            }
+            carry.extend(run);
+            let keep = report(&mut result, carry_addr, &carry, min_len, false);
+            carry_addr += keep as u64;
+            carry.drain(..keep);
+            // carried data starts at the unfinished string and at most 3 more bytes follow it.
+            if carry.len() > MAX_STRING + 3 {
+                report(&mut result, carry_addr, &carry, min_len, true);
+                carry_addr += carry.len() as u64;
+                carry.clear();
+            }
+        }
+        start += chunk_size;
+    }
+    report(&mut result, carry_addr, &carry, min_len, true);
+    Ok(result)
+}
+
+fn positive(_: &str, value: u64, _: &Environment<Core>, _: &mut Core) -> bool {
+    value != 0
+}
+
+pub struct Strings {}
+
+impl Strings {
+    pub fn new(core: &mut Core) -> Self {
+        let env = core.env.clone();
+        env.write()
+            .add_u64_with_cb("strings.minLength", 4, "Minimum number of characters in strings reported by `strings` command", core, positive)
+            .unwrap();
+        Strings {}
+    }
+}
+
+impl Cmd for Strings {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 1 {
+            expect(core, args.len() as u64, 1);
+            return;
+        }
+        let size = match eval(core, &args[0]) {
+            Ok(size) => size,
+            Err(e) => return error_msg(core, "Failed to parse size", &e.to_string()),
+        };
+        let loc = core.get_loc();
+        let min_len = core.env.read().get_u64("strings.minLength").unwrap();
+        let found = match strings(core, loc, size, min_len) {
+            Ok(found) => found,
+            Err(e) => return error_msg(core, "Read Failed", &e.to_string()),
+        };
+        for (addr, encoding, text) in found {
+            writeln!(core.stdout, "0x{:08x} {} {}", addr, encoding, text).unwrap();
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"strings",
+            &"",
+            vec![(
+                "[size]",
+                "Print ascii, utf8, utf16le and utf16be strings found in [size] bytes starting at current location, strings longer than 0x1000 bytes are split.",
+            )],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_strings {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("strings");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [strings]\n\n\
+             Usage:\n\
+             strings [size]\tPrint ascii, utf8, utf16le and utf16be strings found in [size] bytes starting at current location, strings longer than 0x1000 bytes are split.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_scan() {
+        let (found, _) = scan(b"\x01hello\x00\x00w\x00o\x00r\x00l\x00d\x00\x00\x00b\x00y\x00e\x00s\xffh\xc3\xa9llo", 4);
+        let found: Vec<(usize, Encoding, &str)> = found.iter().map(|s| (s.start, s.encoding, &*s.text)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, Encoding::Ascii, "hello"),
+                (8, Encoding::Utf16Le, "world"),
+                (19, Encoding::Utf16Be, "byes"),
+                (28, Encoding::Utf8, "héllo")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open("malloc://0x10", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.open_at("malloc://0x10", IoMode::READ | IoMode::WRITE, 0x30).unwrap();
+        core.io.pwrite(0x0, b"\x00abc\x00rair is cool\x00\x00\x00\xd8\xa8\xd8\xa7\xd8\xa8\x00").unwrap();
+        // two strings separated by a gap are not merged
+        core.io.pwrite(0x30, b"tail").unwrap();
+        core.io.pwrite(0x1c, b"head").unwrap();
+        core.env.write().set_u64("search.chunkSize", 3, &mut Core::new_no_colors()).unwrap();
+        core.run("strings", &["0x40".to_string()]);
+        core.env.write().set_u64("strings.minLength", 3, &mut Core::new_no_colors()).unwrap();
+        core.run("strings", &["0x20".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x00000005 ascii rair is cool\n\
+             0x0000001c ascii head\n\
+             0x00000030 ascii tail\n\
+             0x00000001 ascii abc\n\
+             0x00000005 ascii rair is cool\n\
+             0x00000014 utf8 باب\n\
+             0x0000001c ascii head\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_strings_long() {
+        let mut core = Core::new_no_colors();
+        core.io.open("malloc://0x3000", IoMode::READ | IoMode::WRITE).unwrap();
+        core.io.pwrite(0x0, &[b'a'; 0x1100]).unwrap();
+        core.io.pwrite(0x1800, &[b'b'; MAX_STRING]).unwrap();
+        core.env.write().set_u64("search.chunkSize", 0x10, &mut Core::new_no_colors()).unwrap();
+        let found: Vec<(u64, usize)> = strings(&mut core, 0, 0x3000, 4).unwrap().iter().map(|(addr, _, text)| (*addr, text.len())).collect();
+        // the first string is split at the end of the chunk where it grew too long.
+        assert_eq!(found, vec![(0, 0x1010), (0x1010, 0xf0), (0x1800, MAX_STRING)]);
+    }
+
+    #[test]
+    fn test_strings_errors() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run("strings", &[]);
+        core.run("strings", &["0x1x".to_string()]);
+        assert!(core.env.write().set_u64("strings.minLength", 0, &mut Core::new_no_colors()).is_err());
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Arguments Error: Expected 1 argument(s), found 0.\n\
+             Error: Failed to parse size\n\
+             invalid digit found in string\n"
+        );
+    }
+}
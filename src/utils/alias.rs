@@ -0,0 +1,196 @@
+/*
+ * alias.rs: Commands for defining command aliases.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::core::*;
+use crate::helper::*;
+use crate::parser::*;
+use std::io::Write;
+use std::sync::Arc;
+
+// Command registered under the name of an alias.
+struct Aliased {
+    name: String,
+    line: String,
+}
+
+impl Cmd for Aliased {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        let mut commands = match parse_line(&self.line) {
+            Ok(commands) => commands,
+            Err(e) => return error_msg(core, "Failed to parse command", &format!("{}.", e)),
+        };
+        if let Some(last) = commands.last_mut() {
+            last.args.extend_from_slice(args);
+        }
+        for command in &commands {
+            core.run_parsed(command);
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        writeln!(core.stdout, "{} is an alias for `{}`.", self.name, self.line).unwrap();
+    }
+}
+
+// Registers *name* as a command running *line*, replacing the old alias with the same name.
+pub(super) fn add_alias(core: &mut Core, name: &str, line: &str) -> Result<(), String> {
+    if let Err(e) = parse_line(line) {
+        return Err(format!("{}.", e));
+    }
+    let commands = core.commands();
+    let mut commands = commands.lock();
+    if core.aliases.contains_key(name) {
+        commands.remove_command(name);
+    }
+    let aliased = Aliased {
+        name: name.to_string(),
+        line: line.to_string(),
+    };
+    if !commands.add_command(name, Arc::new(aliased)) {
+        return Err(format!("Command `{}` already exists.", name));
+    }
+    core.aliases.insert(name.to_string(), line.to_string());
+    Ok(())
+}
+
+// Returns false if *name* is not an alias.
+pub(super) fn remove_alias(core: &mut Core, name: &str) -> bool {
+    if core.aliases.remove(name).is_none() {
+        return false;
+    }
+    core.commands().lock().remove_command(name);
+    true
+}
+
+#[derive(Default)]
+pub struct Alias {}
+
+impl Alias {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Alias {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() {
+            for (name, line) in &core.aliases {
+                writeln!(core.stdout, "{}\t{}", name, line).unwrap();
+            }
+        } else if args[0] == "-" {
+            if args.len() != 2 {
+                expect(core, args.len() as u64, 2);
+                return;
+            }
+            if !remove_alias(core, &args[1]) {
+                error_msg(core, "Failed to remove alias", &format!("Alias `{}` does not exist.", args[1]));
+            }
+        } else if args.len() == 1 {
+            match core.aliases.get(&args[0]) {
+                Some(line) => writeln!(core.stdout, "{}", line).unwrap(),
+                None => error_msg(core, "Failed to find alias", &format!("Alias `{}` does not exist.", args[0])),
+            }
+        } else if let Err(e) = add_alias(core, &args[0], &args[1..].join(" ")) {
+            error_msg(core, "Failed to add alias", &e);
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"alias",
+            &"",
+            vec![
+                ("", "List all aliases."),
+                ("[name]", "Print the command line of alias [name]."),
+                ("[name] [command line]", "Make [name] run [command line], arguments of [name] are appended to its last command."),
+                ("- [name]", "Remove alias [name]."),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_alias {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help("alias");
+        core.run_line("alias p8 'px 8'");
+        core.help("p8");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Command: [alias]\n\n\
+             Usage:\n\
+             alias\tList all aliases.\n\
+             alias [name]\tPrint the command line of alias [name].\n\
+             alias [name] [command line]\tMake [name] run [command line], arguments of [name] are appended to its last command.\n\
+             alias - [name]\tRemove alias [name].\n\
+             p8 is an alias for `px 8`.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("alias w2 's 0x10; wx'; alias top s 0; w2 4142; alias; alias w2");
+        assert_eq!(core.get_loc(), 0x10);
+        let mut data = [0; 2];
+        core.io.pread(0x10, &mut data).unwrap();
+        assert_eq!(&data, b"AB");
+        core.run_line("alias top s 4; top; alias - top; alias - top; top; alias seek s 0");
+        core.run_line("alias x \"'a\"");
+        assert_eq!(core.get_loc(), 4);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "top\ts 0\n\
+             w2\ts 0x10; wx\n\
+             s 0x10; wx\n"
+        );
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to remove alias\n\
+             Alias `top` does not exist.\n\
+             Error: Execution failed\n\
+             Command top is not found.\n\
+             Similar command: map, o.\n\
+             Error: Failed to add alias\n\
+             Command `seek` already exists.\n\
+             Error: Failed to add alias\n\
+             Missing closing '.\n"
+        );
+    }
+
+    #[test]
+    fn test_alias_commands() {
+        let mut core = Core::new_no_colors();
+        core.run_line("alias mapsAll maps; alias mapsVir maps");
+        let commands = core.commands();
+        assert_eq!(commands.lock().prefix("mapsA"), vec!["mapsAll"]);
+        assert_eq!(commands.lock().suggest("mapsVor", 1), vec!["mapsVir"]);
+        core.run_line("alias - mapsVir");
+        assert!(commands.lock().suggest("mapsVor", 1).is_empty());
+        assert_eq!(commands.lock().prefix("maps"), vec!["maps", "mapsAll"]);
+    }
+}
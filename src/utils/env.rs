@@ -16,6 +16,7 @@
  */
 
 use crate::core::*;
+use crate::expr::*;
 use crate::helper::*;
 use rair_env::EnvData;
 use std::io::Write;
@@ -63,7 +64,7 @@ impl Environment {
             };
             res = env.write().set_i64(key, value, core);
         } else if env.read().is_u64(key) {
-            let value = match str_to_num(value) {
+            let value = match eval(core, value) {
                 Ok(value) => value,
                 Err(e) => return error_msg(core, "Failed to set variable.", &e.to_string()),
             };
@@ -117,7 +118,7 @@ impl Environment {
 }
 
 impl Cmd for Environment {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() > 3 {
             expect_range(core, args.len() as u64, 0, 3)
         } else if args.is_empty() {
@@ -171,7 +172,7 @@ impl EnvironmentReset {
 }
 
 impl Cmd for EnvironmentReset {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
@@ -201,7 +202,7 @@ impl EnvironmentHelp {
 }
 
 impl Cmd for EnvironmentHelp {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
@@ -274,7 +275,7 @@ mod test_env {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut er = EnvironmentReset::new();
+        let er = EnvironmentReset::new();
         let env = core.env.clone();
         let (r, g, b) = env.read().get_color("color.1").unwrap();
         env.write().set_color("color.1", (r + 1, g + 1, b + 1), &mut core).unwrap();
@@ -289,7 +290,7 @@ mod test_env {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut er = EnvironmentReset::new();
+        let er = EnvironmentReset::new();
         er.run(&mut core, &["doest.exist".to_string()]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
         assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Failed to reset variable.\nEnvironment variable not found.\n");
@@ -316,7 +317,7 @@ mod test_env {
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
 
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.run(&mut core, &[]);
         let s = core.stdout.utf8_string().unwrap();
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
@@ -332,7 +333,7 @@ mod test_env {
         let mut core = get_good_core();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.run(&mut core, &["b".to_string()]);
         env.run(&mut core, &["u".to_string()]);
         env.run(&mut core, &["i".to_string()]);
@@ -364,7 +365,7 @@ mod test_env {
         let mut core = get_good_core();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.run(&mut core, &["b  =".to_string(), "true ".to_string()]);
         env.run(&mut core, &["u".to_string(), "= 0x5".to_string()]);
         env.run(&mut core, &["b".to_string()]);
@@ -378,7 +379,7 @@ mod test_env {
         let mut core = get_good_core();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.run(&mut core, &["b".to_string(), "=".to_string(), "true".to_string()]);
         env.run(&mut core, &["b".to_string()]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "true\n");
@@ -391,7 +392,7 @@ mod test_env {
         core.env.write().add_bool("b", false, "").unwrap();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.run(&mut core, &["b".to_string(), "=".to_string(), "true".to_string(), "extra".to_string()]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
         assert_eq!(core.stderr.utf8_string().unwrap(), "Arguments Error: Expected between 0 and 3 arguments, found 4.\n");
@@ -411,7 +412,7 @@ mod test_env {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.run(&mut core, &["b".to_string()]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
         assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Failed to display variable.\nVariable `b` doesn't exist.\n");
@@ -432,7 +433,7 @@ mod test_env {
         env.write().add_color("c", (0xee, 0xee, 0xee), "").unwrap();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut env = Environment::new();
+        let env = Environment::new();
         env.run(&mut core, &["b=no".to_string()]);
         assert_eq!(core.stdout.utf8_string().unwrap(), "");
         assert_eq!(core.stderr.utf8_string().unwrap(), "Error: Failed to set variable.\nExpected `true` or `false`, found `no`.\n");
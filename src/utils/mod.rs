@@ -14,23 +14,31 @@
  * You should have received a copy of the GNU Lesser General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+mod alias;
 mod env;
 mod project;
 mod quit;
+mod script;
 
+use self::alias::*;
 use self::env::*;
 use self::project::*;
 pub use self::quit::Quit;
+use self::script::*;
 use crate::core::Core;
-use parking_lot::Mutex;
 use std::sync::Arc;
 
 pub fn register_utils(core: &mut Core) {
-    core.add_command("quit", "q", Arc::new(Mutex::new(Quit::new())));
-    core.add_command("save", "", Arc::new(Mutex::new(Save::new())));
-    core.add_command("load", "", Arc::new(Mutex::new(Load::new())));
-    core.add_command("environment", "e", Arc::new(Mutex::new(Environment::new())));
-    core.add_command("environmentReset", "er", Arc::new(Mutex::new(EnvironmentReset::new())));
-    let eh = Arc::new(Mutex::new(EnvironmentHelp::new(core)));
+    core.add_command("quit", "q", Arc::new(Quit::new()));
+    core.add_command("save", "", Arc::new(Save::new()));
+    core.add_command("load", "", Arc::new(Load::new()));
+    core.add_command("environment", "e", Arc::new(Environment::new()));
+    core.add_command("environmentReset", "er", Arc::new(EnvironmentReset::new()));
+    let eh = Arc::new(EnvironmentHelp::new(core));
     core.add_command("environmentHelp", "eh", eh);
+    let script = Arc::new(Script::new(core));
+    core.add_command("script", ".", script);
+    core.add_command("macro", "", Arc::new(Macro::new()));
+    core.add_command("call", "", Arc::new(Call::new()));
+    core.add_command("alias", "", Arc::new(Alias::new()));
 }
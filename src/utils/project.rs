@@ -15,6 +15,7 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+use super::alias::add_alias;
 use crate::core::*;
 use crate::helper::*;
 use flate2::write::{ZlibDecoder, ZlibEncoder};
@@ -33,7 +34,7 @@ impl Save {
     }
 }
 impl Cmd for Save {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
@@ -68,7 +69,7 @@ impl Load {
     }
 }
 impl Cmd for Load {
-    fn run(&mut self, core: &mut Core, args: &[String]) {
+    fn run(&self, core: &mut Core, args: &[String]) {
         if args.len() != 1 {
             expect(core, args.len() as u64, 1);
             return;
@@ -100,7 +101,19 @@ impl Cmd for Load {
         mem::swap(&mut core.stderr, &mut core2.stderr);
         mem::swap(&mut core.env, &mut core2.env);
         core2.set_commands(core.commands());
+        core2.errors = core.errors;
+        // aliases live in the shared commands, so the old ones are replaced by the loaded ones.
+        let commands = core.commands();
+        for name in core.aliases.keys() {
+            commands.lock().remove_command(name);
+        }
+        let aliases = mem::take(&mut core2.aliases);
         *core = core2;
+        for (name, line) in aliases {
+            if let Err(e) = add_alias(core, &name, &line) {
+                error_msg(core, "Failed to add alias", &e);
+            }
+        }
     }
     fn help(&self, core: &mut Core) {
         help(core, &"load", &"", vec![("[file_path]", "load project from given path.")]);
@@ -114,6 +127,8 @@ mod test_project {
     use crate::writer::*;
     use rair_io::*;
     use std::fs;
+    use std::path::Path;
+    use test_file::*;
     #[test]
     fn test_project_help() {
         let mut core = Core::new_no_colors();
@@ -139,8 +154,8 @@ mod test_project {
         let mut core = Core::new_no_colors();
         core.stderr = Writer::new_buf();
         core.stdout = Writer::new_buf();
-        let mut load = Load::new();
-        let mut save = Save::new();
+        let load = Load::new();
+        let save = Save::new();
         core.io.open("malloc://0x500", IoMode::READ | IoMode::WRITE).unwrap();
         core.io.open_at("malloc://0x1337", IoMode::READ | IoMode::WRITE, 0x31000).unwrap();
         core.io.map(0x31000, 0xfff31000, 0x337).unwrap();
@@ -154,10 +169,170 @@ mod test_project {
             "Handle\tStart address\tsize\t\tPermissions\tURI\n\
              0\t0x00000000\t0x00000500\tWRITE | READ\tmalloc://0x500\n\
              1\t0x00031000\t0x00001337\tWRITE | READ\tmalloc://0x1337\n\
-             Virtual Address     Physical Address    Size\n\
-             0xfff31000          0x31000             0x337\n"
+             Virtual Address     Physical Address    Size                Perm    Name\n\
+             0xfff31000          0x31000             0x337               rwx\n"
         );
         assert_eq!(core.stderr.utf8_string().unwrap(), "");
         fs::remove_file("rair_project").unwrap();
     }
+
+    #[test]
+    fn test_project_macros_aliases() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("macro dump 'px $1'; alias p1 px 1; save rair_macro_project; macro remove dump; alias - p1; alias p2 px 2");
+        core.run_line("load rair_macro_project; macro; alias; p2");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "dump\tpx $1\np1\tpx 1\n");
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Execution failed\n\
+             Command p2 is not found.\n\
+             Similar command: m, px, pb, p1, wx, /x, um, o, s, /, q, e, ., f, /w, /r, er, eh, fr, fs, fd.\n"
+        );
+        fs::remove_file("rair_macro_project").unwrap();
+    }
+
+    #[test]
+    fn test_project_flags() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("f entry 0x10; fs funcs; f main 0x40 0x20; save rair_flags_project; f - main; fs default");
+        core.run_line("load rair_flags_project; flags; fs; fd main+4; s main");
+        assert_eq!(core.get_loc(), 0x40);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0x10\t0x1\tPhy\tentry\n\
+             0x40\t0x20\tPhy\tmain\n\
+             \x20 default\n\
+             * funcs\n\
+             main + 0x4\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        fs::remove_file("rair_flags_project").unwrap();
+    }
+
+    #[test]
+    fn test_project_comments() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("comment 0x10 0x20 header; comment 0x14 4 magic; save rair_comments_project; comment - 0x14 4");
+        core.run_line("load rair_comments_project; comment 0x15");
+        assert_eq!(core.stdout.utf8_string().unwrap(), "0x10\t0x20\tPhy\theader\n0x14\t0x4\tPhy\tmagic\n");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        fs::remove_file("rair_comments_project").unwrap();
+    }
+
+    #[test]
+    fn test_project_journal() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x8", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run_line("wx 0102; wx 03 @ 4; undo; save rair_journal_project; undo");
+        core.run_line("load rair_journal_project; journal; undo; redo 2; px 8");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "0\t0x0\t0x2\n\
+             1\t0x4\t0x1\t(undone)\n\
+             - offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000000 0102 0000 0300 0000                      ........\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        fs::remove_file("rair_journal_project").unwrap();
+    }
+
+    #[test]
+    fn test_project_malloc() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x8", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run("wx", &["0102030405".to_string()]);
+        core.run("save", &["rair_malloc_project".to_string()]);
+        core.io.close_all();
+        core.run("load", &["rair_malloc_project".to_string()]);
+        core.run("px", &["0x8".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "- offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000000 0102 0304 0500 0000                      ........\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        fs::remove_file("rair_malloc_project").unwrap();
+    }
+    fn remove_keys(value: &mut serde_cbor::Value, keys: &[&str]) {
+        if let serde_cbor::Value::Map(map) = value {
+            for key in keys {
+                map.remove(&serde_cbor::Value::Text(key.to_string()));
+            }
+        }
+    }
+    fn field<'a>(value: &'a mut serde_cbor::Value, key: &str) -> &'a mut serde_cbor::Value {
+        match value {
+            serde_cbor::Value::Map(map) => map.get_mut(&serde_cbor::Value::Text(key.to_string())).unwrap(),
+            _ => panic!("Expected map"),
+        }
+    }
+    #[test]
+    fn test_project_old_format() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        core.run("map", &["0x10".to_string(), "0x1000".to_string(), "0x10".to_string()]);
+        core.run("m", &["vir".to_string()]);
+        core.run("s", &["0x1004".to_string()]);
+        // strip the fields that projects saved by older versions lack.
+        let mut value = serde_cbor::value::to_value(&core).unwrap();
+        remove_keys(&mut value, &["binaries", "macros", "aliases", "flags", "comments", "hits"]);
+        let io = field(&mut value, "io");
+        remove_keys(io, &["journal"]);
+        if let serde_cbor::Value::Array(descs) = field(field(io, "descs"), "hndl_to_descs") {
+            for desc in descs {
+                remove_keys(desc, &["cow_delta", "plugin_operations", "dirty"]);
+            }
+        }
+        let mut compressor = ZlibEncoder::new(Vec::new(), Compression::default());
+        compressor.write_all(&serde_cbor::to_vec(&value).unwrap()).unwrap();
+        fs::write("rair_old_project", compressor.finish().unwrap()).unwrap();
+        core.io.close_all();
+        core.run("m", &["phy".to_string()]);
+        core.run("s", &["0".to_string()]);
+        core.run("load", &["rair_old_project".to_string()]);
+        core.run("files", &[]);
+        assert_eq!(core.get_loc(), 0x1004);
+        assert_eq!(core.mode, AddrMode::Vir);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Handle\tStart address\tsize\t\tPermissions\tURI\n\
+             0\t0x00000000\t0x00000020\tWRITE | READ\tmalloc://0x20\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        fs::remove_file("rair_old_project").unwrap();
+    }
+    fn test_project_cow_cb(path: &Path) {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open(&path.to_string_lossy(), IoMode::COW).unwrap();
+        core.run("wx", &["deadbeef".to_string()]);
+        core.run("save", &["rair_cow_project".to_string()]);
+        core.io.close_all();
+        core.run("load", &["rair_cow_project".to_string()]);
+        core.run("px", &["0x8".to_string()]);
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "- offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000000 dead beef 0305 080d                      ........\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+        fs::remove_file("rair_cow_project").unwrap();
+    }
+    #[test]
+    fn test_project_cow() {
+        operate_on_file(&test_project_cow_cb, DATA);
+    }
 }
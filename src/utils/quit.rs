@@ -28,7 +28,7 @@ impl Quit {
 }
 
 impl Cmd for Quit {
-    fn run(&mut self, _core: &mut Core, _args: &[String]) {
+    fn run(&self, _core: &mut Core, _args: &[String]) {
         process::exit(0);
     }
     fn help(&self, core: &mut Core) {
@@ -0,0 +1,357 @@
+/*
+ * script.rs: Commands for running script files and user defined macros.
+ * Copyright (C) 2019  Oddcoder
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Lesser General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::core::*;
+use crate::helper::*;
+use std::fs;
+use std::io::Write;
+
+#[derive(Default)]
+pub struct Script {}
+
+impl Script {
+    pub fn new(core: &mut Core) -> Self {
+        core.env
+            .write()
+            .add_bool("script.stopOnError", false, "Stop executing script files after the first command that fails")
+            .unwrap();
+        Script {}
+    }
+}
+
+impl Cmd for Script {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.len() != 1 {
+            expect(core, args.len() as u64, 1);
+            return;
+        }
+        let script = match fs::read_to_string(&args[0]) {
+            Ok(script) => script,
+            Err(e) => return error_msg(core, "Failed to open script", &e.to_string()),
+        };
+        let stop = core.env.read().get_bool("script.stopOnError").unwrap();
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let errors = core.errors;
+            core.run_line(line);
+            if stop && core.errors != errors {
+                let msg = format!("Failed at line {} of {}.", i + 1, args[0]);
+                return error_msg(core, "Script stopped", &msg);
+            }
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"script",
+            &".",
+            vec![("[file_path]", "Run commands from file line by line, lines starting with `#` are comments.")],
+        );
+    }
+}
+
+// Escapes *arg* so that the command line parser reads it back as it is. *quote* is the quote
+// character the argument is substituted inside of, if any.
+fn escape(arg: &str, quote: Option<char>) -> String {
+    match quote {
+        // single quotes can't escape anything, so the quote is closed, escaped and opened again.
+        Some('\'') => arg.replace('\'', "'\\''"),
+        Some(_) => arg.replace('\\', "\\\\").replace('"', "\\\""),
+        None if arg.is_empty() => "''".to_string(),
+        None => {
+            let mut escaped = String::new();
+            for c in arg.chars() {
+                if c.is_whitespace() || ";@~>|'\"\\".contains(c) {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+            escaped
+        }
+    }
+}
+
+// Replaces `$1`, `$2`, ... in body with the corresponding argument and `$@` with all arguments.
+// Arguments are escaped so that each of them stays a single word of the expanded command line.
+// Returns the number of arguments needed as error if there are too few arguments.
+fn expand(body: &str, args: &[String]) -> Result<String, usize> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut expanded = String::new();
+    let mut needed = 0;
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '$' || i + 1 == chars.len() {
+            match (chars[i], quote) {
+                ('\\', Some('\'')) => (),
+                ('\\', _) if i + 1 < chars.len() => {
+                    expanded.push('\\');
+                    i += 1;
+                }
+                ('\'', None) | ('"', None) => quote = Some(chars[i]),
+                (c, Some(q)) if c == q => quote = None,
+                _ => (),
+            }
+            expanded.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        match chars[i + 1] {
+            // `$$` is the current location, not a parameter.
+            '$' => {
+                expanded.push_str("$$");
+                i += 2;
+            }
+            '@' => {
+                let all: Vec<String> = args.iter().map(|arg| escape(arg, quote)).collect();
+                expanded.push_str(&all.join(" "));
+                i += 2;
+            }
+            '1'..='9' => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let n: usize = chars[start..i].iter().collect::<String>().parse().unwrap();
+                needed = needed.max(n);
+                if let Some(arg) = args.get(n - 1) {
+                    expanded.push_str(&escape(arg, quote));
+                }
+            }
+            _ => {
+                expanded.push('$');
+                i += 1;
+            }
+        }
+    }
+    if needed > args.len() {
+        return Err(needed);
+    }
+    Ok(expanded)
+}
+
+#[derive(Default)]
+pub struct Macro {}
+
+impl Macro {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Macro {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() {
+            for (name, body) in &core.macros {
+                writeln!(core.stdout, "{}\t{}", name, body).unwrap();
+            }
+        } else if args[0] == "remove" {
+            if args.len() != 2 {
+                expect(core, args.len() as u64, 2);
+                return;
+            }
+            if core.macros.remove(&args[1]).is_none() {
+                error_msg(core, "Failed to remove macro", &format!("Macro `{}` does not exist.", args[1]));
+            }
+        } else if args.len() == 1 {
+            match core.macros.get(&args[0]) {
+                Some(body) => writeln!(core.stdout, "{}", body).unwrap(),
+                None => error_msg(core, "Failed to find macro", &format!("Macro `{}` does not exist.", args[0])),
+            }
+        } else {
+            core.macros.insert(args[0].clone(), args[1..].join(" "));
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(
+            core,
+            &"macro",
+            &"",
+            vec![
+                ("", "List all macros."),
+                ("[name]", "Print the commands of macro [name]."),
+                ("[name] [commands]", "Define macro [name], `$1`, `$2`, ... are replaced by the arguments and `$@` by all of them."),
+                ("remove [name]", "Remove macro [name]."),
+            ],
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct Call {}
+
+impl Call {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Cmd for Call {
+    fn run(&self, core: &mut Core, args: &[String]) {
+        if args.is_empty() {
+            expect(core, 0, 1);
+            return;
+        }
+        let body = match core.macros.get(&args[0]) {
+            Some(body) => body.clone(),
+            None => return error_msg(core, "Failed to call macro", &format!("Macro `{}` does not exist.", args[0])),
+        };
+        match expand(&body, &args[1..]) {
+            Ok(line) => core.run_line(&line),
+            Err(n) => {
+                let msg = format!("Macro `{}` expects {} argument(s), found {}.", args[0], n, args.len() - 1);
+                error_msg(core, "Failed to call macro", &msg);
+            }
+        }
+    }
+    fn help(&self, core: &mut Core) {
+        help(core, &"call", &"", vec![("[name] <args>", "Run macro [name] with the given arguments.")]);
+    }
+}
+
+#[cfg(test)]
+mod test_script {
+    use super::*;
+    use crate::writer::Writer;
+    use rair_io::*;
+
+    #[test]
+    fn test_help() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.help(".");
+        core.help("macro");
+        core.help("call");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "Commands: [script | .]\n\n\
+             Usage:\n\
+             . [file_path]\tRun commands from file line by line, lines starting with `#` are comments.\n\
+             Command: [macro]\n\n\
+             Usage:\n\
+             macro\tList all macros.\n\
+             macro [name]\tPrint the commands of macro [name].\n\
+             macro [name] [commands]\tDefine macro [name], `$1`, `$2`, ... are replaced by the arguments and `$@` by all of them.\n\
+             macro remove [name]\tRemove macro [name].\n\
+             Command: [call]\n\n\
+             Usage:\n\
+             call [name] <args>\tRun macro [name] with the given arguments.\n"
+        );
+        assert_eq!(core.stderr.utf8_string().unwrap(), "");
+    }
+
+    #[test]
+    fn test_expand() {
+        let args = vec!["a".to_string(), "b c".to_string()];
+        assert_eq!(expand("x $2 $1 $$ $@ $x $", &args).unwrap(), "x b\\ c a $$ a b\\ c $x $");
+        assert_eq!(expand("$1$2", &args).unwrap(), "ab\\ c");
+        assert_eq!(expand("$3 $1", &args).err().unwrap(), 3);
+        let args = vec!["x; q".to_string(), "it's \"".to_string(), "".to_string()];
+        assert_eq!(expand("$1 $3", &args).unwrap(), "x\\;\\ q ''");
+        assert_eq!(expand("'$2' \"$2\" \\'$1", &args).unwrap(), "'it'\\''s \"' \"it's \\\"\" \\'x\\;\\ q");
+    }
+
+    #[test]
+    fn test_script() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        fs::write(
+            "rair_test_script",
+            "# open a file and write to it\n\
+             o rw malloc://0x20\n\n\
+             wx 4142 @ 0x10\n\
+             \x20 s 0x10 # not a comment\n\
+             px 2\n",
+        )
+        .unwrap();
+        core.run(".", &["rair_test_script".to_string()]);
+        assert_eq!(core.get_loc(), 0);
+        let mut data = [0; 2];
+        core.io.pread(0x10, &mut data).unwrap();
+        assert_eq!(&data, b"AB");
+        assert_eq!(core.stderr.utf8_string().unwrap(), "Arguments Error: Expected 1 argument(s), found 5.\n");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "- offset -  0 1  2 3  4 5  6 7  8 9  A B  C D  E F  0123456789ABCDEF\n\
+             0x00000000 0000                                     ..\n"
+        );
+        fs::remove_file("rair_test_script").unwrap();
+    }
+
+    #[test]
+    fn test_script_stop() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.io.open("malloc://0x20", IoMode::READ | IoMode::WRITE).unwrap();
+        fs::write("rair_test_script_stop", "s 5\nseek ff\ns 6\n").unwrap();
+        core.env.write().set_bool("script.stopOnError", true, &mut Core::new_no_colors()).unwrap();
+        core.run(".", &["rair_test_script_stop".to_string()]);
+        assert_eq!(core.get_loc(), 5);
+        core.run(".", &["rair_missing_script".to_string()]);
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Seek Error\n\
+             invalid digit found in string\n\
+             Error: Script stopped\n\
+             Failed at line 2 of rair_test_script_stop.\n\
+             Error: Failed to open script\n\
+             No such file or directory (os error 2)\n"
+        );
+        assert_eq!(core.stdout.utf8_string().unwrap(), "");
+        fs::remove_file("rair_test_script_stop").unwrap();
+    }
+
+    #[test]
+    fn test_macros() {
+        let mut core = Core::new_no_colors();
+        core.stderr = Writer::new_buf();
+        core.stdout = Writer::new_buf();
+        core.run_line("macro triage 'o rw malloc://$1; wx $2 @ 1; s $$+2'; macro nop s $$");
+        core.run_line("macro; macro triage; call triage 0x10 4142");
+        assert_eq!(core.get_loc(), 2);
+        let mut data = [0; 2];
+        core.io.pread(1, &mut data).unwrap();
+        assert_eq!(&data, b"AB");
+        core.run_line("macro note 'comment 0x0 1 $1'; call note 'a b; px 4 > out'; comment 0x0");
+        core.run_line("call triage 0x10; call missing; call; macro remove nop; macro remove nop; macro nop");
+        assert_eq!(
+            core.stdout.utf8_string().unwrap(),
+            "nop\ts $$\n\
+             triage\to rw malloc://$1; wx $2 @ 1; s $$+2\n\
+             o rw malloc://$1; wx $2 @ 1; s $$+2\n\
+             0x0\t0x1\tPhy\ta b; px 4 > out\n"
+        );
+        assert_eq!(
+            core.stderr.utf8_string().unwrap(),
+            "Error: Failed to call macro\n\
+             Macro `triage` expects 2 argument(s), found 1.\n\
+             Error: Failed to call macro\n\
+             Macro `missing` does not exist.\n\
+             Arguments Error: Expected 1 argument(s), found 0.\n\
+             Error: Failed to remove macro\n\
+             Macro `nop` does not exist.\n\
+             Error: Failed to find macro\n\
+             Macro `nop` does not exist.\n"
+        );
+    }
+}
@@ -14,6 +14,7 @@
  * You should have received a copy of the GNU Lesser General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+use std::fs::OpenOptions;
 use std::io;
 use std::io::Write;
 
@@ -52,6 +53,13 @@ impl Writer {
         Writer::Write(out)
     }
 
+    /// Creates a new [Writer] backed by the file at *path*, the file is created if it
+    /// doesn't exist. Old contents are truncated unless *append* is set.
+    pub fn new_file(path: &str, append: bool) -> io::Result<Self> {
+        let file = OpenOptions::new().write(true).create(true).append(append).truncate(!append).open(path)?;
+        Ok(Writer::new_write(Box::new(file)))
+    }
+
     /// Returns a new buffer based [Writer].
     pub fn new_buf() -> Self {
         Writer::Bytes(Vec::new())
@@ -104,6 +112,7 @@ impl Writer {
 #[cfg(test)]
 mod writer_test {
     use super::*;
+    use std::fs;
     #[test]
     fn test_writer_buffer() {
         let mut w = Writer::new_buf();
@@ -127,4 +136,20 @@ mod writer_test {
         w = Writer::new_write(Box::new(io::stdout()));
         assert_eq!(w.bytes(), None);
     }
+
+    #[test]
+    fn test_writer_file() {
+        let mut w = Writer::new_file("writer_test_file", false).unwrap();
+        write!(w, "hello").unwrap();
+        w = Writer::new_file("writer_test_file", true).unwrap();
+        write!(w, " world").unwrap();
+        drop(w);
+        assert_eq!(fs::read_to_string("writer_test_file").unwrap(), "hello world");
+        w = Writer::new_file("writer_test_file", false).unwrap();
+        write!(w, "bye").unwrap();
+        drop(w);
+        assert_eq!(fs::read_to_string("writer_test_file").unwrap(), "bye");
+        fs::remove_file("writer_test_file").unwrap();
+        assert!(Writer::new_file("", false).is_err());
+    }
 }